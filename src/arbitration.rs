@@ -0,0 +1,200 @@
+//! Pen/touch arbitration for convertibles and pen-enabled panels.
+//!
+//! While writing, the hand rests on the screen. `PenTouchArbiter` reads a pen
+//! and a touch device through a `MultiSource` and holds back touch while the
+//! pen is in proximity, and for a while after it leaves. Contacts already on
+//! the surface when the pen comes close are lifted; contacts that go down
+//! during suppression stay suppressed until they lift.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use multi::MultiSource;
+use source::EventSource;
+use stylus::{StylusEvent, StylusTracker};
+use touch::{Contact, ContactState, TouchFrame, TouchTracker};
+use {EvdevEvent, Error, TimeVal};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arbitrated {
+    Pen(StylusEvent),
+    /// A touch frame with suppressed contacts removed.
+    Touch(TouchFrame),
+    /// An event of any other source, with the source's index.
+    Event(usize, EvdevEvent),
+}
+
+pub struct PenTouchArbiter<S> {
+    multi: MultiSource<S>,
+    pen: usize,
+    touch: usize,
+    stylus: StylusTracker,
+    tracker: TouchTracker,
+    timeout: Duration,
+    // When the pen last left proximity
+    pen_out: Option<TimeVal>,
+    // Contacts passed on, and contacts being held back
+    reported: HashSet<i32>,
+    suppressed: HashSet<i32>,
+    queue: VecDeque<Arbitrated>,
+}
+
+impl<S: EventSource> PenTouchArbiter<S> {
+    /// `pen` and `touch` are indices into `multi`.
+    pub fn new(multi: MultiSource<S>, pen: usize, touch: usize) -> PenTouchArbiter<S> {
+        let stylus = StylusTracker::for_source(&multi.sources()[pen]);
+        PenTouchArbiter {
+            multi,
+            pen,
+            touch,
+            stylus,
+            tracker: TouchTracker::new(),
+            timeout: Duration::from_millis(200),
+            pen_out: None,
+            reported: HashSet::new(),
+            suppressed: HashSet::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// How long touch stays suppressed after the pen leaves proximity.
+    /// Defaults to 200ms.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_stylus(mut self, stylus: StylusTracker) -> Self {
+        self.stylus = stylus;
+        self
+    }
+
+    pub fn into_inner(self) -> MultiSource<S> {
+        self.multi
+    }
+
+    /// Whether touch is held back at `time`.
+    pub fn is_suppressing(&self, time: TimeVal) -> bool {
+        self.stylus.tool().is_some()
+            || self.pen_out.is_some_and(|out| time < out.plus(self.timeout))
+    }
+
+    /// The next pen event, touch frame or other event, or `None` when the
+    /// sources run dry.
+    pub fn read_next(&mut self) -> Result<Option<Arbitrated>, Error> {
+        while self.queue.is_empty() {
+            let (idx, ev) = match self.multi.read_event()? {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            if idx == self.pen {
+                for pen in self.stylus.process(&ev) {
+                    let entered = matches!(pen, StylusEvent::ProximityIn { .. });
+                    if let StylusEvent::ProximityOut { .. } = pen {
+                        self.pen_out = Some(ev.time);
+                    }
+                    self.queue.push_back(Arbitrated::Pen(pen));
+                    if entered {
+                        self.lift_reported(ev.time);
+                    }
+                }
+            } else if idx == self.touch {
+                if let Some(frame) = self.tracker.process(&ev) {
+                    let frame = self.filter(frame);
+                    if !frame.contacts.is_empty() {
+                        self.queue.push_back(Arbitrated::Touch(frame));
+                    }
+                }
+            } else {
+                self.queue.push_back(Arbitrated::Event(idx, ev));
+            }
+        }
+        Ok(self.queue.pop_front())
+    }
+
+    /// Lifts the contacts passed on so far, without waiting for their next
+    /// touch frame, and holds them back from now on.
+    fn lift_reported(&mut self, time: TimeVal) {
+        let contacts: Vec<_> = self.tracker.contacts().into_iter()
+            .filter(|c| self.reported.contains(&c.tracking_id))
+            .map(|c| Contact { state: ContactState::Up, ..c })
+            .collect();
+        self.suppressed.extend(self.reported.drain());
+        if !contacts.is_empty() {
+            self.queue.push_back(Arbitrated::Touch(TouchFrame { time, contacts }));
+        }
+    }
+
+    fn filter(&mut self, frame: TouchFrame) -> TouchFrame {
+        let suppressing = self.is_suppressing(frame.time);
+        let mut out = Vec::new();
+        for mut c in frame.contacts {
+            let id = c.tracking_id;
+            let lifted = c.state == ContactState::Up;
+            if self.suppressed.contains(&id) {
+                if lifted {
+                    self.suppressed.remove(&id);
+                }
+                continue;
+            }
+            if suppressing {
+                if self.reported.remove(&id) {
+                    // Lift it for whoever is listening
+                    c.state = ContactState::Up;
+                    out.push(c);
+                }
+                if !lifted {
+                    self.suppressed.insert(id);
+                }
+                continue;
+            }
+            if lifted {
+                self.reported.remove(&id);
+            } else {
+                self.reported.insert(id);
+            }
+            out.push(c);
+        }
+        TouchFrame { time: frame.time, contacts: out }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codes::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn suppression_test() {
+        let mut touch = MockSource::new(DeviceDescription::new("touch"));
+        touch.key(KeyCode::BTN_TOUCH, 1).abs(AbsCode::ABS_X, 10).sync();
+        touch.advance(20_000).abs(AbsCode::ABS_X, 20).sync();
+        // The pen left at 100ms; suppression lasts until 300ms
+        touch.advance(130_000).key(KeyCode::BTN_TOUCH, 0).sync();
+        touch.advance(50_000).key(KeyCode::BTN_TOUCH, 1).sync();
+        touch.advance(150_000).abs(AbsCode::ABS_X, 30).sync();
+        touch.advance(10_000).key(KeyCode::BTN_TOUCH, 0).sync();
+        touch.advance(40_000).key(KeyCode::BTN_TOUCH, 1).sync();
+        let mut pen = MockSource::new(DeviceDescription::new("pen"));
+        pen.advance(10_000).key(KeyCode::BTN_TOOL_PEN, 1).sync();
+        pen.advance(90_000).key(KeyCode::BTN_TOOL_PEN, 0).sync();
+
+        let mut arbiter = PenTouchArbiter::new(MultiSource::new(vec![touch, pen]), 1, 0);
+        let mut out = Vec::new();
+        while let Some(next) = arbiter.read_next().unwrap() {
+            out.push(match next {
+                Arbitrated::Pen(StylusEvent::ProximityIn { .. }) => "in".to_string(),
+                Arbitrated::Pen(StylusEvent::ProximityOut { .. }) => "out".to_string(),
+                Arbitrated::Touch(frame) => {
+                    let c = frame.contacts[0];
+                    format!("{} {:?} {}", frame.time.as_micros() / 1000, c.state, c.x)
+                }
+                other => panic!("{:?}", other),
+            });
+        }
+        // Lifted as soon as the pen came close, not at the next touch frame
+        assert_eq!(vec!["0 Down 10", "in", "10 Up 10", "out", "400 Down 30"], out);
+    }
+}
@@ -0,0 +1,385 @@
+//! Barcode scanners that present themselves as keyboards.
+//!
+//! A scan arrives as a burst of keystrokes a few milliseconds apart, ended
+//! by Enter or Tab. `BarcodeReader` collects bursts into `Scan`s and hands
+//! back anything slower as typing, so a keyboard and a scanner can share a
+//! device. Typed keys are only known not to be a scan once the next key
+//! comes late, or on `flush`.
+//!
+//! Scanners can be set up to frame their data with a prefix and suffix,
+//! and to send an AIM symbology identifier (`]E0` for EAN-13) first; both
+//! are taken off the data.
+
+use std::collections::HashSet;
+use std::mem;
+use std::time::Duration;
+
+use codes::*;
+use keyboard::{KeyState, KeyboardState, Modifiers};
+use keymap::{Keymap, Keysym};
+use source::EventSource;
+use {EvdevEvent, Error, EventDevice, TimeVal};
+
+/// An AIM symbology identifier, `]` followed by these two characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AimId {
+    pub code: char,
+    pub modifier: char,
+}
+
+impl AimId {
+    pub fn symbology(&self) -> Option<&'static str> {
+        match self.code {
+            'A' => Some("Code 39"),
+            'C' => Some("Code 128"),
+            'd' => Some("Data Matrix"),
+            'E' => Some("EAN/UPC"),
+            'e' => Some("GS1 DataBar"),
+            'F' => Some("Codabar"),
+            'G' => Some("Code 93"),
+            'I' => Some("Interleaved 2 of 5"),
+            'L' => Some("PDF417"),
+            'Q' => Some("QR Code"),
+            'z' => Some("Aztec"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scan {
+    /// When the first key of the scan came.
+    pub time: TimeVal,
+    /// The data without framing and symbology identifier.
+    pub data: String,
+    pub aim: Option<AimId>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScannerEvent {
+    Scan(Scan),
+    /// Key events that weren't a scan, without their `SYN_REPORT`s.
+    Typed(Vec<EvdevEvent>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScannerConfig {
+    /// The longest gap between key presses of a scan.
+    pub max_interval: Duration,
+    /// Shorter bursts are typing. Counts the data only, without the prefix,
+    /// suffix and AIM ID.
+    pub min_length: usize,
+    pub enter_terminates: bool,
+    pub tab_terminates: bool,
+    /// Framing the scanner adds; scans without it are typing.
+    pub prefix: String,
+    pub suffix: String,
+    /// Whether scans start with an AIM symbology identifier.
+    pub aim: bool,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> ScannerConfig {
+        ScannerConfig {
+            max_interval: Duration::from_millis(50),
+            min_length: 4,
+            enter_terminates: true,
+            tab_terminates: true,
+            prefix: String::new(),
+            suffix: String::new(),
+            aim: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BarcodeReader {
+    keymap: Keymap,
+    keyboard: KeyboardState,
+    config: ScannerConfig,
+    // The burst so far
+    events: Vec<EvdevEvent>,
+    text: String,
+    start: Option<TimeVal>,
+    last_press: Option<TimeVal>,
+    // Keys held at the end of a scan, whose releases are dropped
+    swallowed: HashSet<KeyCode>,
+}
+
+impl BarcodeReader {
+    pub fn new(keymap: Keymap) -> BarcodeReader {
+        BarcodeReader {
+            keymap,
+            keyboard: KeyboardState::new(),
+            config: ScannerConfig::default(),
+            events: Vec::new(),
+            text: String::new(),
+            start: None,
+            last_press: None,
+            swallowed: HashSet::new(),
+        }
+    }
+
+    /// Grabs `dev`, so scans don't type into other programs, and starts
+    /// from its keyboard state.
+    pub fn grab(dev: &mut EventDevice, keymap: Keymap) -> Result<BarcodeReader, Error> {
+        dev.grab(true)?;
+        Ok(BarcodeReader::new(keymap).with_keyboard(KeyboardState::from_device(dev)))
+    }
+
+    pub fn with_config(mut self, config: ScannerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_keyboard(mut self, keyboard: KeyboardState) -> Self {
+        self.keyboard = keyboard;
+        self
+    }
+
+    pub fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    /// Whether a burst is under way.
+    pub fn is_pending(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    /// Feeds one event.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Vec<ScannerEvent> {
+        let mut out = Vec::new();
+        let key = match self.keyboard.process(ev) {
+            Some(key) => key,
+            None => return out,
+        };
+        if key.state == KeyState::Released {
+            if self.swallowed.remove(&key.key) {
+                return out;
+            }
+            if self.events.is_empty() {
+                out.push(ScannerEvent::Typed(vec![ev.clone()]));
+            } else {
+                self.events.push(ev.clone());
+            }
+            return out;
+        }
+        out.extend(self.flush(ev.time));
+        self.events.push(ev.clone());
+        self.start = self.start.or(Some(ev.time));
+        self.last_press = Some(ev.time);
+        if key.state == KeyState::Repeated {
+            // Scanners don't hold keys
+            out.extend(self.take_typed());
+            return out;
+        }
+        if Modifiers::is_modifier(key.key) {
+            return out;
+        }
+        match self.keymap.translate(&key) {
+            Some(Keysym::Char(c)) if !c.is_control() => self.text.push(c),
+            Some(Keysym::Named(ref name)) if self.is_terminator(name) => out.extend(self.finish()),
+            _ => out.extend(self.take_typed()),
+        }
+        out
+    }
+
+    /// Hands back a burst as typing once `max_interval` has passed since
+    /// its last key; call it when no events come for a while.
+    pub fn flush(&mut self, now: TimeVal) -> Vec<ScannerEvent> {
+        match self.last_press {
+            Some(last) if now > last.plus(self.config.max_interval) => self.take_typed(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Reads from `src` until something comes out. When the source runs
+    /// dry, a pending burst is handed back as typing.
+    pub fn next_events<S: EventSource>(&mut self, src: &mut S) -> Result<Vec<ScannerEvent>, Error> {
+        while let Some(ev) = src.read_event()? {
+            let events = self.process(&ev);
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+        Ok(self.take_typed())
+    }
+
+    fn is_terminator(&self, name: &str) -> bool {
+        match name {
+            "Return" | "KP_Enter" => self.config.enter_terminates,
+            "Tab" => self.config.tab_terminates,
+            _ => false,
+        }
+    }
+
+    fn reset(&mut self) -> Vec<EvdevEvent> {
+        self.text.clear();
+        self.start = None;
+        self.last_press = None;
+        mem::take(&mut self.events)
+    }
+
+    fn take_typed(&mut self) -> Vec<ScannerEvent> {
+        let events = self.reset();
+        if events.is_empty() {
+            return Vec::new();
+        }
+        vec![ScannerEvent::Typed(events)]
+    }
+
+    // At a terminator: a scan if it's long enough and framed right
+    fn finish(&mut self) -> Vec<ScannerEvent> {
+        let time = match self.start {
+            Some(time) => time,
+            None => return Vec::new(),
+        };
+        let data = self.text.strip_prefix(self.config.prefix.as_str())
+            .and_then(|data| data.strip_suffix(self.config.suffix.as_str()))
+            .map(str::to_string);
+        let mut data = match data {
+            Some(data) => data,
+            None => return self.take_typed(),
+        };
+        let mut aim = None;
+        if self.config.aim && data.starts_with(']') {
+            let mut chars = data.chars().skip(1);
+            if let (Some(code), Some(modifier)) = (chars.next(), chars.next()) {
+                aim = Some(AimId { code, modifier });
+                data = chars.collect();
+            }
+        }
+        if data.chars().count() < self.config.min_length {
+            return self.take_typed();
+        }
+        self.reset();
+        self.swallowed.extend(self.keyboard.pressed());
+        vec![ScannerEvent::Scan(Scan { time, data, aim })]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keyboard::Locks;
+    use mock::MockSource;
+    use num::FromPrimitive;
+    use source::DeviceDescription;
+
+    // Types `text` on a US keyboard, pressing keys `gap` microseconds apart
+    fn type_text(src: &mut MockSource, text: &str, gap: i64) {
+        let us = Keymap::builtin("us").unwrap();
+        let shift = Modifiers { left_shift: true, ..Default::default() };
+        for c in text.chars() {
+            let sym = match c {
+                '\n' => Keysym::named("Return"),
+                '\t' => Keysym::named("Tab"),
+                c => Keysym::Char(c),
+            };
+            let (key, shifted) = (1..0x80)
+                .filter_map(KeyCode::from_u16)
+                .flat_map(|key| vec![(key, false), (key, true)])
+                .find(|&(key, shifted)| {
+                    let mods = if shifted { shift } else { Modifiers::default() };
+                    us.keysym(key, &mods, &Locks::default()) == Some(sym.clone())
+                })
+                .unwrap();
+            src.advance(gap);
+            if shifted {
+                src.key(KeyCode::KEY_LEFTSHIFT, 1).sync();
+            }
+            src.key(key, 1).sync().advance(gap / 2).key(key, 0).sync();
+            if shifted {
+                src.key(KeyCode::KEY_LEFTSHIFT, 0).sync();
+            }
+        }
+    }
+
+    fn read_all(reader: &mut BarcodeReader, src: &mut MockSource) -> Vec<ScannerEvent> {
+        let mut out = Vec::new();
+        loop {
+            let events = reader.next_events(src).unwrap();
+            if events.is_empty() {
+                return out;
+            }
+            out.extend(events);
+        }
+    }
+
+    #[test]
+    fn scan_test() {
+        let mut src = MockSource::new(DeviceDescription::new("scanner"));
+        type_text(&mut src, "]E09780201379624\n", 4_000);
+        // Someone types on the same device, then a scan that's too short
+        type_text(&mut src, "ok\n", 150_000);
+        type_text(&mut src, "12\t", 4_000);
+
+        let config = ScannerConfig { aim: true, ..Default::default() };
+        let mut reader = BarcodeReader::new(Keymap::builtin("us").unwrap()).with_config(config);
+        let out = read_all(&mut reader, &mut src);
+        let scan = match out[0] {
+            ScannerEvent::Scan(ref scan) => scan.clone(),
+            ref other => panic!("{:?}", other),
+        };
+        assert_eq!("9780201379624", scan.data);
+        assert_eq!(Some("EAN/UPC"), scan.aim.and_then(|aim| aim.symbology()));
+        assert_eq!(4_000, scan.time.as_micros());
+
+        // The rest is typing: o, k, Enter, and 1, 2, Tab as one burst. The
+        // releases of the last keys of a burst come on their own.
+        let typed: Vec<usize> = out[1..].iter().map(|ev| match *ev {
+            ScannerEvent::Typed(ref events) => events.iter().filter(|ev| ev.ev.val == 1).count(),
+            ref other => panic!("{:?}", other),
+        }).collect();
+        assert_eq!(vec![1, 1, 1, 0, 3, 0], typed);
+        assert!(!reader.is_pending());
+    }
+
+    #[test]
+    fn framing_test() {
+        let mut src = MockSource::new(DeviceDescription::new("scanner"));
+        type_text(&mut src, "$A12345#\n", 4_000);
+        type_text(&mut src, "A12345\n", 4_000);
+        type_text(&mut src, "$B6789#", 4_000);
+
+        let config = ScannerConfig { prefix: "$".to_string(), suffix: "#".to_string(), ..Default::default() };
+        let mut reader = BarcodeReader::new(Keymap::builtin("us").unwrap()).with_config(config);
+        let mut scans = Vec::new();
+        let mut typed = 0;
+        while let Some(ev) = src.read_event().unwrap() {
+            for out in reader.process(&ev) {
+                match out {
+                    ScannerEvent::Scan(scan) => scans.push(scan.data),
+                    ScannerEvent::Typed(events) => typed += events.len(),
+                }
+            }
+        }
+        assert_eq!(vec!["A12345"], scans);
+        // The unframed scan: seven keys and Shift, pressed and released
+        assert_eq!(16, typed);
+
+        // The last one never ended
+        assert!(reader.is_pending());
+        assert!(reader.flush(TimeVal::from_micros(src.now().as_micros() + 10_000)).is_empty());
+        assert_eq!(1, reader.flush(TimeVal::from_micros(src.now().as_micros() + 60_000)).len());
+
+        // The AIM ID doesn't count towards the minimum length
+        let mut src = MockSource::new(DeviceDescription::new("scanner"));
+        type_text(&mut src, "]E0A\n", 4_000);
+        type_text(&mut src, "]E01234\n", 4_000);
+        let config = ScannerConfig { aim: true, ..Default::default() };
+        let mut reader = BarcodeReader::new(Keymap::builtin("us").unwrap()).with_config(config);
+        let mut out = Vec::new();
+        while let Some(ev) = src.read_event().unwrap() {
+            out.extend(reader.process(&ev).into_iter().map(|out| match out {
+                ScannerEvent::Scan(scan) => scan.data,
+                ScannerEvent::Typed(_) => "typed".to_string(),
+            }));
+        }
+        // A key's release may come out on its own
+        out.dedup();
+        assert_eq!(vec!["typed", "1234"], out);
+    }
+}
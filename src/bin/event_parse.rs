@@ -0,0 +1,425 @@
+extern crate event_parse;
+extern crate num;
+#[cfg(feature = "serde")] #[macro_use] extern crate serde_json;
+
+use event_parse::codes::*;
+use event_parse::multi::MultiSource;
+use event_parse::replay::{self, ReplaySource};
+use event_parse::{DeviceDescription, Error, EvdevEvent, EventDevice, EventSource};
+#[cfg(feature = "serde")]
+use event_parse::jsonl::{JsonlSource, JsonlWriter};
+use num::FromPrimitive;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+use std::{env, process, thread};
+
+const USAGE: &str = "\
+Usage: event_parse <command> [options]
+
+Commands:
+  list                      List input devices
+  info <dev>                Show a device's capabilities
+  monitor <dev>...          Print events from one or more devices
+  grab <dev>                Grab a device exclusively and print its events
+  record <dev>              Write raw events to a file (or stdout)
+  replay <file>             Print events from a raw (or .jsonl) recording
+
+A device is a path (/dev/input/event3), an index (3 or event3) or a name.
+
+Options:
+  -f, --format <fmt>        Output format: text, tsv or jsonl (default: text);
+                            record writes raw events unless jsonl is given
+  -c, --count <n>           Stop after n events
+  -o, --output <file>       Output file for record
+      --realtime            Replay with the recorded timing
+  -h, --help                Show this help";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Text,
+    Tsv,
+    #[cfg(feature = "serde")]
+    Jsonl,
+}
+
+struct Options {
+    format: Format,
+    count: Option<usize>,
+    output: Option<String>,
+    realtime: bool,
+    args: Vec<String>,
+}
+
+impl Options {
+    /// Whether to read another event after `seen` of them.
+    fn wants_more(&self, seen: usize) -> bool {
+        self.count.is_none_or(|c| seen < c)
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, Error> {
+    let mut opts = Options {
+        format: Format::Text,
+        count: None,
+        output: None,
+        realtime: false,
+        args: Vec::new(),
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = match arg.find('=') {
+            Some(pos) if arg.starts_with("--") => (&arg[..pos], Some(arg[pos + 1..].to_string())),
+            _ => (&arg[..], None),
+        };
+        let mut value = |name: &str| -> Result<String, Error> {
+            match inline.clone() {
+                Some(v) => Ok(v),
+                None => iter.next().cloned().ok_or_else(|| Error::from(format!("{} needs a value", name))),
+            }
+        };
+        match flag {
+            "-f" | "--format" => {
+                opts.format = match &value(flag)?[..] {
+                    "text" => Format::Text,
+                    "tsv" => Format::Tsv,
+                    #[cfg(feature = "serde")]
+                    "jsonl" => Format::Jsonl,
+                    other => return Err(Error::from(format!("unknown format '{}'", other))),
+                }
+            }
+            "-c" | "--count" => opts.count = Some(value(flag)?.parse()?),
+            "-o" | "--output" => opts.output = Some(value(flag)?),
+            "--realtime" => opts.realtime = true,
+            f if f.starts_with('-') && f.len() > 1 => {
+                return Err(Error::from(format!("unknown option '{}'", f)))
+            }
+            _ => opts.args.push(arg.clone()),
+        }
+    }
+    Ok(opts)
+}
+
+fn type_name(ty: u16) -> String {
+    match TypeCode::from_u16(ty) {
+        Some(t) => t.to_string(),
+        None => "?".to_string(),
+    }
+}
+
+fn write_event<W: Write>(out: &mut W, format: Format, dev: &str, ev: &EvdevEvent) -> Result<(), Error> {
+    let (ty, code) = ev.ev.code.to_raw();
+    match format {
+        Format::Tsv => writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}", dev, ev.time, ty, code, ev.ev.val,
+                                type_name(ty), ev.ev.code)?,
+        Format::Text if dev.is_empty() => writeln!(out, "{}", ev)?,
+        Format::Text => writeln!(out, "[{}] {}", dev, ev)?,
+        #[cfg(feature = "serde")]
+        Format::Jsonl => {
+            let mut value = serde_json::to_value(ev)?;
+            if !dev.is_empty() {
+                value["device"] = json!(dev);
+            }
+            JsonlWriter::new(out).write(&value)?;
+        }
+    }
+    Ok(())
+}
+
+fn list(opts: &Options) -> Result<(), Error> {
+    let mut devices: Vec<_> = event_parse::list_devices()?.into_iter().collect();
+    devices.sort_by_key(|d| d.0);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (num, (name, _)) in devices {
+        match opts.format {
+            Format::Text => writeln!(out, "/dev/input/event{}:\t{}", num, name)?,
+            Format::Tsv => writeln!(out, "{}\t/dev/input/event{}\t{}", num, num, name)?,
+            #[cfg(feature = "serde")]
+            Format::Jsonl => JsonlWriter::new(&mut out).write(&json!({
+                "index": num,
+                "path": format!("/dev/input/event{}", num),
+                "name": name,
+            }))?,
+        }
+    }
+    Ok(())
+}
+
+fn info(opts: &Options) -> Result<(), Error> {
+    if opts.args.len() != 1 {
+        return Err(Error::from("info takes exactly one device".to_string()));
+    }
+    let dev = event_parse::find_device(&opts.args[0])?;
+    let desc = DeviceDescription::from_source(&dev);
+    let id = dev.input_id();
+
+    let mut codes: Vec<_> = desc.codes.iter().cloned().collect();
+    codes.sort_by_key(|c| c.to_raw());
+    let mut props: Vec<_> = desc.props.iter().cloned().collect();
+    props.sort();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match opts.format {
+        Format::Text => {
+            let version = dev.driver_version();
+            writeln!(out, "Input driver version is {}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff)?;
+            writeln!(out, "Input device ID: bus {:#x} vendor {:#x} product {:#x} version {:#x}",
+                     id.bustype, id.vendor, id.product, id.version)?;
+            writeln!(out, "Input device name: \"{}\"", dev.name())?;
+            if let Some(phys) = dev.phys() {
+                writeln!(out, "Input device phys: \"{}\"", phys)?;
+            }
+            writeln!(out, "Supported events:")?;
+            let mut last_type = None;
+            for code in &codes {
+                let (ty, num) = code.to_raw();
+                if last_type != Some(ty) {
+                    writeln!(out, "  Event type {} ({})", ty, type_name(ty))?;
+                    last_type = Some(ty);
+                }
+                if ty == TypeCode::EV_SYN as u16 {
+                    continue;
+                }
+                writeln!(out, "    Event code {} ({})", num, code)?;
+                if let EvdevCode::AbsCode(abs) = *code {
+                    if let Some(info) = desc.abs_info(abs) {
+                        writeln!(out, "      Value {:6}", info.value)?;
+                        writeln!(out, "      Min   {:6}", info.minimum)?;
+                        writeln!(out, "      Max   {:6}", info.maximum)?;
+                        if info.fuzz != 0 {
+                            writeln!(out, "      Fuzz  {:6}", info.fuzz)?;
+                        }
+                        if info.flat != 0 {
+                            writeln!(out, "      Flat  {:6}", info.flat)?;
+                        }
+                        if info.resolution != 0 {
+                            writeln!(out, "      Resolution {:6}", info.resolution)?;
+                        }
+                    }
+                }
+            }
+            writeln!(out, "Properties:")?;
+            for prop in props {
+                writeln!(out, "  Property type {} ({})", prop as u16, prop)?;
+            }
+        }
+        Format::Tsv => {
+            writeln!(out, "name\t{}", dev.name())?;
+            writeln!(out, "id\t{:#x}\t{:#x}\t{:#x}\t{:#x}", id.bustype, id.vendor, id.product, id.version)?;
+            for code in &codes {
+                let (ty, num) = code.to_raw();
+                write!(out, "code\t{}\t{}\t{}\t{}", ty, num, type_name(ty), code)?;
+                if let Some(info) = match *code { EvdevCode::AbsCode(abs) => desc.abs_info(abs), _ => None } {
+                    write!(out, "\t{}\t{}\t{}\t{}\t{}\t{}", info.value, info.minimum, info.maximum,
+                           info.fuzz, info.flat, info.resolution)?;
+                }
+                writeln!(out)?;
+            }
+            for prop in props {
+                writeln!(out, "prop\t{}\t{}", prop as u16, prop)?;
+            }
+        }
+        #[cfg(feature = "serde")]
+        Format::Jsonl => JsonlWriter::new(&mut out).write(&desc)?,
+    }
+    Ok(())
+}
+
+fn open_all(specs: &[String]) -> Result<Vec<EventDevice>, Error> {
+    if specs.is_empty() {
+        return Err(Error::from("no device given".to_string()));
+    }
+    specs.iter()
+        .map(|spec| event_parse::find_device(spec)
+             .map_err(|e| Error::from(format!("{}: {}", spec, e))))
+        .collect()
+}
+
+fn monitor(opts: &Options, grab: bool) -> Result<(), Error> {
+    if grab && opts.args.len() != 1 {
+        return Err(Error::from("grab takes exactly one device".to_string()));
+    }
+    let mut devices = open_all(&opts.args)?;
+    for dev in &mut devices {
+        dev.set_nonblocking(true)?;
+        if grab {
+            dev.grab(true)?;
+        }
+    }
+    // Only tag events with their device when there is more than one
+    let labels: Vec<String> = if opts.args.len() > 1 || opts.format == Format::Tsv {
+        opts.args.clone()
+    } else {
+        vec![String::new()]
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut multi = MultiSource::new(devices);
+    let mut seen = 0;
+    while opts.wants_more(seen) {
+        let (idx, ev) = match multi.read_event()? {
+            Some(next) => next,
+            None => break,
+        };
+        write_event(&mut out, opts.format, &labels[idx], &ev)?;
+        if ev.is_syn_report() {
+            out.flush()?;
+        }
+        seen += 1;
+    }
+    Ok(())
+}
+
+fn record(opts: &Options) -> Result<(), Error> {
+    if opts.args.len() != 1 {
+        return Err(Error::from("record takes exactly one device".to_string()));
+    }
+    let mut dev = event_parse::find_device(&opts.args[0])?;
+    let mut out: Box<dyn Write> = match opts.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    #[cfg(feature = "serde")]
+    {
+        if opts.format == Format::Jsonl {
+            JsonlWriter::new(&mut out).write(&DeviceDescription::from_source(&dev))?;
+        }
+    }
+
+    let mut seen = 0;
+    while opts.wants_more(seen) {
+        let ev = match dev.read_event()? {
+            Some(ev) => ev,
+            None => break,
+        };
+        match opts.format {
+            #[cfg(feature = "serde")]
+            Format::Jsonl => JsonlWriter::new(&mut out).write(&ev)?,
+            _ => replay::write_raw_event(&mut out, &ev)?,
+        }
+        if ev.is_syn_report() {
+            out.flush()?;
+        }
+        seen += 1;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn open_recording(path: &str) -> Result<Box<dyn EventSource>, Error> {
+    if path.ends_with(".jsonl") {
+        return Ok(Box::new(JsonlSource::open(path)?));
+    }
+    Ok(Box::new(ReplaySource::open(path, DeviceDescription::new(path))?))
+}
+
+#[cfg(not(feature = "serde"))]
+fn open_recording(path: &str) -> Result<Box<dyn EventSource>, Error> {
+    Ok(Box::new(ReplaySource::open(path, DeviceDescription::new(path))?))
+}
+
+fn replay(opts: &Options) -> Result<(), Error> {
+    if opts.args.len() != 1 {
+        return Err(Error::from("replay takes exactly one file".to_string()));
+    }
+    let path = &opts.args[0];
+    let mut src = open_recording(path)?;
+    let label = if opts.format == Format::Tsv { path.clone() } else { String::new() };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut last = None;
+    let mut seen = 0;
+    while opts.wants_more(seen) {
+        let ev = match src.read_event()? {
+            Some(ev) => ev,
+            None => break,
+        };
+        if opts.realtime {
+            if let Some(prev) = last {
+                let delta = ev.time.as_micros() - prev;
+                if delta > 0 {
+                    out.flush()?;
+                    thread::sleep(Duration::from_micros(delta as u64));
+                }
+            }
+            last = Some(ev.time.as_micros());
+        }
+        write_event(&mut out, opts.format, &label, &ev)?;
+        seen += 1;
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Error> {
+    let cmd = match args.first() {
+        Some(cmd) => &cmd[..],
+        None => return Err(Error::from(format!("no command given\n\n{}", USAGE))),
+    };
+    if cmd == "-h" || cmd == "--help" || cmd == "help" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let opts = parse_options(&args[1..])?;
+    match cmd {
+        "list" => list(&opts),
+        "info" => info(&opts),
+        "monitor" => monitor(&opts, false),
+        "grab" => monitor(&opts, true),
+        "record" => record(&opts),
+        "replay" => replay(&opts),
+        _ => Err(Error::from(format!("unknown command '{}'\n\n{}", cmd, USAGE))),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("event_parse: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, Error> {
+        parse_options(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parse_options_test() {
+        let opts = parse(&["-f", "tsv", "--count=0", "event3", "-o", "out.raw", "--realtime", "kbd"]).unwrap();
+        assert_eq!(Format::Tsv, opts.format);
+        assert_eq!(Some(0), opts.count);
+        assert!(!opts.wants_more(0));
+        assert_eq!(Some("out.raw".to_string()), opts.output);
+        assert!(opts.realtime);
+        assert_eq!(vec!["event3", "kbd"], opts.args);
+
+        let opts = parse(&["--format=text", "-c", "2", "-"]).unwrap();
+        assert_eq!((Format::Text, Some(2)), (opts.format, opts.count));
+        assert!(opts.wants_more(1) && !opts.wants_more(2));
+        assert_eq!(vec!["-"], opts.args);
+        assert!(parse(&[]).unwrap().wants_more(1_000_000));
+    }
+
+    #[test]
+    fn parse_options_error_test() {
+        let err = |args: &[&str]| parse(args).err().map(|e| e.to_string());
+        assert_eq!(Some("unknown format 'xml'".to_string()), err(&["-f", "xml"]));
+        assert!(err(&["--count", "many"]).is_some());
+        assert!(err(&["-c", "-1"]).is_some());
+        assert_eq!(Some("unknown option '--bogus'".to_string()), err(&["--bogus"]));
+        assert_eq!(Some("-o needs a value".to_string()), err(&["-o"]));
+        assert_eq!(Some("--count needs a value".to_string()), err(&["event3", "--count"]));
+    }
+}
@@ -0,0 +1,445 @@
+//! Mapping raw touch coordinates to screen pixels.
+//!
+//! Coordinates are first normalized to the device's axis range, then run
+//! through a 2x3 affine matrix with libinput's `LIBINPUT_CALIBRATION_MATRIX`
+//! semantics, then scaled to the screen resolution.
+//!
+//! Existing calibrations can be imported from tslib's `pointercal` file and
+//! from the `LIBINPUT_CALIBRATION_MATRIX` and `WL_CALIBRATION` udev properties.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Mul;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use source::{position_axes, AbsInfo, EventSource};
+use touch::{Contact, TouchFrame};
+use Error;
+
+/// A row-major 2x3 affine matrix `[a, b, c, d, e, f]`, mapping normalized
+/// `(x, y)` to `(a*x + b*y + c, d*x + e*y + f)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Matrix(pub [f64; 6]);
+
+pub const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Rotation {
+    None,
+    /// Clockwise, as seen on the screen.
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Matrix {
+    pub fn rotation(rotation: Rotation) -> Matrix {
+        match rotation {
+            Rotation::None => IDENTITY,
+            Rotation::Cw90 => Matrix([0.0, -1.0, 1.0, 1.0, 0.0, 0.0]),
+            Rotation::Cw180 => Matrix([-1.0, 0.0, 1.0, 0.0, -1.0, 1.0]),
+            Rotation::Cw270 => Matrix([0.0, 1.0, 0.0, -1.0, 0.0, 1.0]),
+        }
+    }
+
+    pub fn flip_x() -> Matrix {
+        Matrix([-1.0, 0.0, 1.0, 0.0, 1.0, 0.0])
+    }
+
+    pub fn flip_y() -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 0.0, -1.0, 1.0])
+    }
+
+    pub fn swap_axes() -> Matrix {
+        Matrix([0.0, 1.0, 0.0, 1.0, 0.0, 0.0])
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = &self.0;
+        (m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])
+    }
+
+    pub fn invert(&self) -> Option<Matrix> {
+        let [a, b, c, d, e, f] = self.0;
+        let det = a * e - b * d;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        Some(Matrix([
+            e / det, -b / det, (b * f - c * e) / det,
+            -d / det, a / det, (c * d - a * f) / det,
+        ]))
+    }
+
+    /// Weston's `WL_CALIBRATION`: libinput's layout, but with the translation
+    /// in pixels of the `width` x `height` output. Converted the way weston does.
+    pub fn from_wl_calibration(s: &str, width: u32, height: u32) -> Result<Matrix, Error> {
+        let mut m: Matrix = s.parse()?;
+        m.0[2] /= f64::from(width);
+        m.0[5] /= f64::from(height);
+        Ok(m)
+    }
+
+    /// Least-squares fit of the matrix mapping each `from` point onto the `to`
+    /// point at the same index. Needs at least three points not on one line.
+    pub fn fit(from: &[(f64, f64)], to: &[(f64, f64)]) -> Option<Matrix> {
+        if from.len() < 3 || from.len() != to.len() {
+            return None;
+        }
+        // Normal equations: (A^T A) p = A^T t with rows [x, y, 1]
+        let mut ata = [[0.0; 3]; 3];
+        let mut atx = [0.0; 3];
+        let mut aty = [0.0; 3];
+        for (&(x, y), &(tx, ty)) in from.iter().zip(to) {
+            let row = [x, y, 1.0];
+            for i in 0..3 {
+                for j in 0..3 {
+                    ata[i][j] += row[i] * row[j];
+                }
+                atx[i] += row[i] * tx;
+                aty[i] += row[i] * ty;
+            }
+        }
+        let p = solve3(&ata, &atx)?;
+        let q = solve3(&ata, &aty)?;
+        Some(Matrix([p[0], p[1], p[2], q[0], q[1], q[2]]))
+    }
+}
+
+/// `self * rhs` applies `rhs` first, then `self`.
+impl Mul for Matrix {
+    type Output = Matrix;
+
+    fn mul(self, rhs: Matrix) -> Matrix {
+        let l = &self.0;
+        let r = &rhs.0;
+        Matrix([
+            l[0] * r[0] + l[1] * r[3], l[0] * r[1] + l[1] * r[4], l[0] * r[2] + l[1] * r[5] + l[2],
+            l[3] * r[0] + l[4] * r[3], l[3] * r[1] + l[4] * r[4], l[3] * r[2] + l[4] * r[5] + l[5],
+        ])
+    }
+}
+
+/// Six numbers separated by whitespace, as in `LIBINPUT_CALIBRATION_MATRIX`.
+impl FromStr for Matrix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Matrix, Error> {
+        let nums = s.trim().trim_matches('"').split_whitespace()
+            .map(|n| n.parse::<f64>().map_err(|e| Error(format!("bad matrix value '{}': {}", n, e))))
+            .collect::<Result<Vec<_>, _>>()?;
+        if nums.len() != 6 {
+            return Err(Error(format!("calibration matrix needs 6 values, got {}", nums.len())));
+        }
+        let mut m = [0.0; 6];
+        m.copy_from_slice(&nums);
+        Ok(Matrix(m))
+    }
+}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = &self.0;
+        write!(f, "{} {} {} {} {} {}", m[0], m[1], m[2], m[3], m[4], m[5])
+    }
+}
+
+fn det3(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// Cramer's rule, good enough for a 3x3 system
+fn solve3(m: &[[f64; 3]; 3], b: &[f64; 3]) -> Option<[f64; 3]> {
+    let det = det3(m);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut out = [0.0; 3];
+    for (col, o) in out.iter_mut().enumerate() {
+        let mut mc = *m;
+        for row in 0..3 {
+            mc[row][col] = b[row];
+        }
+        *o = det3(&mc) / det;
+    }
+    Some(out)
+}
+
+/// One tap of a calibration run: where the target was drawn and the raw
+/// position the panel reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sample {
+    pub screen: (f64, f64),
+    pub raw: (i32, i32),
+}
+
+/// Where tslib's `ts_calibrate` puts its five targets: the corners inset by
+/// `margin` pixels, then the centre.
+pub fn five_point_targets(width: u32, height: u32, margin: f64) -> [(f64, f64); 5] {
+    let (w, h) = (f64::from(width), f64::from(height));
+    [
+        (margin, margin),
+        (w - margin, margin),
+        (w - margin, h - margin),
+        (margin, h - margin),
+        (w / 2.0, h / 2.0),
+    ]
+}
+
+/// tslib's `pointercal`: `a b c d e f s [xres yres]`, mapping raw positions
+/// straight to pixels as `((a*x + b*y + c) / s, (d*x + e*y + f) / s)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pointercal {
+    pub coeffs: [i64; 7],
+    /// The screen size the file was made for, if it says.
+    pub resolution: Option<(u32, u32)>,
+}
+
+impl Pointercal {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Pointercal, Error> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl FromStr for Pointercal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Pointercal, Error> {
+        let nums = s.split_whitespace().map(|n| n.parse::<i64>()).collect::<Result<Vec<_>, _>>()?;
+        let resolution = match nums.len() {
+            7 => None,
+            9 if nums[7] > 0 && nums[8] > 0 => Some((nums[7] as u32, nums[8] as u32)),
+            _ => return Err(Error(format!("malformed pointercal: '{}'", s.trim()))),
+        };
+        if nums[6] == 0 {
+            return Err(Error("pointercal divisor is zero".to_string()));
+        }
+        let mut coeffs = [0; 7];
+        coeffs.copy_from_slice(&nums[..7]);
+        Ok(Pointercal { coeffs, resolution })
+    }
+}
+
+/// Properties from udev text: `udevadm info` output (`E: KEY=value`), the
+/// udev database (`E:KEY=value`), rules (`ENV{KEY}="value"`) or hwdb entries
+/// (`KEY=value`).
+pub fn udev_properties(text: &str) -> HashMap<String, String> {
+    let mut props = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("E:").unwrap_or(line).trim_start();
+        for part in line.split(',') {
+            let part = part.trim();
+            let (key, value) = if let Some(rest) = part.strip_prefix("ENV{") {
+                match rest.find("}=") {
+                    Some(end) if !rest[end + 2..].starts_with('=') => (&rest[..end], &rest[end + 2..]),
+                    _ => continue,
+                }
+            } else {
+                match part.find('=') {
+                    Some(eq) => (&part[..eq], &part[eq + 1..]),
+                    None => continue,
+                }
+            };
+            if !key.is_empty() && key.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+                props.insert(key.to_string(), value.trim_matches('"').to_string());
+            }
+        }
+    }
+    props
+}
+
+/// The properties udev stored for a device node such as `/dev/input/event3`,
+/// read from the udev database without going through libudev.
+pub fn udev_device_properties<P: AsRef<Path>>(devnode: P) -> Result<HashMap<String, String>, Error> {
+    let rdev = fs::metadata(devnode)?.rdev();
+    let db = fs::read_to_string(format!("/run/udev/data/c{}:{}", libc::major(rdev), libc::minor(rdev)))?;
+    Ok(udev_properties(&db))
+}
+
+/// Maps raw positions of one device to pixels of one screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    pub x: AbsInfo,
+    pub y: AbsInfo,
+    pub width: u32,
+    pub height: u32,
+    pub matrix: Matrix,
+}
+
+impl Calibration {
+    pub fn new(x: AbsInfo, y: AbsInfo, width: u32, height: u32) -> Calibration {
+        Calibration { x, y, width, height, matrix: IDENTITY }
+    }
+
+    /// Maps the source's `position_axes` to a `width` by `height` screen.
+    pub fn for_source<S: EventSource + ?Sized>(src: &S, width: u32, height: u32) -> Result<Calibration, Error> {
+        let (x, y) = position_axes(src)?;
+        Ok(Calibration::new(x, y, width, height))
+    }
+
+    pub fn with_matrix(mut self, matrix: Matrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Applies `matrix` after the current one, e.g. a rotation preset on top
+    /// of a measured calibration.
+    pub fn then(mut self, matrix: Matrix) -> Self {
+        self.matrix = matrix * self.matrix;
+        self
+    }
+
+    /// Converts a tslib calibration to a matrix for this device. Pixels are
+    /// taken relative to the pointercal's resolution if it has one, so the
+    /// result still fits when the screen size changed.
+    pub fn with_pointercal(mut self, pc: &Pointercal) -> Self {
+        let c = pc.coeffs.map(|n| n as f64);
+        let (w, h) = pc.resolution.unwrap_or((self.width, self.height));
+        let (sx, sy) = (c[6] * f64::from(w), c[6] * f64::from(h));
+        let (x0, y0) = (f64::from(self.x.minimum), f64::from(self.y.minimum));
+        let (xs, ys) = (span(&self.x), span(&self.y));
+        self.matrix = Matrix([
+            c[0] * xs / sx, c[1] * ys / sx, (c[0] * x0 + c[1] * y0 + c[2]) / sx,
+            c[3] * xs / sy, c[4] * ys / sy, (c[3] * x0 + c[4] * y0 + c[5]) / sy,
+        ]);
+        self
+    }
+
+    /// Applies `LIBINPUT_CALIBRATION_MATRIX`, or failing that `WL_CALIBRATION`,
+    /// from `props` (see `udev_properties`). Without either the calibration is
+    /// returned unchanged.
+    pub fn with_udev_properties(self, props: &HashMap<String, String>) -> Result<Self, Error> {
+        if let Some(m) = props.get("LIBINPUT_CALIBRATION_MATRIX") {
+            Ok(self.with_matrix(m.parse()?))
+        } else if let Some(m) = props.get("WL_CALIBRATION") {
+            Ok(self.with_matrix(Matrix::from_wl_calibration(m, self.width, self.height)?))
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn normalize(&self, x: i32, y: i32) -> (f64, f64) {
+        ((f64::from(x) - f64::from(self.x.minimum)) / span(&self.x),
+         (f64::from(y) - f64::from(self.y.minimum)) / span(&self.y))
+    }
+
+    /// Raw device position to screen pixels.
+    pub fn map(&self, x: i32, y: i32) -> (f64, f64) {
+        let (nx, ny) = self.normalize(x, y);
+        let (sx, sy) = self.matrix.apply(nx, ny);
+        (sx * f64::from(self.width), sy * f64::from(self.height))
+    }
+
+    pub fn map_contact(&self, contact: &Contact) -> Contact {
+        let (x, y) = self.map(contact.x, contact.y);
+        Contact {
+            x: x.round() as i32,
+            y: y.round() as i32,
+            ..*contact
+        }
+    }
+
+    /// The frame with every contact position in screen pixels.
+    pub fn map_frame(&self, frame: &TouchFrame) -> TouchFrame {
+        TouchFrame {
+            time: frame.time,
+            contacts: frame.contacts.iter().map(|c| self.map_contact(c)).collect(),
+        }
+    }
+
+    /// Fits the matrix to tapped samples, tslib style. Five samples (see
+    /// `five_point_targets`) are usual; three is the minimum.
+    pub fn solve(mut self, samples: &[Sample]) -> Result<Calibration, Error> {
+        let (w, h) = (f64::from(self.width), f64::from(self.height));
+        let from: Vec<_> = samples.iter().map(|s| self.normalize(s.raw.0, s.raw.1)).collect();
+        let to: Vec<_> = samples.iter().map(|s| (s.screen.0 / w, s.screen.1 / h)).collect();
+        self.matrix = Matrix::fit(&from, &to)
+            .ok_or_else(|| Error("calibration needs three or more samples not on one line".to_string()))?;
+        Ok(self)
+    }
+}
+
+fn span(axis: &AbsInfo) -> f64 {
+    f64::from(axis.maximum) - f64::from(axis.minimum) + 1.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn axis(max: i32) -> AbsInfo {
+        AbsInfo { maximum: max, ..Default::default() }
+    }
+
+    fn assert_near(expected: (f64, f64), actual: (f64, f64)) {
+        assert!((expected.0 - actual.0).abs() < 1e-6 && (expected.1 - actual.1).abs() < 1e-6,
+                "expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn presets_test() {
+        let cal = Calibration::new(axis(1023), axis(1023), 800, 480);
+        assert_near((400.0, 240.0), cal.map(512, 512));
+
+        let rotated = cal.then(Matrix::rotation(Rotation::Cw90));
+        assert_near((800.0, 0.0), rotated.map(0, 0));
+        let flipped = cal.then(Matrix::flip_x()).then(Matrix::flip_x());
+        assert_near(cal.map(100, 900), flipped.map(100, 900));
+        assert_near((800.0, 0.0), cal.then(Matrix::swap_axes()).map(0, 1024));
+    }
+
+    #[test]
+    fn five_point_test() {
+        let truth = Calibration::new(axis(4095), axis(4095), 800, 480)
+            .with_matrix(Matrix([0.0, -1.02, 1.01, 0.98, 0.0, -0.01]));
+        let inverse = truth.matrix.invert().unwrap();
+        let samples: Vec<_> = five_point_targets(800, 480, 50.0).iter()
+            .map(|&(sx, sy)| {
+                let (nx, ny) = inverse.apply(sx / 800.0, sy / 480.0);
+                Sample { screen: (sx, sy), raw: ((nx * 4096.0) as i32, (ny * 4096.0) as i32) }
+            })
+            .collect();
+
+        let solved = Calibration::new(axis(4095), axis(4095), 800, 480).solve(&samples).unwrap();
+        for s in &samples {
+            let (x, y) = solved.map(s.raw.0, s.raw.1);
+            assert!((x - s.screen.0).abs() < 1.0 && (y - s.screen.1).abs() < 1.0);
+        }
+        assert!(Calibration::new(axis(10), axis(10), 1, 1).solve(&samples[..2]).is_err());
+    }
+
+    #[test]
+    fn import_test() {
+        let cal = Calibration::new(axis(4095), axis(4095), 1024, 600);
+        // Scales to 800x480 and flips y
+        let pc: Pointercal = "12800 0 0 0 -7680 31457280 65536 800 480".parse().unwrap();
+        assert_eq!(Some((800, 480)), pc.resolution);
+        let mapped = cal.with_pointercal(&pc);
+        assert_near((512.0, 600.0), mapped.map(2048, 0));
+        assert!("1 0 0 0 1 0 0".parse::<Pointercal>().is_err());
+
+        let props = udev_properties("\
+E:ID_INPUT_TOUCHSCREEN=1
+E:WL_CALIBRATION=0 1 0 1 0 0
+ENV{LIBINPUT_CALIBRATION_MATRIX}=\"-1 0 1 0 1 0\"
+");
+        assert_eq!("1", props["ID_INPUT_TOUCHSCREEN"]);
+        let flipped = cal.with_udev_properties(&props).unwrap();
+        assert_near((1024.0, 0.0), flipped.map(0, 0));
+
+        let wl = Matrix::from_wl_calibration("1 0 512 0 1 0", 1024, 600).unwrap();
+        assert_eq!(Matrix([1.0, 0.0, 0.5, 0.0, 1.0, 0.0]), wl);
+        assert_eq!("1 0 0.5 0 1 0", wl.to_string());
+    }
+}
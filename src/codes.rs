@@ -1,6 +1,7 @@
 //#[macro_use] extern crate enum_primitive;
+use num::FromPrimitive;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EvdevCode {
     SynCode(SynCode),
 	KeyCode(KeyCode),
@@ -11,11 +12,12 @@ pub enum EvdevCode {
     LedCode(LedCode),
     SndCode(SndCode),
     RepCode(RepCode),
-    Undefined(u16),
+    Undefined(u16, u16),
 }
 
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum TypeCode {
         EV_SYN			= 0x00,
         EV_KEY			= 0x01,
@@ -39,7 +41,8 @@ enum_from_primitive! {
 }
 
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum SynCode {
         SYN_REPORT		    = 0,
         SYN_CONFIG		    = 1,
@@ -51,7 +54,8 @@ enum_from_primitive! {
 }
 
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum KeyCode {
         KEY_RESERVED	    = 0,
         KEY_ESC			    = 1,
@@ -681,7 +685,8 @@ enum_from_primitive! {
  * Relative axes,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum RelCode {
         REL_X			        = 0x00,
         REL_Y			        = 0x01,
@@ -702,7 +707,8 @@ enum_from_primitive! {
  * Absolute axes,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum AbsCode {
         ABS_X			    = 0x00,
         ABS_Y			    = 0x01,
@@ -758,7 +764,8 @@ enum_from_primitive! {
  * Switch events,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum SwiCode {
         SW_LID			        = 0x00  /* set = lid shut */,
         SW_TABLET_MODE		    = 0x01  /* set = tablet mode */,
@@ -787,7 +794,8 @@ enum_from_primitive! {
  * Misc events,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum MscCode {
         MSC_SERIAL		= 0x00,
         MSC_PULSELED	= 0x01,
@@ -804,7 +812,8 @@ enum_from_primitive! {
  * LEDs,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum LedCode {
         LED_NUML		= 0x00,
         LED_CAPSL		= 0x01,
@@ -826,7 +835,8 @@ enum_from_primitive! {
  * Autorepeat values,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum RepCode {
         REP_DELAY		= 0x00,
         REP_PERIOD		= 0x01,
@@ -839,7 +849,8 @@ enum_from_primitive! {
  * Sounds,
  */
 enum_from_primitive! {
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub enum SndCode {
         SND_CLICK		= 0x00,
         SND_BELL		= 0x01,
@@ -847,4 +858,124 @@ enum_from_primitive! {
         //SND_MAX			= 0x07,
         //SND_CNT			(SND_MAX+1),
     }
-}
\ No newline at end of file
+}
+/*,
+ * Device properties,
+ */
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum PropCode {
+        INPUT_PROP_POINTER		    = 0x00	/* needs a pointer */,
+        INPUT_PROP_DIRECT		    = 0x01	/* direct input devices */,
+        INPUT_PROP_BUTTONPAD	    = 0x02	/* has button(s) under pad */,
+        INPUT_PROP_SEMI_MT		    = 0x03	/* touch rectangle only */,
+        INPUT_PROP_TOPBUTTONPAD	    = 0x04	/* softbuttons at top of pad */,
+        INPUT_PROP_POINTING_STICK	= 0x05	/* is a pointing stick */,
+        INPUT_PROP_ACCELEROMETER	= 0x06	/* has accelerometer */,
+        //INPUT_PROP_MAX			= 0x1f,
+        //INPUT_PROP_CNT			(INPUT_PROP_MAX + 1),
+    }
+}
+
+impl EvdevCode {
+    /// Maps a raw `(type, code)` pair, `None` if either part is unknown.
+    pub fn from_raw(ty: u16, num: u16) -> Option<EvdevCode> {
+        match TypeCode::from_u16(ty) {
+            Some(TypeCode::EV_SYN) => SynCode::from_u16(num).map(EvdevCode::SynCode),
+            Some(TypeCode::EV_KEY) => KeyCode::from_u16(num).map(EvdevCode::KeyCode),
+            Some(TypeCode::EV_REL) => RelCode::from_u16(num).map(EvdevCode::RelCode),
+            Some(TypeCode::EV_ABS) => AbsCode::from_u16(num).map(EvdevCode::AbsCode),
+            Some(TypeCode::EV_MSC) => MscCode::from_u16(num).map(EvdevCode::MscCode),
+            Some(TypeCode::EV_SW) => SwiCode::from_u16(num).map(EvdevCode::SwiCode),
+            Some(TypeCode::EV_LED) => LedCode::from_u16(num).map(EvdevCode::LedCode),
+            Some(TypeCode::EV_SND) => SndCode::from_u16(num).map(EvdevCode::SndCode),
+            Some(TypeCode::EV_REP) => RepCode::from_u16(num).map(EvdevCode::RepCode),
+            _ => None,
+        }
+    }
+
+    pub fn type_code(&self) -> Option<TypeCode> {
+        match *self {
+            EvdevCode::SynCode(_) => Some(TypeCode::EV_SYN),
+            EvdevCode::KeyCode(_) => Some(TypeCode::EV_KEY),
+            EvdevCode::RelCode(_) => Some(TypeCode::EV_REL),
+            EvdevCode::AbsCode(_) => Some(TypeCode::EV_ABS),
+            EvdevCode::MscCode(_) => Some(TypeCode::EV_MSC),
+            EvdevCode::SwiCode(_) => Some(TypeCode::EV_SW),
+            EvdevCode::LedCode(_) => Some(TypeCode::EV_LED),
+            EvdevCode::SndCode(_) => Some(TypeCode::EV_SND),
+            EvdevCode::RepCode(_) => Some(TypeCode::EV_REP),
+            EvdevCode::Undefined(..) => None,
+        }
+    }
+
+    /// The raw `(type, code)` pair as found in a kernel `input_event`.
+    pub fn to_raw(&self) -> (u16, u16) {
+        match *self {
+            EvdevCode::SynCode(c) => (TypeCode::EV_SYN as u16, c as u16),
+            EvdevCode::KeyCode(c) => (TypeCode::EV_KEY as u16, c as u16),
+            EvdevCode::RelCode(c) => (TypeCode::EV_REL as u16, c as u16),
+            EvdevCode::AbsCode(c) => (TypeCode::EV_ABS as u16, c as u16),
+            EvdevCode::MscCode(c) => (TypeCode::EV_MSC as u16, c as u16),
+            EvdevCode::SwiCode(c) => (TypeCode::EV_SW as u16, c as u16),
+            EvdevCode::LedCode(c) => (TypeCode::EV_LED as u16, c as u16),
+            EvdevCode::SndCode(c) => (TypeCode::EV_SND as u16, c as u16),
+            EvdevCode::RepCode(c) => (TypeCode::EV_REP as u16, c as u16),
+            EvdevCode::Undefined(t, c) => (t, c),
+        }
+    }
+}
+
+// Codes serialize as their symbolic name ("KEY_A"); codes without a name as a
+// `[type, code]` pair.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+
+    impl Serialize for EvdevCode {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            match *self {
+                EvdevCode::SynCode(ref c) => c.serialize(s),
+                EvdevCode::KeyCode(ref c) => c.serialize(s),
+                EvdevCode::RelCode(ref c) => c.serialize(s),
+                EvdevCode::AbsCode(ref c) => c.serialize(s),
+                EvdevCode::MscCode(ref c) => c.serialize(s),
+                EvdevCode::SwiCode(ref c) => c.serialize(s),
+                EvdevCode::LedCode(ref c) => c.serialize(s),
+                EvdevCode::SndCode(ref c) => c.serialize(s),
+                EvdevCode::RepCode(ref c) => c.serialize(s),
+                EvdevCode::Undefined(ty, code) => (ty, code).serialize(s),
+            }
+        }
+    }
+
+    struct CodeVisitor;
+
+    impl<'de> Visitor<'de> for CodeVisitor {
+        type Value = EvdevCode;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an event code name or a [type, code] pair")
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<EvdevCode, E> {
+            name.parse().map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<EvdevCode, A::Error> {
+            let ty = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let code = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+            Ok(EvdevCode::from_raw(ty, code).unwrap_or(EvdevCode::Undefined(ty, code)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for EvdevCode {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<EvdevCode, D::Error> {
+            d.deserialize_any(CodeVisitor)
+        }
+    }
+}
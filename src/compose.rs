@@ -0,0 +1,343 @@
+//! Dead keys and compose sequences.
+//!
+//! A `ComposeTable` maps keysym sequences to text, like X11 `Compose`
+//! files: `<Multi_key> <o> <c> : "©" copyright`. `ComposeTable::dead_keys`
+//! covers the dead keys of the built-in keymaps on its own. A `Composer`
+//! follows the symbols typed and reports when a sequence is under way,
+//! finished or broken off.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+
+use codes::*;
+use keyboard::{KeyEvent, KeyState, Modifiers};
+use keymap::{Keymap, Keysym};
+use Error;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Dead key, the character it types before a space and when pressed twice,
+// and the base and composed character pairs
+const DEAD_KEYS: [(&str, char, char, &str); 7] = [
+    ("dead_grave", '`', '`', "aà eè iì oò uù AÀ EÈ IÌ OÒ UÙ"),
+    ("dead_acute", '\'', '´', "aá eé ií oó uú yý cć nń sś zź AÁ EÉ IÍ OÓ UÚ YÝ CĆ NŃ SŚ ZŹ"),
+    ("dead_circumflex", '^', '^', "aâ eê iî oô uû AÂ EÊ IÎ OÔ UÛ"),
+    ("dead_tilde", '~', '~', "aã nñ oõ AÃ NÑ OÕ"),
+    ("dead_diaeresis", '"', '¨', "aä eë iï oö uü yÿ AÄ EË IÏ OÖ UÜ"),
+    ("dead_cedilla", '¸', '¸', "cç CÇ"),
+    ("dead_abovering", '°', '°', "aå uů AÅ UŮ"),
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ComposeTable {
+    sequences: HashMap<Vec<Keysym>, String>,
+    // Every proper prefix of a sequence
+    prefixes: HashSet<Vec<Keysym>>,
+}
+
+impl ComposeTable {
+    pub fn new() -> ComposeTable {
+        ComposeTable::default()
+    }
+
+    /// The common dead key sequences, without a `Compose` file.
+    pub fn dead_keys() -> ComposeTable {
+        let mut table = ComposeTable::new();
+        for &(name, spacing, doubled, pairs) in &DEAD_KEYS {
+            let dead = Keysym::named(name);
+            table.add(vec![dead.clone(), Keysym::Char(' ')], &spacing.to_string());
+            table.add(vec![dead.clone(), dead.clone()], &doubled.to_string());
+            for pair in pairs.split(' ') {
+                let mut chars = pair.chars();
+                if let (Some(base), Some(composed)) = (chars.next(), chars.next()) {
+                    table.add(vec![dead.clone(), Keysym::Char(base)], &composed.to_string());
+                }
+            }
+        }
+        table
+    }
+
+    /// Reads a `Compose` file. `include`s are followed, with `%H` for the
+    /// home directory; `%L` and `%S`, the system's own tables, are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ComposeTable, Error> {
+        let mut table = ComposeTable::new();
+        table.read_file(path.as_ref(), 0)?;
+        Ok(table)
+    }
+
+    pub fn add(&mut self, sequence: Vec<Keysym>, text: &str) {
+        for len in 1..sequence.len() {
+            self.prefixes.insert(sequence[..len].to_vec());
+        }
+        self.sequences.insert(sequence, text.to_string());
+    }
+
+    /// Adds the sequences of `other`, replacing those already here.
+    pub fn extend(&mut self, other: ComposeTable) {
+        self.sequences.extend(other.sequences);
+        self.prefixes.extend(other.prefixes);
+    }
+
+    pub fn get(&self, sequence: &[Keysym]) -> Option<&str> {
+        self.sequences.get(sequence).map(|s| s.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    fn read_file(&mut self, path: &Path, depth: usize) -> Result<(), Error> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(Error(format!("Compose includes nested too deep at {}", path.display())));
+        }
+        let text = fs::read_to_string(path).map_err(|e| Error(format!("{}: {}", path.display(), e)))?;
+        self.parse(&text, path.parent(), depth)
+    }
+
+    fn parse(&mut self, text: &str, dir: Option<&Path>, depth: usize) -> Result<(), Error> {
+        for (num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || Error(format!("malformed Compose line {}: '{}'", num + 1, line));
+            if let Some(rest) = line.strip_prefix("include") {
+                let file = quoted(rest.trim_start()).ok_or_else(malformed)?.0;
+                if file.contains("%L") || file.contains("%S") {
+                    continue;
+                }
+                let file = file.replace("%H", &env::var("HOME").unwrap_or_default()).replace("%%", "%");
+                let path = match dir {
+                    Some(dir) => dir.join(file),
+                    None if Path::new(&file).is_absolute() => Path::new(&file).to_path_buf(),
+                    None => return Err(Error(format!("cannot include '{}' without a Compose file", file))),
+                };
+                self.read_file(&path, depth + 1)?;
+                continue;
+            }
+            let colon = line.find(':').ok_or_else(malformed)?;
+            let sequence = match parse_sequence(&line[..colon]) {
+                Some(sequence) => sequence,
+                // Sequences with modifiers
+                None => continue,
+            };
+            let result = line[colon + 1..].trim_start();
+            let (text, rest) = match quoted(result) {
+                Some((text, rest)) => (Some(text), rest),
+                None => (None, result),
+            };
+            // The result keysym if there's no string
+            let keysym = rest.split_whitespace().next().filter(|word| !word.starts_with('#'));
+            let text = match (text, keysym.and_then(Keysym::from_name)) {
+                (Some(text), _) => text,
+                (None, Some(Keysym::Char(c))) => c.to_string(),
+                _ => return Err(malformed()),
+            };
+            self.add(sequence, &text);
+        }
+        Ok(())
+    }
+}
+
+// `<dead_acute> <a>` as keysyms; `None` if it has anything else
+fn parse_sequence(s: &str) -> Option<Vec<Keysym>> {
+    let mut sequence = Vec::new();
+    for word in s.split_whitespace() {
+        if !(word.starts_with('<') && word.ends_with('>')) || word.len() < 3 {
+            return None;
+        }
+        sequence.push(Keysym::from_name(&word[1..word.len() - 1])?);
+    }
+    if sequence.is_empty() {
+        return None;
+    }
+    Some(sequence)
+}
+
+// A leading quoted string, unescaped, and what follows it
+fn quoted(s: &str) -> Option<(String, &str)> {
+    if !s.starts_with('"') {
+        return None;
+    }
+    let mut text = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => text.push('\n'),
+                c => text.push(c),
+            },
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+impl FromStr for ComposeTable {
+    type Err = Error;
+
+    /// Parses a `Compose` file; only absolute `include`s can be followed.
+    fn from_str(s: &str) -> Result<ComposeTable, Error> {
+        let mut table = ComposeTable::new();
+        table.parse(s, None, 0)?;
+        Ok(table)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComposeStatus {
+    /// The symbol isn't part of a sequence.
+    Passthrough(Keysym),
+    /// The symbol started or continued a sequence.
+    Composing,
+    Composed(String),
+    /// The symbol doesn't continue the sequence, and both are dropped.
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Composer {
+    table: ComposeTable,
+    pending: Vec<Keysym>,
+}
+
+impl Composer {
+    pub fn new(table: ComposeTable) -> Composer {
+        Composer { table, pending: Vec::new() }
+    }
+
+    pub fn table(&self) -> &ComposeTable {
+        &self.table
+    }
+
+    pub fn is_composing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The symbols of the sequence under way.
+    pub fn pending(&self) -> &[Keysym] {
+        &self.pending
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Feeds the symbol of a key press.
+    pub fn feed(&mut self, sym: Keysym) -> ComposeStatus {
+        self.pending.push(sym);
+        if let Some(text) = self.table.get(&self.pending) {
+            let text = text.to_string();
+            self.pending.clear();
+            return ComposeStatus::Composed(text);
+        }
+        if self.table.prefixes.contains(&self.pending) {
+            return ComposeStatus::Composing;
+        }
+        let started = self.pending.len() > 1;
+        let sym = self.pending.pop();
+        self.pending.clear();
+        match sym {
+            Some(sym) if !started => ComposeStatus::Passthrough(sym),
+            _ => ComposeStatus::Cancelled,
+        }
+    }
+
+    /// Translates a key press with `keymap` and feeds it. Releases and
+    /// modifier and lock keys give `None`, so Shift can be used within a
+    /// sequence. So do repeats during a sequence and repeats of keys that
+    /// start one, so a dead key held a bit long doesn't compose with itself.
+    pub fn process(&mut self, keymap: &Keymap, ev: &KeyEvent) -> Option<ComposeStatus> {
+        let locks = [KeyCode::KEY_CAPSLOCK, KeyCode::KEY_NUMLOCK, KeyCode::KEY_SCROLLLOCK];
+        if Modifiers::is_modifier(ev.key) || locks.contains(&ev.key) {
+            return None;
+        }
+        let sym = keymap.translate(ev)?;
+        let starts = self.table.prefixes.contains(slice::from_ref(&sym));
+        if ev.state == KeyState::Repeated && (self.is_composing() || starts) {
+            return None;
+        }
+        Some(self.feed(sym))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keyboard::KeyboardState;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn dead_keys_test() {
+        let mut src = MockSource::new(DeviceDescription::new("keyboard"));
+        for &(key, val) in &[(KeyCode::KEY_LEFTBRACE, 1), (KeyCode::KEY_LEFTBRACE, 2), (KeyCode::KEY_LEFTBRACE, 0),
+                             (KeyCode::KEY_LEFTSHIFT, 1), (KeyCode::KEY_E, 1), (KeyCode::KEY_E, 0),
+                             (KeyCode::KEY_LEFTSHIFT, 0),
+                             (KeyCode::KEY_LEFTBRACE, 1), (KeyCode::KEY_SPACE, 1),
+                             (KeyCode::KEY_LEFTBRACE, 1), (KeyCode::KEY_T, 1), (KeyCode::KEY_T, 1),
+                             (KeyCode::KEY_T, 2)] {
+            src.key(key, val).sync();
+        }
+        let fr = Keymap::builtin("fr").unwrap();
+        let mut kbd = KeyboardState::new();
+        let mut composer = Composer::new(ComposeTable::dead_keys());
+        let mut out = Vec::new();
+        while let Some(ev) = kbd.next_key(&mut src).unwrap() {
+            out.extend(composer.process(&fr, &ev));
+        }
+        assert_eq!(vec![
+            ComposeStatus::Composing,
+            ComposeStatus::Composed("Ê".to_string()),
+            ComposeStatus::Composing,
+            ComposeStatus::Composed("^".to_string()),
+            ComposeStatus::Composing,
+            ComposeStatus::Cancelled,
+            ComposeStatus::Passthrough(Keysym::Char('t')),
+            ComposeStatus::Passthrough(Keysym::Char('t')),
+        ], out);
+        assert!(!composer.is_composing());
+    }
+
+    #[test]
+    fn compose_file_test() {
+        let table: ComposeTable = r#"
+            # Partly from en_US.UTF-8
+            include "%L"
+            <Multi_key> <o> <c> 		: "©"   copyright # COPYRIGHT SIGN
+            <Multi_key> <minus> <minus> <period> : "–" endash
+            <Multi_key> <minus> <minus> <minus> : "—" emdash
+            <Multi_key> <less> <3>	: "\"♥\""
+            <dead_acute> <a>	: aacute
+            ~Ctrl <Multi_key> <x>	: "ignored"
+        "#.parse().unwrap();
+        assert_eq!(5, table.len());
+        assert_eq!(Some("á"), table.get(&[Keysym::named("dead_acute"), Keysym::Char('a')]));
+
+        let mut composer = Composer::new(table);
+        let multi = || Keysym::named("Multi_key");
+        assert_eq!(ComposeStatus::Composing, composer.feed(multi()));
+        assert_eq!(ComposeStatus::Composing, composer.feed(Keysym::Char('-')));
+        assert_eq!(ComposeStatus::Composing, composer.feed(Keysym::Char('-')));
+        assert_eq!(3, composer.pending().len());
+        assert_eq!(ComposeStatus::Composed("—".to_string()), composer.feed(Keysym::Char('-')));
+        composer.feed(multi());
+        composer.feed(Keysym::Char('<'));
+        assert_eq!(ComposeStatus::Composed("\"♥\"".to_string()), composer.feed(Keysym::Char('3')));
+        composer.feed(multi());
+        assert_eq!(ComposeStatus::Cancelled, composer.feed(Keysym::Char('x')));
+        assert_eq!(ComposeStatus::Passthrough(Keysym::Char('x')), composer.feed(Keysym::Char('x')));
+
+        assert!("<a> <b> \"ab\"".parse::<ComposeTable>().is_err());
+        assert!("include \"relative\"".parse::<ComposeTable>().is_err());
+    }
+}
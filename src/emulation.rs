@@ -0,0 +1,298 @@
+//! Single-pointer emulation for consumers that only understand `ABS_X`,
+//! `ABS_Y` and `BTN_TOUCH`.
+//!
+//! Follows the kernel's `input_mt_report_pointer_emulation`: the oldest
+//! contact drives the pointer, and the number of contacts is reported with
+//! `BTN_TOOL_FINGER`, `BTN_TOOL_DOUBLETAP` and so on. To export the result,
+//! create a `UinputDevice` from `DeviceDescription::from_source` of a
+//! `PointerEmulationSource` and write its events there.
+
+use std::collections::{HashMap, VecDeque};
+
+use codes::*;
+use source::{AbsInfo, EventSource};
+use touch::{ContactState, TouchFrame, TouchTracker};
+use {EvdevEvent, Error};
+
+const FINGER_TOOLS: [KeyCode; 5] = [
+    KeyCode::BTN_TOOL_FINGER,
+    KeyCode::BTN_TOOL_DOUBLETAP,
+    KeyCode::BTN_TOOL_TRIPLETAP,
+    KeyCode::BTN_TOOL_QUADTAP,
+    KeyCode::BTN_TOOL_QUINTTAP,
+];
+
+/// Turns touch frames into single-pointer events.
+#[derive(Clone, Debug)]
+pub struct PointerEmulation {
+    // Contacts on the surface, oldest first
+    order: Vec<i32>,
+    finger_count: bool,
+    pressure: bool,
+    // Values last reported; like the kernel, repeated values are not sent
+    last: HashMap<EvdevCode, i32>,
+}
+
+impl Default for PointerEmulation {
+    fn default() -> Self {
+        PointerEmulation::new()
+    }
+}
+
+impl PointerEmulation {
+    pub fn new() -> PointerEmulation {
+        PointerEmulation {
+            order: Vec::new(),
+            finger_count: true,
+            pressure: false,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Reports `ABS_PRESSURE` only if the source has `ABS_MT_PRESSURE`, as
+    /// the kernel does.
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> PointerEmulation {
+        PointerEmulation::new()
+            .with_pressure(src.has_event_code(EvdevCode::AbsCode(AbsCode::ABS_MT_PRESSURE)))
+    }
+
+    /// Whether to report the `BTN_TOOL_*` finger count (the kernel's
+    /// `use_count`). On by default.
+    pub fn with_finger_count(mut self, finger_count: bool) -> Self {
+        self.finger_count = finger_count;
+        self
+    }
+
+    pub fn with_pressure(mut self, pressure: bool) -> Self {
+        self.pressure = pressure;
+        self
+    }
+
+    /// The emulated events for one frame, ending in `SYN_REPORT`; empty if
+    /// nothing the pointer reports changed.
+    pub fn process(&mut self, frame: &TouchFrame) -> Vec<EvdevEvent> {
+        for c in &frame.contacts {
+            if c.state == ContactState::Up {
+                self.order.retain(|&id| id != c.tracking_id);
+            } else if !self.order.contains(&c.tracking_id) {
+                self.order.push(c.tracking_id);
+            }
+        }
+        let count = self.order.len();
+        let oldest = self.order.first().and_then(|&id| frame.contact(id));
+
+        let mut values = vec![(EvdevCode::KeyCode(KeyCode::BTN_TOUCH), (count > 0) as i32)];
+        if self.finger_count {
+            for (n, &tool) in FINGER_TOOLS.iter().enumerate() {
+                values.push((EvdevCode::KeyCode(tool), (count == n + 1) as i32));
+            }
+        }
+        match oldest {
+            Some(c) => {
+                values.push((EvdevCode::AbsCode(AbsCode::ABS_X), c.x));
+                values.push((EvdevCode::AbsCode(AbsCode::ABS_Y), c.y));
+                if self.pressure {
+                    values.push((EvdevCode::AbsCode(AbsCode::ABS_PRESSURE), c.pressure));
+                }
+            }
+            None if self.pressure => values.push((EvdevCode::AbsCode(AbsCode::ABS_PRESSURE), 0)),
+            None => {}
+        }
+
+        let mut out = Vec::new();
+        for (code, val) in values {
+            // Keys start out released, axes have no value yet
+            let initial = if let EvdevCode::KeyCode(_) = code { Some(0) } else { None };
+            let prev = self.last.get(&code).cloned().or(initial);
+            if prev != Some(val) {
+                self.last.insert(code, val);
+                out.push(EvdevEvent::new(frame.time, code, val));
+            }
+        }
+        if !out.is_empty() {
+            out.push(EvdevEvent::new(frame.time, EvdevCode::SynCode(SynCode::SYN_REPORT), 0));
+        }
+        out
+    }
+}
+
+fn is_emulated(code: EvdevCode) -> bool {
+    match code {
+        EvdevCode::KeyCode(KeyCode::BTN_TOUCH)
+        | EvdevCode::AbsCode(AbsCode::ABS_X)
+        | EvdevCode::AbsCode(AbsCode::ABS_Y)
+        | EvdevCode::AbsCode(AbsCode::ABS_PRESSURE) => true,
+        EvdevCode::KeyCode(key) => FINGER_TOOLS.contains(&key),
+        _ => false,
+    }
+}
+
+fn is_multitouch(code: EvdevCode) -> bool {
+    match code {
+        EvdevCode::AbsCode(abs) => abs as u16 >= AbsCode::ABS_MT_SLOT as u16,
+        EvdevCode::SynCode(SynCode::SYN_MT_REPORT) => true,
+        _ => false,
+    }
+}
+
+/// A multitouch source seen as a single-touch one. Events unrelated to
+/// touch pass through unchanged.
+pub struct PointerEmulationSource<S> {
+    src: S,
+    tracker: TouchTracker,
+    emulation: PointerEmulation,
+    queue: VecDeque<EvdevEvent>,
+    // Passed through events waiting for a SYN_REPORT
+    unsynced: bool,
+}
+
+impl<S: EventSource> PointerEmulationSource<S> {
+    pub fn new(src: S) -> PointerEmulationSource<S> {
+        let emulation = PointerEmulation::for_source(&src);
+        PointerEmulationSource::with_emulation(src, emulation)
+    }
+
+    pub fn with_emulation(src: S, emulation: PointerEmulation) -> PointerEmulationSource<S> {
+        PointerEmulationSource {
+            src,
+            tracker: TouchTracker::new(),
+            emulation,
+            queue: VecDeque::new(),
+            unsynced: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.src
+    }
+
+    fn slots(&self) -> usize {
+        self.src.abs_info(AbsCode::ABS_MT_SLOT).map_or(1, |info| (info.maximum + 1).max(1) as usize)
+    }
+}
+
+impl<S: EventSource> EventSource for PointerEmulationSource<S> {
+    fn name(&self) -> &str {
+        self.src.name()
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        while self.queue.is_empty() {
+            let ev = match self.src.read_event()? {
+                Some(ev) => ev,
+                None => return Ok(None),
+            };
+            if let Some(frame) = self.tracker.process(&ev) {
+                let emulated = self.emulation.process(&frame);
+                self.unsynced &= emulated.is_empty();
+                self.queue.extend(emulated);
+            }
+            if ev.is_syn_report() {
+                if self.unsynced {
+                    self.queue.push_back(ev);
+                    self.unsynced = false;
+                }
+            } else if !is_emulated(ev.ev.code) && !is_multitouch(ev.ev.code) {
+                self.queue.push_back(ev);
+                self.unsynced = true;
+            }
+        }
+        Ok(self.queue.pop_front())
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        ty == TypeCode::EV_KEY || ty == TypeCode::EV_ABS || self.src.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        match code {
+            EvdevCode::KeyCode(KeyCode::BTN_TOUCH)
+            | EvdevCode::AbsCode(AbsCode::ABS_X)
+            | EvdevCode::AbsCode(AbsCode::ABS_Y) => true,
+            EvdevCode::AbsCode(AbsCode::ABS_PRESSURE) => self.emulation.pressure,
+            EvdevCode::KeyCode(key) if FINGER_TOOLS.contains(&key) => {
+                self.emulation.finger_count && FINGER_TOOLS.iter().position(|&k| k == key).unwrap() < self.slots()
+            }
+            code if is_multitouch(code) => false,
+            code => self.src.has_event_code(code),
+        }
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.src.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        let mt = |mt_code, code| self.src.abs_info(mt_code).or_else(|| self.src.abs_info(code));
+        match code {
+            AbsCode::ABS_X => mt(AbsCode::ABS_MT_POSITION_X, code),
+            AbsCode::ABS_Y => mt(AbsCode::ABS_MT_POSITION_Y, code),
+            AbsCode::ABS_PRESSURE if self.emulation.pressure => mt(AbsCode::ABS_MT_PRESSURE, code),
+            AbsCode::ABS_PRESSURE => None,
+            code if is_multitouch(EvdevCode::AbsCode(code)) => None,
+            code => self.src.abs_info(code),
+        }
+    }
+
+    fn raw_fd(&self) -> Option<::std::os::unix::io::RawFd> {
+        self.src.raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    fn events(src: &mut PointerEmulationSource<MockSource>) -> Vec<(EvdevCode, i32)> {
+        let mut out = Vec::new();
+        while let Some(ev) = src.read_event().unwrap() {
+            out.push((ev.ev.code, ev.ev.val));
+        }
+        out
+    }
+
+    #[test]
+    fn oldest_contact_test() {
+        let desc = DeviceDescription::new("touch")
+            .with_abs(AbsCode::ABS_MT_SLOT, AbsInfo { maximum: 9, ..Default::default() })
+            .with_abs(AbsCode::ABS_MT_POSITION_X, AbsInfo { maximum: 1023, ..Default::default() })
+            .with_abs(AbsCode::ABS_MT_POSITION_Y, AbsInfo { maximum: 767, ..Default::default() })
+            .with_code(EvdevCode::KeyCode(KeyCode::KEY_POWER));
+        let mut src = MockSource::new(desc);
+        src.abs(AbsCode::ABS_MT_SLOT, 1)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, 7)
+            .abs(AbsCode::ABS_MT_POSITION_X, 10)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 20)
+            .sync()
+            .abs(AbsCode::ABS_MT_SLOT, 0)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, 8)
+            .abs(AbsCode::ABS_MT_POSITION_X, 500)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 600)
+            .sync()
+            .key(KeyCode::KEY_POWER, 1)
+            .sync()
+            .abs(AbsCode::ABS_MT_SLOT, 1)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, -1)
+            .sync();
+
+        let mut src = PointerEmulationSource::new(src);
+        assert!(src.has_event_code(EvdevCode::KeyCode(KeyCode::BTN_TOOL_QUINTTAP)));
+        assert!(!src.has_event_code(EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT)));
+        assert_eq!(767, src.abs_info(AbsCode::ABS_Y).unwrap().maximum);
+
+        let key = |k| EvdevCode::KeyCode(k);
+        let abs = |a| EvdevCode::AbsCode(a);
+        let syn = EvdevCode::SynCode(SynCode::SYN_REPORT);
+        assert_eq!(vec![
+            (key(KeyCode::BTN_TOUCH), 1), (key(KeyCode::BTN_TOOL_FINGER), 1),
+            (abs(AbsCode::ABS_X), 10), (abs(AbsCode::ABS_Y), 20), (syn, 0),
+            // The first finger stays in charge
+            (key(KeyCode::BTN_TOOL_FINGER), 0), (key(KeyCode::BTN_TOOL_DOUBLETAP), 1), (syn, 0),
+            (key(KeyCode::KEY_POWER), 1), (syn, 0),
+            (key(KeyCode::BTN_TOOL_FINGER), 1), (key(KeyCode::BTN_TOOL_DOUBLETAP), 0),
+            (abs(AbsCode::ABS_X), 500), (abs(AbsCode::ABS_Y), 600), (syn, 0),
+        ], events(&mut src));
+    }
+}
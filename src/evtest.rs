@@ -0,0 +1,290 @@
+//! The text format printed by `evtest`, both ways.
+//!
+//! `EvdevEvent`'s `Display` produces evtest's event lines, and `EvtestSource`
+//! turns a pasted evtest log (header included) back into an event stream.
+
+use num::FromPrimitive;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use codes::*;
+use source::{AbsInfo, DeviceDescription, EventSource};
+use {EvdevEvent, Error, TimeVal};
+
+fn type_name(ty: u16) -> String {
+    match TypeCode::from_u16(ty) {
+        Some(t) => t.to_string(),
+        None => "?".to_string(),
+    }
+}
+
+// evtest prints raw scancodes in hex
+fn is_hex_value(code: &EvdevCode) -> bool {
+    matches!(*code, EvdevCode::MscCode(MscCode::MSC_RAW) | EvdevCode::MscCode(MscCode::MSC_SCAN))
+}
+
+impl fmt::Display for TimeVal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:06}", self.sec, self.usec)
+    }
+}
+
+impl fmt::Display for EvdevEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Event: time {}, ", self.time)?;
+        match self.ev.code {
+            EvdevCode::SynCode(SynCode::SYN_MT_REPORT) => write!(f, "++++++++++++++ SYN_MT_REPORT ++++++++++++"),
+            EvdevCode::SynCode(SynCode::SYN_DROPPED) => write!(f, ">>>>>>>>>>>>>> SYN_DROPPED <<<<<<<<<<<<"),
+            EvdevCode::SynCode(c) => write!(f, "-------------- {} ------------", c),
+            ref code => {
+                let (ty, num) = code.to_raw();
+                write!(f, "type {} ({}), code {} ({}), ", ty, type_name(ty), num, code)?;
+                if is_hex_value(code) {
+                    write!(f, "value {:02x}", self.ev.val)
+                } else {
+                    write!(f, "value {}", self.ev.val)
+                }
+            }
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<TimeVal, Error> {
+    let mut parts = s.splitn(2, '.');
+    let sec = parts.next().unwrap_or("").parse()?;
+    let usec = match parts.next() {
+        // Pad or cut to microseconds, "12.5" means 500000us
+        Some(frac) => format!("{:0<6.6}", frac).parse()?,
+        None => 0,
+    };
+    Ok(TimeVal::new(sec, usec))
+}
+
+/// The word following `key` in `s`, e.g. `field("type 3 (EV_ABS)", "type ")` is `3`.
+fn field<'a>(s: &'a str, key: &str) -> Option<&'a str> {
+    let start = s.find(key)? + key.len();
+    s[start..].split_whitespace().next().map(|v| v.trim_end_matches(','))
+}
+
+/// Parses one evtest event line. Lines that are not events (headers, blank
+/// lines, "Testing ...") give `Ok(None)`; any text before `Event:` is ignored
+/// so prefixed log lines work too.
+pub fn parse_event_line(line: &str) -> Result<Option<EvdevEvent>, Error> {
+    let rest = match line.find("Event: time ") {
+        Some(pos) => &line[pos + "Event: time ".len()..],
+        None => return Ok(None),
+    };
+    let comma = rest.find(',').ok_or_else(|| Error::from(format!("malformed event line: {}", line)))?;
+    let time = parse_time(rest[..comma].trim())?;
+    let body = rest[comma + 1..].trim();
+
+    if body.starts_with("--") || body.starts_with("++") || body.starts_with(">>") {
+        let name = body.trim_matches(|c: char| c == '-' || c == '+' || c == '<' || c == '>' || c == ' ');
+        let syn: SynCode = name.parse()?;
+        return Ok(Some(EvdevEvent::new(time, EvdevCode::SynCode(syn), 0)));
+    }
+
+    let missing = || Error::from(format!("malformed event line: {}", line));
+    let ty: u16 = field(body, "type ").ok_or_else(missing)?.parse()?;
+    let num: u16 = field(body, "code ").ok_or_else(missing)?.parse()?;
+    let code = EvdevCode::from((ty, num));
+    let value = field(body, "value ").ok_or_else(missing)?;
+    let val = if is_hex_value(&code) {
+        u32::from_str_radix(value, 16)? as i32
+    } else {
+        value.parse()?
+    };
+    Ok(Some(EvdevEvent::new(time, code, val)))
+}
+
+/// Parses the "Input device name"/"Supported events"/"Properties" header of an
+/// evtest log. Unrecognised lines are skipped.
+#[derive(Default)]
+struct HeaderParser {
+    desc: DeviceDescription,
+    ty: Option<u16>,
+    abs: Option<AbsCode>,
+}
+
+impl HeaderParser {
+    fn line(&mut self, line: &str) -> Result<(), Error> {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("Input device name: ") {
+            self.desc.name = name.trim_matches('"').to_string();
+        } else if line.starts_with("Event type ") {
+            self.ty = Some(field(line, "Event type ").unwrap_or("").parse()?);
+            self.abs = None;
+        } else if line.starts_with("Event code ") {
+            let num: u16 = field(line, "Event code ").unwrap_or("").parse()?;
+            self.abs = None;
+            if let Some(code) = self.ty.and_then(|ty| EvdevCode::from_raw(ty, num)) {
+                self.desc.codes.insert(code);
+                if let EvdevCode::AbsCode(abs) = code {
+                    self.desc.abs.insert(abs, AbsInfo::default());
+                    self.abs = Some(abs);
+                }
+            }
+        } else if line.starts_with("Property type ") {
+            let num: u16 = field(line, "Property type ").unwrap_or("").parse()?;
+            if let Some(prop) = PropCode::from_u16(num) {
+                self.desc.props.insert(prop);
+            }
+        } else if let Some(abs) = self.abs {
+            let mut words = line.split_whitespace();
+            let (key, val) = match (words.next(), words.next()) {
+                (Some(key), Some(val)) => (key, val),
+                _ => return Ok(()),
+            };
+            let val = match val.parse() {
+                Ok(val) => val,
+                Err(_) => return Ok(()),
+            };
+            let info = self.desc.abs.get_mut(&abs).unwrap();
+            match key {
+                "Value" => info.value = val,
+                "Min" => info.minimum = val,
+                "Max" => info.maximum = val,
+                "Fuzz" => info.fuzz = val,
+                "Flat" => info.flat = val,
+                "Resolution" => info.resolution = val,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replays an evtest log. Capabilities come from the log's header if it has
+/// one; otherwise the description is empty.
+pub struct EvtestSource<R> {
+    lines: R,
+    desc: DeviceDescription,
+    pending: Option<EvdevEvent>,
+}
+
+impl<R: BufRead> EvtestSource<R> {
+    /// Reads the header up to the first event line.
+    pub fn new(mut lines: R) -> Result<EvtestSource<R>, Error> {
+        let mut header = HeaderParser::default();
+        let mut line = String::new();
+        let pending = loop {
+            line.clear();
+            if lines.read_line(&mut line)? == 0 {
+                break None;
+            }
+            if let Some(ev) = parse_event_line(&line)? {
+                break Some(ev);
+            }
+            header.line(&line)?;
+        };
+        Ok(EvtestSource { lines, desc: header.desc, pending })
+    }
+
+    pub fn description(&self) -> &DeviceDescription {
+        &self.desc
+    }
+}
+
+impl<R: Read> EvtestSource<BufReader<R>> {
+    pub fn from_reader(reader: R) -> Result<Self, Error> {
+        EvtestSource::new(BufReader::new(reader))
+    }
+}
+
+impl<R: BufRead> EventSource for EvtestSource<R> {
+    fn name(&self) -> &str {
+        &self.desc.name
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        if let Some(ev) = self.pending.take() {
+            return Ok(Some(ev));
+        }
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.lines.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if let Some(ev) = parse_event_line(&line)? {
+                return Ok(Some(ev));
+            }
+        }
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.desc.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        self.desc.has_event_code(code)
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.desc.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        self.desc.abs_info(code)
+    }
+}
+
+/// Parses all event lines of an evtest log.
+pub fn parse_log(log: &str) -> Result<Vec<EvdevEvent>, Error> {
+    let mut events = Vec::new();
+    for line in log.lines() {
+        if let Some(ev) = parse_event_line(line)? {
+            events.push(ev);
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOG: &str = "\
+Input driver version is 1.0.1
+Input device ID: bus 0x0 vendor 0x0 product 0x0 version 0x0
+Input device name: \"FT5406 memory based driver\"
+Supported events:
+  Event type 0 (EV_SYN)
+  Event type 1 (EV_KEY)
+    Event code 330 (BTN_TOUCH)
+  Event type 3 (EV_ABS)
+    Event code 53 (ABS_MT_POSITION_X)
+      Value      0
+      Min        0
+      Max      800
+Properties:
+  Property type 1 (INPUT_PROP_DIRECT)
+Testing ... (interrupt to exit)
+Event: time 1500000000.012345, type 3 (EV_ABS), code 53 (ABS_MT_POSITION_X), value 412
+Event: time 1500000000.012345, type 4 (EV_MSC), code 4 (MSC_SCAN), value 7001e
+Event: time 1500000000.012345, -------------- SYN_REPORT ------------
+";
+
+    #[test]
+    fn display_test() {
+        let ev = EvdevEvent::new(TimeVal::new(12, 345), EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT), 0);
+        assert_eq!("Event: time 12.000345, type 3 (EV_ABS), code 47 (ABS_MT_SLOT), value 0", ev.to_string());
+        let ev = EvdevEvent::new(TimeVal::new(12, 345), EvdevCode::SynCode(SynCode::SYN_REPORT), 0);
+        assert_eq!("Event: time 12.000345, -------------- SYN_REPORT ------------", ev.to_string());
+    }
+
+    #[test]
+    fn parse_log_test() {
+        let mut src = EvtestSource::from_reader(LOG.as_bytes()).unwrap();
+        assert_eq!("FT5406 memory based driver", src.name());
+        assert!(src.has_property(PropCode::INPUT_PROP_DIRECT));
+        assert!(src.has_event_code(EvdevCode::KeyCode(KeyCode::BTN_TOUCH)));
+        assert_eq!(800, src.abs_info(AbsCode::ABS_MT_POSITION_X).unwrap().maximum);
+
+        let mut lines = LOG.lines().skip(15);
+        while let Some(ev) = src.read_event().unwrap() {
+            assert_eq!(lines.next().unwrap(), ev.to_string());
+        }
+        assert_eq!(None, lines.next());
+    }
+}
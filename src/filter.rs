@@ -0,0 +1,575 @@
+//! Filters for noisy touch panels: hysteresis, smoothing, debouncing and
+//! pressure thresholds.
+//!
+//! Filters work on `TouchFrame`s and can be chained with `FilterChain`.
+//! Like the gesture recognizers they only look at frame timestamps, so a
+//! recorded sequence always filters the same way.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use source::{AbsInfo, EventSource};
+use touch::{Contact, ContactState, TouchFrame, TouchTracker};
+use {Error, TimeVal};
+
+/// One stage of a filter chain.
+pub trait TouchFilter {
+    /// Filters one frame. Contacts may be changed, held back or dropped.
+    fn filter(&mut self, frame: TouchFrame) -> TouchFrame;
+
+    /// Contacts held back that are due at `now`, for filters that delay.
+    fn tick(&mut self, _now: TimeVal) -> Vec<Contact> {
+        Vec::new()
+    }
+}
+
+/// Filters frames through a list of stages, in order.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn TouchFilter>>,
+    tracker: TouchTracker,
+}
+
+impl FilterChain {
+    pub fn new() -> FilterChain {
+        FilterChain::default()
+    }
+
+    pub fn with<F: TouchFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    fn run(&mut self, from: usize, mut frame: TouchFrame) -> Option<TouchFrame> {
+        for filter in &mut self.filters[from..] {
+            frame = filter.filter(frame);
+        }
+        if frame.contacts.is_empty() {
+            None
+        } else {
+            Some(frame)
+        }
+    }
+
+    /// The filtered frame, or `None` if every contact was held back or dropped.
+    pub fn process(&mut self, frame: TouchFrame) -> Option<TouchFrame> {
+        self.run(0, frame)
+    }
+
+    /// Releases contacts that delaying stages held back until `now`. Call
+    /// this periodically, since held contacts may not see another frame.
+    pub fn tick(&mut self, now: TimeVal) -> Option<TouchFrame> {
+        let mut out: Option<TouchFrame> = None;
+        for i in 0..self.filters.len() {
+            let due = self.filters[i].tick(now);
+            if due.is_empty() {
+                continue;
+            }
+            if let Some(frame) = self.run(i + 1, TouchFrame { time: now, contacts: due }) {
+                match out {
+                    Some(ref mut out) => out.contacts.extend(frame.contacts),
+                    None => out = Some(frame),
+                }
+            }
+        }
+        out
+    }
+
+    /// Reads from `src` until the next frame that makes it through the chain.
+    pub fn next_frame<S: EventSource>(&mut self, src: &mut S) -> Result<Option<TouchFrame>, Error> {
+        while let Some(frame) = self.tracker.next_frame(src)? {
+            let time = frame.time;
+            let mut filtered = self.tick(time);
+            if let Some(frame) = self.process(frame) {
+                match filtered {
+                    Some(ref mut out) => out.contacts.extend(frame.contacts),
+                    None => filtered = Some(frame),
+                }
+            }
+            if filtered.is_some() {
+                return Ok(filtered);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Ignores movements within `margin` of where a contact rests, the way
+/// libinput dejitters touchpads. The rest position follows the contact once
+/// it moves further.
+pub struct Hysteresis {
+    margin: (i32, i32),
+    centers: HashMap<i32, (i32, i32)>,
+}
+
+fn hysteresis(center: i32, value: i32, margin: i32) -> i32 {
+    if value > center + margin {
+        value - margin
+    } else if value < center - margin {
+        value + margin
+    } else {
+        center
+    }
+}
+
+impl Hysteresis {
+    pub fn new(margin_x: i32, margin_y: i32) -> Hysteresis {
+        Hysteresis { margin: (margin_x, margin_y), centers: HashMap::new() }
+    }
+
+    /// Uses the axes' `fuzz` as margin.
+    pub fn from_abs(x: &AbsInfo, y: &AbsInfo) -> Hysteresis {
+        Hysteresis::new(x.fuzz, y.fuzz)
+    }
+}
+
+impl TouchFilter for Hysteresis {
+    fn filter(&mut self, mut frame: TouchFrame) -> TouchFrame {
+        for c in &mut frame.contacts {
+            let center = match (c.state, self.centers.get(&c.tracking_id)) {
+                (ContactState::Down, _) | (_, None) => (c.x, c.y),
+                (_, Some(&(cx, cy))) => (hysteresis(cx, c.x, self.margin.0), hysteresis(cy, c.y, self.margin.1)),
+            };
+            c.x = center.0;
+            c.y = center.1;
+            if c.state == ContactState::Up {
+                self.centers.remove(&c.tracking_id);
+            } else {
+                self.centers.insert(c.tracking_id, center);
+            }
+        }
+        frame
+    }
+}
+
+/// Averages each contact's last `window` positions.
+pub struct MovingAverage {
+    window: usize,
+    history: HashMap<i32, Vec<(i32, i32)>>,
+}
+
+impl MovingAverage {
+    pub fn new(window: usize) -> MovingAverage {
+        MovingAverage { window: window.max(1), history: HashMap::new() }
+    }
+}
+
+impl TouchFilter for MovingAverage {
+    fn filter(&mut self, mut frame: TouchFrame) -> TouchFrame {
+        for c in &mut frame.contacts {
+            if c.state == ContactState::Down {
+                self.history.remove(&c.tracking_id);
+            }
+            let (x, y) = {
+                let history = self.history.entry(c.tracking_id).or_default();
+                history.push((c.x, c.y));
+                if history.len() > self.window {
+                    history.remove(0);
+                }
+                let n = history.len() as f64;
+                let (sx, sy) = history.iter().fold((0.0, 0.0), |acc, &(x, y)| (acc.0 + f64::from(x), acc.1 + f64::from(y)));
+                (sx / n, sy / n)
+            };
+            c.x = x.round() as i32;
+            c.y = y.round() as i32;
+            if c.state == ContactState::Up {
+                self.history.remove(&c.tracking_id);
+            }
+        }
+        frame
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LowPass {
+    value: f64,
+    deriv: f64,
+}
+
+/// The one-euro filter (Casiez et al.): smooths heavily while a contact is
+/// slow and follows closely when it moves fast. `min_cutoff` is in Hz, `beta`
+/// scales the cutoff with speed in units per second.
+pub struct OneEuro {
+    min_cutoff: f64,
+    beta: f64,
+    d_cutoff: f64,
+    state: HashMap<i32, (TimeVal, LowPass, LowPass)>,
+}
+
+fn alpha(cutoff: f64, dt: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+impl OneEuro {
+    pub fn new(min_cutoff: f64, beta: f64) -> OneEuro {
+        OneEuro { min_cutoff, beta, d_cutoff: 1.0, state: HashMap::new() }
+    }
+
+    /// Cutoff for the speed estimate, 1 Hz by default.
+    pub fn with_derivative_cutoff(mut self, d_cutoff: f64) -> Self {
+        self.d_cutoff = d_cutoff;
+        self
+    }
+
+    fn axis(&self, lp: &mut LowPass, value: f64, dt: f64) -> f64 {
+        let deriv = (value - lp.value) / dt;
+        lp.deriv += alpha(self.d_cutoff, dt) * (deriv - lp.deriv);
+        let cutoff = self.min_cutoff + self.beta * lp.deriv.abs();
+        lp.value += alpha(cutoff, dt) * (value - lp.value);
+        lp.value
+    }
+}
+
+impl TouchFilter for OneEuro {
+    fn filter(&mut self, mut frame: TouchFrame) -> TouchFrame {
+        for c in &mut frame.contacts {
+            let (x, y) = (f64::from(c.x), f64::from(c.y));
+            let state = match self.state.get(&c.tracking_id) {
+                Some(&(last, mut fx, mut fy)) if c.state != ContactState::Down => {
+                    let dt = frame.time.micros_since(last) as f64 / 1e6;
+                    if dt > 0.0 {
+                        c.x = self.axis(&mut fx, x, dt).round() as i32;
+                        c.y = self.axis(&mut fy, y, dt).round() as i32;
+                    } else {
+                        c.x = fx.value.round() as i32;
+                        c.y = fy.value.round() as i32;
+                    }
+                    (frame.time, fx, fy)
+                }
+                _ => (frame.time, LowPass { value: x, deriv: 0.0 }, LowPass { value: y, deriv: 0.0 }),
+            };
+            if c.state == ContactState::Up {
+                self.state.remove(&c.tracking_id);
+            } else {
+                self.state.insert(c.tracking_id, state);
+            }
+        }
+        frame
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Bounce {
+    /// Down, but not for long enough to be reported. `contact` is its
+    /// latest position.
+    Pending { since: TimeVal, contact: Contact },
+    Reported,
+    /// Lifted at `since`; the lift is reported unless a new contact takes
+    /// over before `release_time` passes.
+    Releasing { since: TimeVal, contact: Contact },
+}
+
+/// Drops contacts shorter than `min_duration` and bridges lifts shorter than
+/// `release_time`, hiding the phantom up/down pairs of resistive panels.
+///
+/// A contact is reported once it lasted `min_duration`, so its `Down` comes
+/// that much late. A contact that goes down within `release_time` of another
+/// one lifting, and within the resume radius of where it lifted, continues
+/// the lifted contact under its tracking ID.
+pub struct Debounce {
+    min_duration: Duration,
+    release_time: Duration,
+    resume_radius: i32,
+    contacts: HashMap<i32, Bounce>,
+    // New tracking ID to the ID of the contact it continues
+    renamed: HashMap<i32, i32>,
+}
+
+impl Debounce {
+    pub fn new(min_duration: Duration, release_time: Duration) -> Debounce {
+        Debounce {
+            min_duration,
+            release_time,
+            resume_radius: i32::MAX,
+            contacts: HashMap::new(),
+            renamed: HashMap::new(),
+        }
+    }
+
+    /// How far from a lifted contact, in device units, a new one may go down
+    /// and still continue it. Any distance by default.
+    pub fn with_resume_radius(mut self, radius: i32) -> Self {
+        self.resume_radius = radius;
+        self
+    }
+
+    /// The lifted contact nearest to `c`, if one is within the resume radius.
+    /// Ties go to the lowest tracking ID.
+    fn resumed(&self, c: &Contact) -> Option<i32> {
+        let radius = i64::from(self.resume_radius);
+        self.contacts.iter()
+            .filter_map(|(&id, b)| match *b {
+                Bounce::Releasing { contact, .. } => {
+                    let (dx, dy) = (i64::from(contact.x - c.x), i64::from(contact.y - c.y));
+                    Some((dx * dx + dy * dy, id))
+                }
+                _ => None,
+            })
+            .filter(|&(dist, _)| dist <= radius * radius)
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    fn release(&mut self, now: TimeVal, c: Contact, out: &mut Vec<Contact>) {
+        if self.release_time == Duration::from_secs(0) {
+            self.contacts.remove(&c.tracking_id);
+            out.push(c);
+        } else {
+            self.contacts.insert(c.tracking_id, Bounce::Releasing { since: now, contact: c });
+        }
+    }
+
+    /// Lifts whose release time passed at `now`.
+    fn released(&mut self, now: TimeVal) -> Vec<Contact> {
+        let mut due: Vec<(i32, Contact)> = self.contacts.iter()
+            .filter_map(|(&id, b)| match *b {
+                Bounce::Releasing { since, contact } if now >= since.plus(self.release_time) =>
+                    Some((id, contact)),
+                _ => None,
+            })
+            .collect();
+        due.sort_by_key(|&(id, _)| id);
+        for &(id, _) in &due {
+            self.contacts.remove(&id);
+        }
+        due.into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// Contacts that lasted `min_duration` by `now`, even if they didn't move.
+    fn promoted(&mut self, now: TimeVal) -> Vec<Contact> {
+        let mut due: Vec<(i32, Contact)> = self.contacts.iter()
+            .filter_map(|(&id, b)| match *b {
+                Bounce::Pending { since, contact } if now >= since.plus(self.min_duration) =>
+                    Some((id, Contact { state: ContactState::Down, ..contact })),
+                _ => None,
+            })
+            .collect();
+        due.sort_by_key(|&(id, _)| id);
+        for &(id, _) in &due {
+            self.contacts.insert(id, Bounce::Reported);
+        }
+        due.into_iter().map(|(_, c)| c).collect()
+    }
+}
+
+impl TouchFilter for Debounce {
+    fn filter(&mut self, frame: TouchFrame) -> TouchFrame {
+        let mut out = self.released(frame.time);
+        for mut c in frame.contacts {
+            if c.state == ContactState::Down {
+                if let Some(old) = self.resumed(&c) {
+                    self.renamed.insert(c.tracking_id, old);
+                    self.contacts.insert(old, Bounce::Reported);
+                }
+            }
+            let new_id = c.tracking_id;
+            if let Some(&old) = self.renamed.get(&new_id) {
+                c.tracking_id = old;
+                if c.state == ContactState::Down {
+                    c.state = ContactState::Move;
+                }
+            }
+            let state = self.contacts.get(&c.tracking_id).cloned()
+                .unwrap_or(Bounce::Pending { since: frame.time, contact: c });
+            match (state, c.state) {
+                (Bounce::Pending { since, .. }, ContactState::Up) => {
+                    if frame.time >= since.plus(self.min_duration) {
+                        // A long tap that never moved
+                        out.push(Contact { state: ContactState::Down, ..c });
+                        self.release(frame.time, c, &mut out);
+                    } else {
+                        self.contacts.remove(&c.tracking_id);
+                    }
+                }
+                (Bounce::Pending { since, .. }, _) => {
+                    if frame.time >= since.plus(self.min_duration) {
+                        c.state = ContactState::Down;
+                        self.contacts.insert(c.tracking_id, Bounce::Reported);
+                        out.push(c);
+                    } else {
+                        self.contacts.insert(c.tracking_id, Bounce::Pending { since, contact: c });
+                    }
+                }
+                (Bounce::Reported, ContactState::Up) => {
+                    self.renamed.remove(&new_id);
+                    self.release(frame.time, c, &mut out);
+                }
+                (Bounce::Reported, _) => out.push(c),
+                (Bounce::Releasing { .. }, _) => {}
+            }
+        }
+        // Contacts held still, which this frame didn't mention
+        out.extend(self.promoted(frame.time));
+        TouchFrame { time: frame.time, contacts: out }
+    }
+
+    fn tick(&mut self, now: TimeVal) -> Vec<Contact> {
+        let mut out = self.released(now);
+        out.extend(self.promoted(now));
+        out
+    }
+}
+
+/// Only counts a contact as touching while its pressure is high enough:
+/// from `press` up until it falls below `release`.
+pub struct PressureThreshold {
+    press: i32,
+    release: i32,
+    touching: HashMap<i32, bool>,
+}
+
+impl PressureThreshold {
+    pub fn new(press: i32, release: i32) -> PressureThreshold {
+        PressureThreshold { press, release: release.min(press), touching: HashMap::new() }
+    }
+}
+
+impl TouchFilter for PressureThreshold {
+    fn filter(&mut self, mut frame: TouchFrame) -> TouchFrame {
+        {
+            // Lifts of contacts that never pressed hard enough are not reported
+            let touching = &mut self.touching;
+            frame.contacts.retain(|c| {
+                if c.state == ContactState::Up && touching.get(&c.tracking_id) != Some(&true) {
+                    touching.remove(&c.tracking_id);
+                    return false;
+                }
+                true
+            });
+        }
+        for c in &mut frame.contacts {
+            let was = self.touching.get(&c.tracking_id).cloned().unwrap_or(false);
+            let is = c.state != ContactState::Up
+                && if was { c.pressure >= self.release } else { c.pressure >= self.press };
+            c.state = match (was, is) {
+                (false, true) => ContactState::Down,
+                (true, false) => ContactState::Up,
+                _ => c.state,
+            };
+            if c.state == ContactState::Up {
+                self.touching.remove(&c.tracking_id);
+            } else {
+                self.touching.insert(c.tracking_id, is);
+            }
+        }
+        let touching = &self.touching;
+        frame.contacts.retain(|c| c.state == ContactState::Up || touching.get(&c.tracking_id) == Some(&true));
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(ms: i64, contacts: Vec<Contact>) -> TouchFrame {
+        TouchFrame { time: TimeVal::from_micros(ms * 1000), contacts }
+    }
+
+    fn at(id: i32, state: ContactState, x: i32) -> Contact {
+        Contact { state, ..Contact::new(0, id, x, 0) }
+    }
+
+    fn xs(frame: &TouchFrame) -> Vec<i32> {
+        frame.contacts.iter().map(|c| c.x).collect()
+    }
+
+    #[test]
+    fn smoothing_test() {
+        let mut hyst = Hysteresis::new(4, 4);
+        let out: Vec<_> = [(ContactState::Down, 100), (ContactState::Move, 103), (ContactState::Move, 97),
+                           (ContactState::Move, 110), (ContactState::Move, 108)].iter()
+            .map(|&(state, x)| xs(&hyst.filter(frame(0, vec![at(1, state, x)])))[0])
+            .collect();
+        assert_eq!(vec![100, 100, 100, 106, 106], out);
+
+        let mut avg = MovingAverage::new(2);
+        assert_eq!(vec![10], xs(&avg.filter(frame(0, vec![at(1, ContactState::Down, 10)]))));
+        assert_eq!(vec![15], xs(&avg.filter(frame(10, vec![at(1, ContactState::Move, 20)]))));
+        assert_eq!(vec![25], xs(&avg.filter(frame(20, vec![at(1, ContactState::Move, 30)]))));
+
+        // Jitter around 100 is damped, a fast move is followed closely
+        let mut euro = OneEuro::new(1.0, 0.05);
+        euro.filter(frame(0, vec![at(1, ContactState::Down, 100)]));
+        for (i, &x) in [104, 96, 104, 96].iter().enumerate() {
+            let out = xs(&euro.filter(frame(10 * (i as i64 + 1), vec![at(1, ContactState::Move, x)])))[0];
+            assert!((out - 100).abs() <= 2, "{} not damped", out);
+        }
+        let mut out = 0;
+        for i in 0..10 {
+            out = xs(&euro.filter(frame(50 + 10 * i, vec![at(1, ContactState::Move, 1000)])))[0];
+        }
+        assert!(out > 950, "{} lags behind", out);
+    }
+
+    #[test]
+    fn debounce_test() {
+        let mut chain = FilterChain::new()
+            .with(Debounce::new(Duration::from_millis(20), Duration::from_millis(30)));
+        // Too short
+        assert_eq!(None, chain.process(frame(0, vec![at(1, ContactState::Down, 5)])));
+        assert_eq!(None, chain.process(frame(10, vec![at(1, ContactState::Up, 5)])));
+
+        assert_eq!(None, chain.process(frame(100, vec![at(2, ContactState::Down, 5)])));
+        let out = chain.process(frame(120, vec![at(2, ContactState::Move, 6)])).unwrap();
+        assert_eq!(vec![at(2, ContactState::Down, 6)], out.contacts);
+        // A phantom lift, bridged
+        assert_eq!(None, chain.process(frame(130, vec![at(2, ContactState::Up, 6)])));
+        let out = chain.process(frame(140, vec![at(3, ContactState::Down, 7)])).unwrap();
+        assert_eq!(vec![at(2, ContactState::Move, 7)], out.contacts);
+        // A real one, reported once the release time passed
+        assert_eq!(None, chain.process(frame(150, vec![at(3, ContactState::Up, 7)])));
+        assert_eq!(None, chain.tick(TimeVal::from_micros(170_000)));
+        let out = chain.tick(TimeVal::from_micros(180_000)).unwrap();
+        assert_eq!(vec![at(2, ContactState::Up, 7)], out.contacts);
+    }
+
+    #[test]
+    fn debounce_hold_test() {
+        let mut chain = FilterChain::new()
+            .with(Debounce::new(Duration::from_millis(20), Duration::from_secs(0)));
+        // Held still: no more frames, reported by a tick
+        assert_eq!(None, chain.process(frame(0, vec![at(1, ContactState::Down, 5)])));
+        assert_eq!(None, chain.tick(TimeVal::from_micros(10_000)));
+        let out = chain.tick(TimeVal::from_micros(20_000)).unwrap();
+        assert_eq!(vec![at(1, ContactState::Down, 5)], out.contacts);
+        let out = chain.process(frame(30, vec![at(1, ContactState::Up, 5)])).unwrap();
+        assert_eq!(vec![at(1, ContactState::Up, 5)], out.contacts);
+
+        // A long tap with no moves and no ticks
+        assert_eq!(None, chain.process(frame(100, vec![at(2, ContactState::Down, 8)])));
+        let out = chain.process(frame(150, vec![at(2, ContactState::Up, 8)])).unwrap();
+        assert_eq!(vec![at(2, ContactState::Down, 8), at(2, ContactState::Up, 8)], out.contacts);
+    }
+
+    #[test]
+    fn debounce_resume_test() {
+        let mut chain = FilterChain::new()
+            .with(Debounce::new(Duration::from_millis(0), Duration::from_millis(30)).with_resume_radius(10));
+        chain.process(frame(0, vec![at(1, ContactState::Down, 100), at(2, ContactState::Down, 200),
+                                    at(3, ContactState::Down, 210)]));
+        assert_eq!(None, chain.process(frame(10, vec![at(1, ContactState::Up, 100), at(2, ContactState::Up, 200),
+                                                      at(3, ContactState::Up, 210)])));
+        // Nearest first, then the lower ID of two as near; too far is a new contact
+        let out = chain.process(frame(20, vec![at(4, ContactState::Down, 208), at(5, ContactState::Down, 205),
+                                               at(6, ContactState::Down, 150)])).unwrap();
+        assert_eq!(vec![at(3, ContactState::Move, 208), at(2, ContactState::Move, 205),
+                        at(6, ContactState::Down, 150)], out.contacts);
+        let out = chain.tick(TimeVal::from_micros(40_000)).unwrap();
+        assert_eq!(vec![at(1, ContactState::Up, 100)], out.contacts);
+    }
+
+    #[test]
+    fn pressure_test() {
+        let mut filter = PressureThreshold::new(50, 30);
+        let press = |state, pressure| Contact { pressure, ..at(1, state, 0) };
+        let states: Vec<_> = [(ContactState::Down, 20), (ContactState::Move, 60), (ContactState::Move, 40),
+                              (ContactState::Move, 20), (ContactState::Move, 40), (ContactState::Up, 60)].iter()
+            .map(|&(state, p)| filter.filter(frame(0, vec![press(state, p)])).contacts.first().map(|c| c.state))
+            .collect();
+        assert_eq!(vec![None, Some(ContactState::Down), Some(ContactState::Move), Some(ContactState::Up),
+                        None, None], states);
+    }
+}
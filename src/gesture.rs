@@ -0,0 +1,835 @@
+//! Tap, long press, drag, swipe, pinch and rotate recognition, plus edge
+//! swipes and multi-finger touchpad gestures.
+//!
+//! The recognizer only looks at the timestamps of the frames it is fed, so a
+//! recorded sequence always gives the same gestures. Distances are configured
+//! in millimetres and converted with the axis resolution from `AbsInfo`.
+
+use std::time::Duration;
+
+use source::{position_axes, AbsInfo, EventSource};
+use touch::{ContactState, TouchFrame};
+use {Error, TimeVal};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Phase {
+    Begin,
+    Update,
+    End,
+}
+
+/// Direction of a swipe on the screen; `Up` is towards smaller y.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// The dominant direction of a movement.
+    pub fn of(dx: f64, dy: f64) -> Direction {
+        if dx.abs() >= dy.abs() {
+            if dx < 0.0 { Direction::Left } else { Direction::Right }
+        } else if dy < 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// Positions are in device units, like `Contact`; velocities in mm/s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Gesture {
+    Tap { pos: (f64, f64) },
+    /// Replaces the `Tap` of the second tap; the first one is still reported.
+    DoubleTap { pos: (f64, f64) },
+    LongPress { pos: (f64, f64) },
+    /// `delta` is the movement since the previous drag event.
+    Drag { phase: Phase, pos: (f64, f64), delta: (f64, f64) },
+    /// Follows the `Drag` end when the finger left fast enough.
+    Swipe { direction: Direction, velocity: f64, pos: (f64, f64) },
+    /// `scale` is the finger distance relative to when the pinch started.
+    Pinch { phase: Phase, scale: f64, center: (f64, f64) },
+    /// `angle` is in degrees, clockwise on the screen, since the rotation started.
+    Rotate { phase: Phase, angle: f64, center: (f64, f64) },
+    /// A one-finger swipe that started at a border of the surface. `delta` is
+    /// the movement since the previous event; `cancelled` is set on the end
+    /// when another finger interrupted it.
+    EdgeSwipe { phase: Phase, edge: Edge, pos: (f64, f64), delta: (f64, f64), cancelled: bool },
+    /// Three or more fingers moving together, like libinput's swipe gestures.
+    /// `delta` is the movement of the fingers' centroid.
+    FingerSwipe { phase: Phase, fingers: usize, delta: (f64, f64), cancelled: bool },
+    /// Three or more fingers resting on the surface; ends cancelled when they
+    /// start to move or the finger count changes.
+    FingerHold { phase: Phase, fingers: usize, cancelled: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GestureConfig {
+    /// How far a finger may wander and still tap or long press.
+    pub tap_distance_mm: f64,
+    pub tap_time: Duration,
+    /// Maximum time and distance between two taps of a double tap.
+    pub double_tap_time: Duration,
+    pub double_tap_distance_mm: f64,
+    pub long_press_time: Duration,
+    /// Minimum release velocity for a drag to count as a swipe.
+    pub swipe_velocity: f64,
+    /// Window the release velocity is measured over.
+    pub velocity_window: Duration,
+    /// Change in finger distance before a pinch starts.
+    pub pinch_distance_mm: f64,
+    /// Change in finger angle before a rotation starts.
+    pub rotate_degrees: f64,
+    /// How close to a border an edge swipe must start.
+    pub edge_mm: f64,
+    /// How far an edge swipe must move inwards before it begins.
+    pub edge_swipe_distance_mm: f64,
+    /// How long resting fingers take to become a hold.
+    pub hold_time: Duration,
+    /// How far the fingers' centroid must move before a multi-finger swipe begins.
+    pub finger_swipe_distance_mm: f64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            tap_distance_mm: 3.0,
+            tap_time: Duration::from_millis(250),
+            double_tap_time: Duration::from_millis(300),
+            double_tap_distance_mm: 10.0,
+            long_press_time: Duration::from_millis(500),
+            swipe_velocity: 150.0,
+            velocity_window: Duration::from_millis(80),
+            pinch_distance_mm: 5.0,
+            rotate_degrees: 10.0,
+            edge_mm: 5.0,
+            edge_swipe_distance_mm: 5.0,
+            hold_time: Duration::from_millis(180),
+            finger_swipe_distance_mm: 5.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct OneFinger {
+    id: i32,
+    start_time: TimeVal,
+    start: (f64, f64),
+    last: (f64, f64),
+    dragging: bool,
+    long_pressed: bool,
+    // Recent positions for the release velocity
+    history: Vec<(TimeVal, (f64, f64))>,
+}
+
+#[derive(Clone, Debug)]
+struct TwoFinger {
+    ids: (i32, i32),
+    start_dist: f64,
+    // Raw angle of the previous frame and the angle accumulated since the start
+    prev_angle: f64,
+    angle: f64,
+    scale: f64,
+    center: (f64, f64),
+    pinching: bool,
+    rotating: bool,
+}
+
+#[derive(Clone, Debug)]
+enum State {
+    Idle,
+    One(OneFinger),
+    Two(TwoFinger),
+    /// A gesture ended but fingers are still down; wait for all to lift.
+    Done,
+}
+
+fn midpoint(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+}
+
+// Into (-180, 180]
+fn wrap_degrees(a: f64) -> f64 {
+    let a = a % 360.0;
+    if a > 180.0 {
+        a - 360.0
+    } else if a <= -180.0 {
+        a + 360.0
+    } else {
+        a
+    }
+}
+
+/// The position axes and the contacts currently on them, shared by the
+/// recognizers.
+#[derive(Clone, Debug)]
+struct Surface {
+    x: AbsInfo,
+    y: AbsInfo,
+    // Device units per millimetre
+    res: (f64, f64),
+    // Oldest first
+    contacts: Vec<(i32, (f64, f64))>,
+}
+
+impl Surface {
+    fn new(x: AbsInfo, y: AbsInfo) -> Surface {
+        Surface { x, y, res: (x.units_per_mm(), y.units_per_mm()), contacts: Vec::new() }
+    }
+
+    fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<Surface, Error> {
+        let (x, y) = position_axes(src)?;
+        Ok(Surface::new(x, y))
+    }
+
+    fn update(&mut self, frame: &TouchFrame) {
+        for c in &frame.contacts {
+            let pos = (f64::from(c.x), f64::from(c.y));
+            if c.state == ContactState::Up {
+                self.contacts.retain(|&(id, _)| id != c.tracking_id);
+            } else if let Some(entry) = self.contacts.iter_mut().find(|e| e.0 == c.tracking_id) {
+                entry.1 = pos;
+            } else {
+                self.contacts.push((c.tracking_id, pos));
+            }
+        }
+    }
+
+    /// Distance between two device positions in millimetres.
+    fn distance_mm(&self, a: (f64, f64), b: (f64, f64)) -> f64 {
+        let (dx, dy) = self.to_mm(b.0 - a.0, b.1 - a.1);
+        dx.hypot(dy)
+    }
+
+    fn to_mm(&self, dx: f64, dy: f64) -> (f64, f64) {
+        (dx / self.res.0, dy / self.res.1)
+    }
+
+    fn angle(&self, a: (f64, f64), b: (f64, f64)) -> f64 {
+        let (dx, dy) = self.to_mm(b.0 - a.0, b.1 - a.1);
+        dy.atan2(dx).to_degrees()
+    }
+
+    fn position(&self, id: i32) -> Option<(f64, f64)> {
+        self.contacts.iter().find(|&&(c, _)| c == id).map(|&(_, pos)| pos)
+    }
+
+    fn centroid(&self) -> (f64, f64) {
+        let n = self.contacts.len().max(1) as f64;
+        let (x, y) = self.contacts.iter().fold((0.0, 0.0), |acc, &(_, p)| (acc.0 + p.0, acc.1 + p.1));
+        (x / n, y / n)
+    }
+
+    /// The border `pos` is within `mm` of, if any; the nearest one if several.
+    fn edge_at(&self, pos: (f64, f64), mm: f64) -> Option<Edge> {
+        let edges = [
+            (Edge::Left, (pos.0 - f64::from(self.x.minimum)) / self.res.0),
+            (Edge::Right, (f64::from(self.x.maximum) - pos.0) / self.res.0),
+            (Edge::Top, (pos.1 - f64::from(self.y.minimum)) / self.res.1),
+            (Edge::Bottom, (f64::from(self.y.maximum) - pos.1) / self.res.1),
+        ];
+        edges.iter()
+            .filter(|&&(_, dist)| dist <= mm)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|&(edge, _)| edge)
+    }
+}
+
+/// Turns `TouchFrame`s into `Gesture`s.
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    surface: Surface,
+    state: State,
+    last_tap: Option<(TimeVal, (f64, f64))>,
+}
+
+impl GestureRecognizer {
+    /// Axes without a resolution count one device unit as a millimetre; use
+    /// `with_resolution` for such devices.
+    pub fn new(x: AbsInfo, y: AbsInfo) -> GestureRecognizer {
+        GestureRecognizer::with_surface(Surface::new(x, y))
+    }
+
+    /// Uses the multitouch position axes if the source has them, the
+    /// single-touch ones otherwise.
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<GestureRecognizer, Error> {
+        Surface::for_source(src).map(GestureRecognizer::with_surface)
+    }
+
+    fn with_surface(surface: Surface) -> GestureRecognizer {
+        GestureRecognizer {
+            config: GestureConfig::default(),
+            surface,
+            state: State::Idle,
+            last_tap: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: GestureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Overrides the axis resolution, in device units per millimetre.
+    pub fn with_resolution(mut self, x: f64, y: f64) -> Self {
+        self.surface.res = (x, y);
+        self
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Reports timer based gestures (long press) due at `now`. Call this
+    /// periodically, since a finger held still produces no frames.
+    pub fn tick(&mut self, now: TimeVal) -> Vec<Gesture> {
+        match self.state {
+            State::One(ref mut f) if !f.dragging && !f.long_pressed
+                && now >= f.start_time.plus(self.config.long_press_time) => {
+                f.long_pressed = true;
+                vec![Gesture::LongPress { pos: f.last }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Feeds one frame; returns the gestures it completed or advanced.
+    pub fn process(&mut self, frame: &TouchFrame) -> Vec<Gesture> {
+        let mut out = self.tick(frame.time);
+        self.surface.update(frame);
+
+        self.state = match ::std::mem::replace(&mut self.state, State::Idle) {
+            State::Idle => self.start(frame.time),
+            State::One(f) => self.update_one(f, frame.time, &mut out),
+            State::Two(f) => self.update_two(f, &mut out),
+            State::Done if self.surface.contacts.is_empty() => State::Idle,
+            State::Done => State::Done,
+        };
+        out
+    }
+
+    fn start(&self, time: TimeVal) -> State {
+        match self.surface.contacts.len() {
+            0 => State::Idle,
+            1 => {
+                let (id, pos) = self.surface.contacts[0];
+                State::One(OneFinger {
+                    id,
+                    start_time: time,
+                    start: pos,
+                    last: pos,
+                    dragging: false,
+                    long_pressed: false,
+                    history: vec![(time, pos)],
+                })
+            }
+            _ => {
+                let (a, b) = (self.surface.contacts[0], self.surface.contacts[1]);
+                let angle = self.surface.angle(a.1, b.1);
+                State::Two(TwoFinger {
+                    ids: (a.0, b.0),
+                    start_dist: self.surface.distance_mm(a.1, b.1).max(f64::EPSILON),
+                    prev_angle: angle,
+                    angle: 0.0,
+                    scale: 1.0,
+                    center: midpoint(a.1, b.1),
+                    pinching: false,
+                    rotating: false,
+                })
+            }
+        }
+    }
+
+    fn update_one(&mut self, mut f: OneFinger, time: TimeVal, out: &mut Vec<Gesture>) -> State {
+        if self.surface.contacts.len() == 1 && self.surface.contacts[0].0 == f.id {
+            let pos = self.surface.contacts[0].1;
+            if !f.dragging && self.surface.distance_mm(f.start, pos) > self.config.tap_distance_mm {
+                f.dragging = true;
+                out.push(Gesture::Drag { phase: Phase::Begin, pos, delta: (pos.0 - f.start.0, pos.1 - f.start.1) });
+            } else if f.dragging && pos != f.last {
+                out.push(Gesture::Drag { phase: Phase::Update, pos, delta: (pos.0 - f.last.0, pos.1 - f.last.1) });
+            }
+            f.last = pos;
+            f.history.push((time, pos));
+            while f.history.len() > 2 && time >= f.history[1].0.plus(self.config.velocity_window) {
+                f.history.remove(0);
+            }
+            return State::One(f);
+        }
+
+        if f.dragging {
+            out.push(Gesture::Drag { phase: Phase::End, pos: f.last, delta: (0.0, 0.0) });
+        }
+        if !self.surface.contacts.is_empty() {
+            // More fingers joined, or the finger was replaced
+            return if self.surface.contacts.len() >= 2 && !f.dragging { self.start(time) } else { State::Done };
+        }
+
+        if f.dragging {
+            let (t0, p0) = f.history[0];
+            let secs = time.micros_since(t0) as f64 / 1e6;
+            let (dx, dy) = self.surface.to_mm(f.last.0 - p0.0, f.last.1 - p0.1);
+            let velocity = if secs > 0.0 { dx.hypot(dy) / secs } else { 0.0 };
+            if velocity >= self.config.swipe_velocity {
+                out.push(Gesture::Swipe { direction: Direction::of(dx, dy), velocity, pos: f.last });
+            }
+        } else if !f.long_pressed && time <= f.start_time.plus(self.config.tap_time) {
+            let double = match self.last_tap {
+                Some((t, p)) => time <= t.plus(self.config.double_tap_time)
+                    && self.surface.distance_mm(p, f.last) <= self.config.double_tap_distance_mm,
+                None => false,
+            };
+            if double {
+                self.last_tap = None;
+                out.push(Gesture::DoubleTap { pos: f.last });
+            } else {
+                self.last_tap = Some((time, f.last));
+                out.push(Gesture::Tap { pos: f.last });
+            }
+        }
+        State::Idle
+    }
+
+    fn update_two(&mut self, mut f: TwoFinger, out: &mut Vec<Gesture>) -> State {
+        let (a, b) = match (self.surface.position(f.ids.0), self.surface.position(f.ids.1)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                if f.pinching {
+                    out.push(Gesture::Pinch { phase: Phase::End, scale: f.scale, center: f.center });
+                }
+                if f.rotating {
+                    out.push(Gesture::Rotate { phase: Phase::End, angle: f.angle, center: f.center });
+                }
+                return if self.surface.contacts.is_empty() { State::Idle } else { State::Done };
+            }
+        };
+
+        let dist = self.surface.distance_mm(a, b);
+        let raw_angle = self.surface.angle(a, b);
+        f.angle += wrap_degrees(raw_angle - f.prev_angle);
+        f.prev_angle = raw_angle;
+        f.scale = dist / f.start_dist;
+        f.center = midpoint(a, b);
+
+        if f.pinching {
+            out.push(Gesture::Pinch { phase: Phase::Update, scale: f.scale, center: f.center });
+        } else if (dist - f.start_dist).abs() > self.config.pinch_distance_mm {
+            f.pinching = true;
+            out.push(Gesture::Pinch { phase: Phase::Begin, scale: f.scale, center: f.center });
+        }
+        if f.rotating {
+            out.push(Gesture::Rotate { phase: Phase::Update, angle: f.angle, center: f.center });
+        } else if f.angle.abs() > self.config.rotate_degrees {
+            f.rotating = true;
+            out.push(Gesture::Rotate { phase: Phase::Begin, angle: f.angle, center: f.center });
+        }
+        State::Two(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum EdgeState {
+    Idle,
+    Candidate { id: i32, edge: Edge, start: (f64, f64) },
+    Swiping { id: i32, edge: Edge, last: (f64, f64) },
+    /// Not an edge swipe; wait for all fingers to lift.
+    Ignore,
+}
+
+/// Recognizes swipes that start at a border of the surface, e.g. to pull in a
+/// menu on a kiosk screen.
+pub struct EdgeSwipeRecognizer {
+    config: GestureConfig,
+    surface: Surface,
+    state: EdgeState,
+}
+
+impl EdgeSwipeRecognizer {
+    pub fn new(x: AbsInfo, y: AbsInfo) -> EdgeSwipeRecognizer {
+        EdgeSwipeRecognizer::with_surface(Surface::new(x, y))
+    }
+
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<EdgeSwipeRecognizer, Error> {
+        Surface::for_source(src).map(EdgeSwipeRecognizer::with_surface)
+    }
+
+    fn with_surface(surface: Surface) -> EdgeSwipeRecognizer {
+        EdgeSwipeRecognizer { config: GestureConfig::default(), surface, state: EdgeState::Idle }
+    }
+
+    pub fn with_config(mut self, config: GestureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Overrides the axis resolution, in device units per millimetre.
+    pub fn with_resolution(mut self, x: f64, y: f64) -> Self {
+        self.surface.res = (x, y);
+        self
+    }
+
+    pub fn process(&mut self, frame: &TouchFrame) -> Vec<Gesture> {
+        self.surface.update(frame);
+        let mut out = Vec::new();
+        let contacts = &self.surface.contacts;
+        let single = if contacts.len() == 1 { Some(contacts[0]) } else { None };
+        self.state = match self.state {
+            EdgeState::Idle => match single {
+                Some((id, pos)) => match self.surface.edge_at(pos, self.config.edge_mm) {
+                    Some(edge) => EdgeState::Candidate { id, edge, start: pos },
+                    None => EdgeState::Ignore,
+                },
+                None if contacts.is_empty() => EdgeState::Idle,
+                None => EdgeState::Ignore,
+            },
+            EdgeState::Candidate { id, edge, start } => match single {
+                Some((c, pos)) if c == id => {
+                    let (dx, dy) = self.surface.to_mm(pos.0 - start.0, pos.1 - start.1);
+                    let inwards = match edge {
+                        Edge::Left => dx,
+                        Edge::Right => -dx,
+                        Edge::Top => dy,
+                        Edge::Bottom => -dy,
+                    };
+                    if inwards > self.config.edge_swipe_distance_mm {
+                        let delta = (pos.0 - start.0, pos.1 - start.1);
+                        out.push(Gesture::EdgeSwipe { phase: Phase::Begin, edge, pos, delta, cancelled: false });
+                        EdgeState::Swiping { id, edge, last: pos }
+                    } else {
+                        EdgeState::Candidate { id, edge, start }
+                    }
+                }
+                _ if contacts.is_empty() => EdgeState::Idle,
+                _ => EdgeState::Ignore,
+            },
+            EdgeState::Swiping { id, edge, last } => match single {
+                Some((c, pos)) if c == id => {
+                    if pos != last {
+                        let delta = (pos.0 - last.0, pos.1 - last.1);
+                        out.push(Gesture::EdgeSwipe { phase: Phase::Update, edge, pos, delta, cancelled: false });
+                    }
+                    EdgeState::Swiping { id, edge, last: pos }
+                }
+                _ => {
+                    let cancelled = !contacts.is_empty();
+                    out.push(Gesture::EdgeSwipe { phase: Phase::End, edge, pos: last, delta: (0.0, 0.0), cancelled });
+                    if cancelled { EdgeState::Ignore } else { EdgeState::Idle }
+                }
+            },
+            EdgeState::Ignore if contacts.is_empty() => EdgeState::Idle,
+            EdgeState::Ignore => EdgeState::Ignore,
+        };
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FingerState {
+    Idle,
+    Pending { fingers: usize, since: TimeVal, start: (f64, f64) },
+    Holding { fingers: usize, start: (f64, f64) },
+    Swiping { fingers: usize, last: (f64, f64) },
+}
+
+/// Recognizes swipes and holds of three or more fingers, mostly on touchpads.
+/// A change in the number of fingers ends the gesture and starts a new one.
+pub struct FingerGestureRecognizer {
+    config: GestureConfig,
+    surface: Surface,
+    state: FingerState,
+}
+
+impl FingerGestureRecognizer {
+    pub fn new(x: AbsInfo, y: AbsInfo) -> FingerGestureRecognizer {
+        FingerGestureRecognizer::with_surface(Surface::new(x, y))
+    }
+
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<FingerGestureRecognizer, Error> {
+        Surface::for_source(src).map(FingerGestureRecognizer::with_surface)
+    }
+
+    fn with_surface(surface: Surface) -> FingerGestureRecognizer {
+        FingerGestureRecognizer { config: GestureConfig::default(), surface, state: FingerState::Idle }
+    }
+
+    pub fn with_config(mut self, config: GestureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Overrides the axis resolution, in device units per millimetre.
+    pub fn with_resolution(mut self, x: f64, y: f64) -> Self {
+        self.surface.res = (x, y);
+        self
+    }
+
+    /// Begins a hold once the fingers rested for `hold_time`. Call this
+    /// periodically, since resting fingers produce no frames.
+    pub fn tick(&mut self, now: TimeVal) -> Vec<Gesture> {
+        match self.state {
+            FingerState::Pending { fingers, since, start }
+                if now >= since.plus(self.config.hold_time) => {
+                self.state = FingerState::Holding { fingers, start };
+                vec![Gesture::FingerHold { phase: Phase::Begin, fingers, cancelled: false }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, frame: &TouchFrame) -> Vec<Gesture> {
+        let mut out = self.tick(frame.time);
+        self.surface.update(frame);
+        let count = self.surface.contacts.len();
+        let pos = self.surface.centroid();
+
+        let current = match self.state {
+            FingerState::Idle => None,
+            FingerState::Pending { fingers, .. }
+            | FingerState::Holding { fingers, .. }
+            | FingerState::Swiping { fingers, .. } => Some(fingers),
+        };
+        if current != Some(count) {
+            // Lifting all fingers ends normally, anything else cancels
+            let cancelled = count != 0;
+            match self.state {
+                FingerState::Holding { fingers, .. } =>
+                    out.push(Gesture::FingerHold { phase: Phase::End, fingers, cancelled }),
+                FingerState::Swiping { fingers, .. } =>
+                    out.push(Gesture::FingerSwipe { phase: Phase::End, fingers, delta: (0.0, 0.0), cancelled }),
+                _ => {}
+            }
+            self.state = if count >= 3 {
+                FingerState::Pending { fingers: count, since: frame.time, start: pos }
+            } else {
+                FingerState::Idle
+            };
+            return out;
+        }
+
+        self.state = match self.state {
+            FingerState::Pending { fingers, start, .. } | FingerState::Holding { fingers, start }
+                if self.surface.distance_mm(start, pos) > self.config.finger_swipe_distance_mm => {
+                if let FingerState::Holding { .. } = self.state {
+                    out.push(Gesture::FingerHold { phase: Phase::End, fingers, cancelled: true });
+                }
+                let delta = (pos.0 - start.0, pos.1 - start.1);
+                out.push(Gesture::FingerSwipe { phase: Phase::Begin, fingers, delta, cancelled: false });
+                FingerState::Swiping { fingers, last: pos }
+            }
+            FingerState::Swiping { fingers, last } => {
+                if pos != last {
+                    let delta = (pos.0 - last.0, pos.1 - last.1);
+                    out.push(Gesture::FingerSwipe { phase: Phase::Update, fingers, delta, cancelled: false });
+                }
+                FingerState::Swiping { fingers, last: pos }
+            }
+            state => state,
+        };
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codes::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+    use touch::TouchTracker;
+
+    // 10 units per mm
+    fn source() -> MockSource {
+        let axis = AbsInfo { maximum: 2000, resolution: 10, ..Default::default() };
+        MockSource::new(DeviceDescription::new("touch")
+                        .with_abs(AbsCode::ABS_MT_POSITION_X, axis)
+                        .with_abs(AbsCode::ABS_MT_POSITION_Y, axis))
+    }
+
+    fn finger(src: &mut MockSource, slot: i32, id: i32, pos: Option<(i32, i32)>) {
+        src.abs(AbsCode::ABS_MT_SLOT, slot);
+        match pos {
+            Some((x, y)) => {
+                src.abs(AbsCode::ABS_MT_TRACKING_ID, id)
+                    .abs(AbsCode::ABS_MT_POSITION_X, x)
+                    .abs(AbsCode::ABS_MT_POSITION_Y, y);
+            }
+            None => {
+                src.abs(AbsCode::ABS_MT_TRACKING_ID, -1);
+            }
+        }
+    }
+
+    fn run(src: &mut MockSource) -> Vec<Gesture> {
+        let mut rec = GestureRecognizer::for_source(&*src).unwrap();
+        let mut tracker = TouchTracker::new();
+        let mut out = Vec::new();
+        while let Some(frame) = tracker.next_frame(src).unwrap() {
+            out.extend(rec.process(&frame));
+        }
+        out
+    }
+
+    #[test]
+    fn tap_test() {
+        let mut src = source();
+        finger(&mut src, 0, 1, Some((500, 500)));
+        src.sync().advance(80_000);
+        finger(&mut src, 0, 1, None);
+        src.sync().advance(100_000);
+        finger(&mut src, 0, 2, Some((510, 505)));
+        src.sync().advance(80_000);
+        finger(&mut src, 0, 2, None);
+        src.sync().advance(1_000_000);
+        finger(&mut src, 0, 3, Some((900, 900)));
+        src.sync().advance(600_000);
+        finger(&mut src, 0, 3, None);
+        src.sync();
+
+        let mut rec = GestureRecognizer::for_source(&src).unwrap();
+        let mut tracker = TouchTracker::new();
+        let mut out = Vec::new();
+        while let Some(frame) = tracker.next_frame(&mut src).unwrap() {
+            if frame.time == TimeVal::new(1, 360_000) {
+                // The finger went down at 0.760s and has not moved since
+                out.extend(rec.tick(TimeVal::new(1, 300_000)));
+            }
+            out.extend(rec.process(&frame));
+        }
+        assert_eq!(vec![
+            Gesture::Tap { pos: (500.0, 500.0) },
+            Gesture::DoubleTap { pos: (510.0, 505.0) },
+            Gesture::LongPress { pos: (900.0, 900.0) },
+        ], out);
+    }
+
+    #[test]
+    fn swipe_test() {
+        let mut src = source();
+        finger(&mut src, 0, 1, Some((100, 500)));
+        src.sync();
+        for i in 1..6 {
+            src.advance(10_000).abs(AbsCode::ABS_MT_POSITION_X, 100 + i * 50).sync();
+        }
+        finger(&mut src, 0, 1, None);
+        src.sync();
+
+        let out = run(&mut src);
+        assert_eq!(Gesture::Drag { phase: Phase::Begin, pos: (150.0, 500.0), delta: (50.0, 0.0) }, out[0]);
+        assert_eq!(Gesture::Drag { phase: Phase::End, pos: (350.0, 500.0), delta: (0.0, 0.0) }, out[5]);
+        match out[6] {
+            // 5mm per 10ms
+            Gesture::Swipe { direction: Direction::Right, velocity, .. } => assert!((velocity - 500.0).abs() < 1.0),
+            ref g => panic!("expected a swipe, got {:?}", g),
+        }
+        assert_eq!(7, out.len());
+    }
+
+    #[test]
+    fn pinch_rotate_test() {
+        let mut src = source();
+        finger(&mut src, 0, 1, Some((400, 500)));
+        finger(&mut src, 1, 2, Some((600, 500)));
+        src.sync().advance(10_000);
+        // Spread to twice the distance
+        src.abs(AbsCode::ABS_MT_SLOT, 0).abs(AbsCode::ABS_MT_POSITION_X, 300)
+            .abs(AbsCode::ABS_MT_SLOT, 1).abs(AbsCode::ABS_MT_POSITION_X, 700)
+            .sync().advance(10_000);
+        // Turn a quarter clockwise around the centre
+        src.abs(AbsCode::ABS_MT_SLOT, 0).abs(AbsCode::ABS_MT_POSITION_X, 500).abs(AbsCode::ABS_MT_POSITION_Y, 300)
+            .abs(AbsCode::ABS_MT_SLOT, 1).abs(AbsCode::ABS_MT_POSITION_X, 500).abs(AbsCode::ABS_MT_POSITION_Y, 700)
+            .sync().advance(10_000);
+        finger(&mut src, 0, 1, None);
+        src.sync().advance(10_000);
+        finger(&mut src, 1, 2, None);
+        src.sync();
+
+        let out = run(&mut src);
+        assert_eq!(vec![
+            Gesture::Pinch { phase: Phase::Begin, scale: 2.0, center: (500.0, 500.0) },
+            Gesture::Pinch { phase: Phase::Update, scale: 2.0, center: (500.0, 500.0) },
+            Gesture::Rotate { phase: Phase::Begin, angle: 90.0, center: (500.0, 500.0) },
+            Gesture::Pinch { phase: Phase::End, scale: 2.0, center: (500.0, 500.0) },
+            Gesture::Rotate { phase: Phase::End, angle: 90.0, center: (500.0, 500.0) },
+        ], out);
+    }
+
+    #[test]
+    fn edge_swipe_test() {
+        let mut src = source();
+        finger(&mut src, 0, 1, Some((2000, 800)));
+        src.sync();
+        for i in 1..4 {
+            src.advance(10_000).abs(AbsCode::ABS_MT_POSITION_X, 2000 - i * 40).sync();
+        }
+        finger(&mut src, 0, 1, None);
+        src.sync();
+        // Starts too far from the border
+        finger(&mut src, 0, 2, Some((1000, 800)));
+        src.sync().abs(AbsCode::ABS_MT_POSITION_X, 900).sync();
+
+        let mut rec = EdgeSwipeRecognizer::for_source(&src).unwrap();
+        let mut tracker = TouchTracker::new();
+        let mut out = Vec::new();
+        while let Some(frame) = tracker.next_frame(&mut src).unwrap() {
+            out.extend(rec.process(&frame));
+        }
+        assert_eq!(vec![
+            Gesture::EdgeSwipe { phase: Phase::Begin, edge: Edge::Right, pos: (1920.0, 800.0),
+                                 delta: (-80.0, 0.0), cancelled: false },
+            Gesture::EdgeSwipe { phase: Phase::Update, edge: Edge::Right, pos: (1880.0, 800.0),
+                                 delta: (-40.0, 0.0), cancelled: false },
+            Gesture::EdgeSwipe { phase: Phase::End, edge: Edge::Right, pos: (1880.0, 800.0),
+                                 delta: (0.0, 0.0), cancelled: false },
+        ], out);
+    }
+
+    #[test]
+    fn finger_gesture_test() {
+        let mut src = source();
+        for slot in 0..3 {
+            finger(&mut src, slot, slot + 1, Some((400 + slot * 100, 500)));
+        }
+        src.sync().advance(200_000);
+        // A fourth finger cancels the hold and starts over
+        finger(&mut src, 3, 4, Some((700, 500)));
+        src.sync().advance(10_000);
+        for slot in 0..4 {
+            src.abs(AbsCode::ABS_MT_SLOT, slot).abs(AbsCode::ABS_MT_POSITION_Y, 400);
+        }
+        src.sync();
+        for slot in 0..4 {
+            finger(&mut src, slot, slot + 1, None);
+        }
+        src.sync();
+
+        let mut rec = FingerGestureRecognizer::for_source(&src).unwrap();
+        let mut tracker = TouchTracker::new();
+        let mut out = Vec::new();
+        while let Some(frame) = tracker.next_frame(&mut src).unwrap() {
+            out.extend(rec.process(&frame));
+        }
+        assert_eq!(vec![
+            Gesture::FingerHold { phase: Phase::Begin, fingers: 3, cancelled: false },
+            Gesture::FingerHold { phase: Phase::End, fingers: 3, cancelled: true },
+            Gesture::FingerSwipe { phase: Phase::Begin, fingers: 4, delta: (0.0, -100.0), cancelled: false },
+            Gesture::FingerSwipe { phase: Phase::End, fingers: 4, delta: (0.0, 0.0), cancelled: false },
+        ], out);
+    }
+}
@@ -0,0 +1,177 @@
+//! JSON Lines event streams: one JSON value per line.
+//!
+//! A stream may start with a `DeviceDescription` line, which `JsonlSource`
+//! uses to answer capability queries.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use codes::*;
+use source::{AbsInfo, DeviceDescription, EventSource};
+use {EvdevEvent, Error};
+
+pub struct JsonlWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W) -> JsonlWriter<W> {
+        JsonlWriter { writer }
+    }
+
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub struct JsonlReader<R> {
+    reader: R,
+    line: String,
+}
+
+impl<R: BufRead> JsonlReader<R> {
+    pub fn new(reader: R) -> JsonlReader<R> {
+        JsonlReader {
+            reader,
+            line: String::new(),
+        }
+    }
+
+    /// Next non-blank line as raw text, without parsing it.
+    fn next_line(&mut self) -> Result<Option<&str>, Error> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if !self.line.trim().is_empty() {
+                return Ok(Some(self.line.trim()));
+            }
+        }
+    }
+
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        match self.next_line()? {
+            Some(line) => Ok(Some(serde_json::from_str(line)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Replays a JSON Lines event stream, optionally headed by a device description.
+pub struct JsonlSource<R> {
+    reader: JsonlReader<R>,
+    desc: DeviceDescription,
+    pending: Option<EvdevEvent>,
+}
+
+impl<R: BufRead> JsonlSource<R> {
+    pub fn new(reader: R) -> Result<JsonlSource<R>, Error> {
+        let mut reader = JsonlReader::new(reader);
+        let (desc, pending) = match reader.next_line()? {
+            Some(line) => match serde_json::from_str::<EvdevEvent>(line) {
+                Ok(ev) => (DeviceDescription::default(), Some(ev)),
+                Err(_) => (serde_json::from_str(line)?, None),
+            },
+            None => (DeviceDescription::default(), None),
+        };
+        Ok(JsonlSource { reader, desc, pending })
+    }
+
+    pub fn description(&self) -> &DeviceDescription {
+        &self.desc
+    }
+}
+
+impl JsonlSource<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        JsonlSource::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> JsonlSource<BufReader<R>> {
+    pub fn from_reader(reader: R) -> Result<Self, Error> {
+        JsonlSource::new(BufReader::new(reader))
+    }
+}
+
+impl<R: BufRead> EventSource for JsonlSource<R> {
+    fn name(&self) -> &str {
+        &self.desc.name
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        if let Some(ev) = self.pending.take() {
+            return Ok(Some(ev));
+        }
+        self.reader.read()
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.desc.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        self.desc.has_event_code(code)
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.desc.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        self.desc.abs_info(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use TimeVal;
+
+    #[test]
+    fn symbolic_names_test() {
+        let ev = EvdevEvent::new(TimeVal::new(1, 2), EvdevCode::KeyCode(KeyCode::KEY_A), 1);
+        assert_eq!(r#"{"time":{"sec":1,"usec":2},"code":"KEY_A","val":1}"#,
+                   serde_json::to_string(&ev).unwrap());
+        let undefined: EvdevCode = serde_json::from_str("[31,7]").unwrap();
+        assert_eq!(EvdevCode::Undefined(31, 7), undefined);
+    }
+
+    #[test]
+    fn stream_roundtrip_test() {
+        let desc = DeviceDescription::new("pad")
+            .with_abs(AbsCode::ABS_X, AbsInfo { maximum: 1023, ..Default::default() });
+        let events = vec![
+            EvdevEvent::new(TimeVal::new(5, 0), EvdevCode::AbsCode(AbsCode::ABS_X), 512),
+            EvdevEvent::new(TimeVal::new(5, 0), EvdevCode::SynCode(SynCode::SYN_REPORT), 0),
+        ];
+
+        let mut writer = JsonlWriter::new(Vec::new());
+        writer.write(&desc).unwrap();
+        for ev in &events {
+            writer.write(ev).unwrap();
+        }
+        let buf = writer.into_inner();
+
+        let mut src = JsonlSource::from_reader(&buf[..]).unwrap();
+        assert_eq!(&desc, src.description());
+        assert_eq!(Some(events[0].clone()), src.read_event().unwrap());
+        assert_eq!(Some(events[1].clone()), src.read_event().unwrap());
+        assert_eq!(None, src.read_event().unwrap());
+    }
+}
@@ -0,0 +1,344 @@
+//! Keyboard state: held keys, modifiers, locks and autorepeat.
+//!
+//! Key events carry 0 for a release, 1 for a press and 2 for the kernel's
+//! autorepeat. `KeyboardState` keeps track of what is held and turns each
+//! key event into a `KeyEvent` with the modifier and lock state that
+//! applies to it. One state can serve several keyboards, lighting the lock
+//! LEDs of all of them.
+
+use std::collections::BTreeSet;
+
+use codes::*;
+use multi::MultiSource;
+use source::{EventSource, LedControl};
+use {EvdevEvent, Error, EventDevice, TimeVal};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KeyState {
+    Released,
+    Pressed,
+    /// Kernel autorepeat of a held key.
+    Repeated,
+}
+
+impl KeyState {
+    pub fn from_value(val: i32) -> Option<KeyState> {
+        match val {
+            0 => Some(KeyState::Released),
+            1 => Some(KeyState::Pressed),
+            2 => Some(KeyState::Repeated),
+            _ => None,
+        }
+    }
+}
+
+/// Which modifier keys are held, left and right apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    pub left_meta: bool,
+    pub right_meta: bool,
+}
+
+impl Modifiers {
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.left_alt || self.right_alt
+    }
+
+    pub fn meta(&self) -> bool {
+        self.left_meta || self.right_meta
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::default()
+    }
+
+    fn flag(&mut self, key: KeyCode) -> Option<&mut bool> {
+        match key {
+            KeyCode::KEY_LEFTSHIFT => Some(&mut self.left_shift),
+            KeyCode::KEY_RIGHTSHIFT => Some(&mut self.right_shift),
+            KeyCode::KEY_LEFTCTRL => Some(&mut self.left_ctrl),
+            KeyCode::KEY_RIGHTCTRL => Some(&mut self.right_ctrl),
+            KeyCode::KEY_LEFTALT => Some(&mut self.left_alt),
+            KeyCode::KEY_RIGHTALT => Some(&mut self.right_alt),
+            KeyCode::KEY_LEFTMETA => Some(&mut self.left_meta),
+            KeyCode::KEY_RIGHTMETA => Some(&mut self.right_meta),
+            _ => None,
+        }
+    }
+
+    pub fn is_modifier(key: KeyCode) -> bool {
+        Modifiers::default().flag(key).is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Locks {
+    pub caps: bool,
+    pub num: bool,
+    pub scroll: bool,
+}
+
+/// A key event with the state it happened in. Modifiers and locks include
+/// the effect of the event itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyEvent {
+    pub time: TimeVal,
+    pub key: KeyCode,
+    pub state: KeyState,
+    pub modifiers: Modifiers,
+    pub locks: Locks,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KeyboardState {
+    pressed: BTreeSet<KeyCode>,
+    modifiers: Modifiers,
+    locks: Locks,
+    sync_leds: bool,
+}
+
+const LOCK_LEDS: [LedCode; 3] = [LedCode::LED_CAPSL, LedCode::LED_NUML, LedCode::LED_SCROLLL];
+
+impl KeyboardState {
+    pub fn new() -> KeyboardState {
+        KeyboardState::default()
+    }
+
+    /// Starts from the keys held on `dev` and its lock LEDs, so keys held
+    /// while opening are released properly later.
+    pub fn from_device(dev: &EventDevice) -> KeyboardState {
+        let led = |led| dev.led(led) == Some(true);
+        let locks = Locks { caps: led(LedCode::LED_CAPSL), num: led(LedCode::LED_NUML), scroll: led(LedCode::LED_SCROLLL) };
+        KeyboardState::new().with_pressed(dev.pressed_keys()).with_locks(locks)
+    }
+
+    pub fn with_pressed<I: IntoIterator<Item = KeyCode>>(mut self, keys: I) -> Self {
+        for key in keys {
+            self.set(key, true);
+        }
+        self
+    }
+
+    pub fn with_locks(mut self, locks: Locks) -> Self {
+        self.locks = locks;
+        self
+    }
+
+    /// Makes `next_key_from` light the lock LEDs of every keyboard when a
+    /// lock changes.
+    pub fn with_led_sync(mut self, sync: bool) -> Self {
+        self.sync_leds = sync;
+        self
+    }
+
+    pub fn is_pressed(&self, key: KeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// Held keys, in key code order.
+    pub fn pressed(&self) -> Vec<KeyCode> {
+        self.pressed.iter().cloned().collect()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn locks(&self) -> Locks {
+        self.locks
+    }
+
+    fn set(&mut self, key: KeyCode, pressed: bool) {
+        if pressed {
+            self.pressed.insert(key);
+        } else {
+            self.pressed.remove(&key);
+        }
+        if let Some(flag) = self.modifiers.flag(key) {
+            *flag = pressed;
+        }
+    }
+
+    /// Feeds one event; returns a `KeyEvent` for key events.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Option<KeyEvent> {
+        let key = match ev.ev.code {
+            EvdevCode::KeyCode(key) => key,
+            _ => return None,
+        };
+        let state = KeyState::from_value(ev.ev.val)?;
+        let was_pressed = self.is_pressed(key);
+        self.set(key, state != KeyState::Released);
+        // Locks toggle on real presses only
+        if state == KeyState::Pressed && !was_pressed {
+            match key {
+                KeyCode::KEY_CAPSLOCK => self.locks.caps = !self.locks.caps,
+                KeyCode::KEY_NUMLOCK => self.locks.num = !self.locks.num,
+                KeyCode::KEY_SCROLLLOCK => self.locks.scroll = !self.locks.scroll,
+                _ => {}
+            }
+        }
+        Some(KeyEvent { time: ev.time, key, state, modifiers: self.modifiers, locks: self.locks })
+    }
+
+    /// Sets the lock LEDs of `dev` to the lock state, e.g. when a keyboard
+    /// is plugged in. LEDs the device doesn't have are left alone. If an LED
+    /// can't be set the others still are, and the first error is returned.
+    pub fn update_leds<L: LedControl + ?Sized>(&self, dev: &mut L) -> Result<(), Error> {
+        let locks = [self.locks.caps, self.locks.num, self.locks.scroll];
+        let mut result = Ok(());
+        for (&led, &on) in LOCK_LEDS.iter().zip(&locks) {
+            if dev.led(led).is_some_and(|lit| lit != on) {
+                let set = dev.set_led(led, on);
+                if result.is_ok() {
+                    result = set;
+                }
+            }
+        }
+        result
+    }
+
+    /// Reads the next key event of any keyboard in `multi`, with the index of
+    /// the keyboard. With LED sync on, lock changes are shown on all of them;
+    /// a keyboard whose LEDs can't be set is skipped.
+    pub fn next_key_from<S: EventSource + LedControl>(&mut self, multi: &mut MultiSource<S>)
+        -> Result<Option<(usize, KeyEvent)>, Error>
+    {
+        while let Some((idx, ev)) = multi.read_event()? {
+            let locks = self.locks;
+            if let Some(key) = self.process(&ev) {
+                if self.sync_leds && self.locks != locks {
+                    for i in 0..multi.sources().len() {
+                        // E.g. unplugged; that's no reason to lose the key
+                        let _ = self.update_leds(multi.source_mut(i));
+                    }
+                }
+                return Ok(Some((idx, key)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads from `src` until the next key event, or until the source runs
+    /// dry.
+    pub fn next_key<S: EventSource>(&mut self, src: &mut S) -> Result<Option<KeyEvent>, Error> {
+        while let Some(ev) = src.read_event()? {
+            if let Some(key) = self.process(&ev) {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn keyboard_test() {
+        let mut src = MockSource::new(DeviceDescription::new("keyboard"));
+        src.key(KeyCode::KEY_RIGHTSHIFT, 1).sync()
+            .key(KeyCode::KEY_A, 1).sync()
+            .key(KeyCode::KEY_A, 2).sync()
+            .key(KeyCode::KEY_A, 0).sync()
+            .key(KeyCode::KEY_RIGHTSHIFT, 0).sync()
+            .key(KeyCode::KEY_CAPSLOCK, 1).sync()
+            .key(KeyCode::KEY_CAPSLOCK, 2).sync()
+            .key(KeyCode::KEY_CAPSLOCK, 0).sync();
+
+        // Num lock was on and left ctrl held when the device was opened
+        let mut kbd = KeyboardState::new()
+            .with_pressed(vec![KeyCode::KEY_LEFTCTRL])
+            .with_locks(Locks { num: true, ..Default::default() });
+        assert!(kbd.modifiers().ctrl());
+
+        let ev = kbd.next_key(&mut src).unwrap().unwrap();
+        assert!(ev.modifiers.right_shift && !ev.modifiers.left_shift);
+        let ev = kbd.next_key(&mut src).unwrap().unwrap();
+        assert_eq!((KeyCode::KEY_A, KeyState::Pressed), (ev.key, ev.state));
+        assert!(ev.modifiers.shift() && ev.modifiers.ctrl());
+        assert_eq!(KeyState::Repeated, kbd.next_key(&mut src).unwrap().unwrap().state);
+        assert_eq!(vec![KeyCode::KEY_LEFTCTRL, KeyCode::KEY_A, KeyCode::KEY_RIGHTSHIFT], kbd.pressed());
+        kbd.next_key(&mut src).unwrap();
+        kbd.next_key(&mut src).unwrap();
+        assert!(!kbd.modifiers().shift());
+
+        // Repeats don't toggle the lock again
+        for _ in 0..3 {
+            kbd.next_key(&mut src).unwrap();
+        }
+        assert_eq!(Locks { caps: true, num: true, scroll: false }, kbd.locks());
+        assert_eq!(vec![KeyCode::KEY_LEFTCTRL], kbd.pressed());
+        assert_eq!(None, kbd.next_key(&mut src).unwrap());
+    }
+
+    #[test]
+    fn led_sync_test() {
+        let leds = |name| DeviceDescription::new(name)
+            .with_code(EvdevCode::LedCode(LedCode::LED_CAPSL))
+            .with_code(EvdevCode::LedCode(LedCode::LED_NUML));
+        let mut laptop = MockSource::new(leds("laptop"));
+        laptop.key(KeyCode::KEY_CAPSLOCK, 1).sync().key(KeyCode::KEY_CAPSLOCK, 0).sync();
+        let mut usb = MockSource::new(leds("usb"));
+        usb.set_led(LedCode::LED_NUML, true).unwrap();
+        usb.key(KeyCode::KEY_NUMLOCK, 1).sync().key(KeyCode::KEY_NUMLOCK, 0).sync();
+        // A keypad without LEDs
+        let mut keypad = MockSource::new(DeviceDescription::new("keypad"));
+        keypad.key(KeyCode::KEY_KP1, 1).sync();
+
+        let mut kbd = KeyboardState::new().with_locks(Locks { num: true, ..Default::default() }).with_led_sync(true);
+        kbd.update_leds(&mut laptop).unwrap();
+        assert_eq!(Some(true), laptop.led(LedCode::LED_NUML));
+        assert_eq!(None, keypad.led(LedCode::LED_NUML));
+
+        let mut multi = MultiSource::new(vec![laptop, usb, keypad]);
+        let (idx, ev) = kbd.next_key_from(&mut multi).unwrap().unwrap();
+        assert_eq!((0, KeyCode::KEY_CAPSLOCK), (idx, ev.key));
+        for dev in &multi.sources()[..2] {
+            assert_eq!((Some(true), Some(true)), (dev.led(LedCode::LED_CAPSL), dev.led(LedCode::LED_NUML)));
+        }
+        while kbd.next_key_from(&mut multi).unwrap().is_some() {}
+        for dev in &multi.sources()[..2] {
+            assert_eq!((Some(true), Some(false)), (dev.led(LedCode::LED_CAPSL), dev.led(LedCode::LED_NUML)));
+        }
+        assert_eq!(Locks { caps: true, num: false, scroll: false }, kbd.locks());
+
+        // A failing LED doesn't keep the others from being set
+        struct Stuck(bool);
+        impl LedControl for Stuck {
+            fn set_led(&mut self, led: LedCode, on: bool) -> Result<(), Error> {
+                if led == LedCode::LED_CAPSL {
+                    return Err(Error("stuck".to_string()));
+                }
+                self.0 = on;
+                Ok(())
+            }
+            fn led(&self, led: LedCode) -> Option<bool> {
+                Some(led != LedCode::LED_CAPSL && self.0)
+            }
+        }
+        let mut stuck = Stuck(true);
+        assert!(kbd.update_leds(&mut stuck).is_err());
+        assert!(!stuck.0);
+    }
+}
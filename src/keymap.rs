@@ -0,0 +1,459 @@
+//! Key codes to text, with built-in US, UK, German, French and Swedish
+//! layouts.
+//!
+//! A `Keymap` gives every key up to four levels: plain, Shift, AltGr and
+//! Shift+AltGr, as in XKB. Caps lock shifts letters only, and the keypad
+//! types digits when num lock is on (or Shift is held with it off). Symbols
+//! without a character are named like XKB keysyms: `Return`, `Left`,
+//! `dead_acute`.
+
+use std::collections::HashMap;
+
+use codes::*;
+use keyboard::{KeyEvent, KeyState, Locks, Modifiers};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Keysym {
+    Char(char),
+    /// An XKB keysym name.
+    Named(String),
+}
+
+impl Keysym {
+    pub fn named(name: &str) -> Keysym {
+        Keysym::Named(name.to_string())
+    }
+
+    pub fn to_char(&self) -> Option<char> {
+        match *self {
+            Keysym::Char(c) => Some(c),
+            Keysym::Named(_) => None,
+        }
+    }
+
+    pub fn is_named(&self, name: &str) -> bool {
+        match *self {
+            Keysym::Named(ref n) => n == name,
+            Keysym::Char(_) => false,
+        }
+    }
+
+    /// The symbol for an XKB keysym name: Latin-1 names, keypad digits and
+    /// `U20AC` style names become characters, others stay named. `None` for `NoSymbol`.
+    pub fn from_name(name: &str) -> Option<Keysym> {
+        if name == "NoSymbol" || name == "VoidSymbol" {
+            return None;
+        }
+        let mut chars = name.trim_start_matches("KP_").chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                return Some(Keysym::Char(c));
+            }
+        }
+        if let Some(i) = LATIN1_NAMES.iter().position(|&n| n == name) {
+            return Some(Keysym::Char(char::from(0xa0 + i as u8)));
+        }
+        if let Some(&(_, c)) = KEYSYM_CHARS.iter().find(|&&(n, _)| n == name) {
+            return Some(Keysym::Char(c));
+        }
+        if name.len() > 4 && name.starts_with('U') {
+            if let Some(c) = u32::from_str_radix(&name[1..], 16).ok().and_then(std::char::from_u32) {
+                return Some(Keysym::Char(c));
+            }
+        }
+        Some(Keysym::named(name))
+    }
+}
+
+// Keysyms 0xa0 to 0xff, which are their Latin-1 characters
+const LATIN1_NAMES: [&str; 96] = [
+    "nobreakspace", "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section",
+    "diaeresis", "copyright", "ordfeminine", "guillemotleft", "notsign", "hyphen", "registered", "macron",
+    "degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph", "periodcentered",
+    "cedilla", "onesuperior", "masculine", "guillemotright", "onequarter", "onehalf", "threequarters",
+    "questiondown",
+    "Agrave", "Aacute", "Acircumflex", "Atilde", "Adiaeresis", "Aring", "AE", "Ccedilla",
+    "Egrave", "Eacute", "Ecircumflex", "Ediaeresis", "Igrave", "Iacute", "Icircumflex", "Idiaeresis",
+    "ETH", "Ntilde", "Ograve", "Oacute", "Ocircumflex", "Otilde", "Odiaeresis", "multiply",
+    "Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udiaeresis", "Yacute", "THORN", "ssharp",
+    "agrave", "aacute", "acircumflex", "atilde", "adiaeresis", "aring", "ae", "ccedilla",
+    "egrave", "eacute", "ecircumflex", "ediaeresis", "igrave", "iacute", "icircumflex", "idiaeresis",
+    "eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde", "odiaeresis", "division",
+    "oslash", "ugrave", "uacute", "ucircumflex", "udiaeresis", "yacute", "thorn", "ydiaeresis",
+];
+
+// ASCII punctuation, aliases and a few others that type a character
+const KEYSYM_CHARS: [(&str, char); 54] = [
+    ("space", ' '), ("exclam", '!'), ("quotedbl", '"'), ("numbersign", '#'), ("dollar", '$'),
+    ("percent", '%'), ("ampersand", '&'), ("apostrophe", '\''), ("quoteright", '\''),
+    ("parenleft", '('), ("parenright", ')'), ("asterisk", '*'), ("plus", '+'), ("comma", ','),
+    ("minus", '-'), ("period", '.'), ("slash", '/'), ("colon", ':'), ("semicolon", ';'), ("less", '<'),
+    ("equal", '='), ("greater", '>'), ("question", '?'), ("at", '@'), ("bracketleft", '['),
+    ("backslash", '\\'), ("bracketright", ']'), ("asciicircum", '^'), ("underscore", '_'), ("grave", '`'),
+    ("quoteleft", '`'), ("braceleft", '{'), ("bar", '|'), ("braceright", '}'), ("asciitilde", '~'),
+    ("guillemetleft", '«'), ("guillemetright", '»'), ("ordmasculine", 'º'), ("Ooblique", 'Ø'),
+    ("ooblique", 'ø'), ("Eth", 'Ð'), ("Thorn", 'Þ'), ("EuroSign", '€'), ("oe", 'œ'), ("OE", 'Œ'),
+    ("Ydiaeresis", 'Ÿ'),
+    ("KP_Space", ' '), ("KP_Multiply", '*'), ("KP_Add", '+'), ("KP_Separator", ','),
+    ("KP_Subtract", '-'), ("KP_Decimal", '.'), ("KP_Divide", '/'), ("KP_Equal", '='),
+];
+
+/// How modifiers pick a level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KeyKind {
+    /// Shift and AltGr pick the level.
+    Plain,
+    /// Caps lock works like Shift; both together cancel out.
+    Alphabetic,
+    /// Level 1 is the navigation symbol, level 2 the digit, picked by num lock.
+    Keypad,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Key {
+    pub kind: KeyKind,
+    /// Up to four levels; a missing level falls back to the one without AltGr.
+    pub levels: Vec<Option<Keysym>>,
+}
+
+impl Key {
+    pub fn new(kind: KeyKind, levels: Vec<Keysym>) -> Key {
+        Key { kind, levels: levels.into_iter().map(Some).collect() }
+    }
+
+    /// A key of the kind XKB gives keys without a type: alphabetic for a
+    /// case pair, keypad if it has keypad symbols, plain otherwise.
+    pub fn guess(levels: Vec<Option<Keysym>>) -> Key {
+        let level = |i: usize| levels.get(i).and_then(|sym| sym.as_ref());
+        let keypad = levels.iter().flatten().any(|sym| match *sym {
+            Keysym::Named(ref name) => name.starts_with("KP_"),
+            Keysym::Char(_) => false,
+        });
+        let kind = if is_case_pair(level(0), level(1)) {
+            KeyKind::Alphabetic
+        } else if keypad && levels.len() > 1 {
+            KeyKind::Keypad
+        } else {
+            KeyKind::Plain
+        };
+        Key { kind, levels }
+    }
+
+    fn level(&self, level: usize) -> Option<&Keysym> {
+        self.levels.get(level).and_then(|sym| sym.as_ref())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keymap {
+    name: String,
+    keys: HashMap<KeyCode, Key>,
+}
+
+// Printable keys of the four main rows, left to right
+const ROWS: [&[KeyCode]; 4] = [
+    &[KeyCode::KEY_GRAVE, KeyCode::KEY_1, KeyCode::KEY_2, KeyCode::KEY_3, KeyCode::KEY_4, KeyCode::KEY_5,
+      KeyCode::KEY_6, KeyCode::KEY_7, KeyCode::KEY_8, KeyCode::KEY_9, KeyCode::KEY_0, KeyCode::KEY_MINUS,
+      KeyCode::KEY_EQUAL],
+    &[KeyCode::KEY_Q, KeyCode::KEY_W, KeyCode::KEY_E, KeyCode::KEY_R, KeyCode::KEY_T, KeyCode::KEY_Y,
+      KeyCode::KEY_U, KeyCode::KEY_I, KeyCode::KEY_O, KeyCode::KEY_P, KeyCode::KEY_LEFTBRACE,
+      KeyCode::KEY_RIGHTBRACE],
+    &[KeyCode::KEY_A, KeyCode::KEY_S, KeyCode::KEY_D, KeyCode::KEY_F, KeyCode::KEY_G, KeyCode::KEY_H,
+      KeyCode::KEY_J, KeyCode::KEY_K, KeyCode::KEY_L, KeyCode::KEY_SEMICOLON, KeyCode::KEY_APOSTROPHE,
+      KeyCode::KEY_BACKSLASH],
+    &[KeyCode::KEY_102ND, KeyCode::KEY_Z, KeyCode::KEY_X, KeyCode::KEY_C, KeyCode::KEY_V, KeyCode::KEY_B,
+      KeyCode::KEY_N, KeyCode::KEY_M, KeyCode::KEY_COMMA, KeyCode::KEY_DOT, KeyCode::KEY_SLASH],
+];
+
+struct Layout {
+    name: &'static str,
+    // One string per row of `ROWS`, keys separated by spaces, one character
+    // per level. Combining accents stand for dead keys.
+    rows: [&'static str; 4],
+    altgr: bool,
+    kp_decimal: char,
+}
+
+const LAYOUTS: [Layout; 5] = [
+    Layout {
+        name: "us",
+        rows: ["`~ 1! 2@ 3# 4$ 5% 6^ 7& 8* 9( 0) -_ =+",
+               "qQ wW eE rR tT yY uU iI oO pP [{ ]}",
+               "aA sS dD fF gG hH jJ kK lL ;: '\" \\|",
+               "<> zZ xX cC vV bB nN mM ,< .> /?"],
+        altgr: false,
+        kp_decimal: '.',
+    },
+    Layout {
+        name: "gb",
+        rows: ["`¬¦ 1! 2\" 3£ 4$€ 5% 6^ 7& 8* 9( 0) -_ =+",
+               "qQ wW eEéÉ rR tT yY uUúÚ iIíÍ oOóÓ pP [{ ]}",
+               "aAáÁ sS dD fF gG hH jJ kK lL ;: '@ #~",
+               "\\| zZ xX cC vV bB nN mM ,< .> /?"],
+        altgr: true,
+        kp_decimal: '.',
+    },
+    Layout {
+        name: "de",
+        rows: ["\u{302}° 1! 2\"² 3§³ 4$ 5% 6& 7/{ 8([ 9)] 0=} ß?\\ \u{301}\u{300}",
+               "qQ@ wW eE€ rR tT zZ uU iI oO pP üÜ +*~",
+               "aA sS dD fF gG hH jJ kK lL öÖ äÄ #'",
+               "<>| yY xX cC vV bB nN mMµ ,; .: -_"],
+        altgr: true,
+        kp_decimal: ',',
+    },
+    Layout {
+        name: "fr",
+        rows: ["² &1 é2~É \"3# '4{ (5[ -6| è7` _8\\ ç9^ à0@ )°] =+}",
+               "aA zZ eE€ rR tT yY uU iI oO pP \u{302}\u{308} $£¤",
+               "qQ sS dD fF gG hH jJ kK lL mM ù% *µ",
+               "<> wW xX cC vV bB nN ,? ;. :/ !§"],
+        altgr: true,
+        kp_decimal: '.',
+    },
+    Layout {
+        name: "se",
+        rows: ["§½ 1! 2\"@ 3#£ 4¤$ 5%€ 6& 7/{ 8([ 9)] 0=} +?\\ \u{301}\u{300}",
+               "qQ wW eE€ rR tT yY uU iI oO pP åÅ \u{308}\u{302}\u{303}",
+               "aA sS dD fF gG hH jJ kK lL öÖ äÄ '*",
+               "<>| zZ xX cC vV bB nN mMµ ,; .: -_"],
+        altgr: true,
+        kp_decimal: ',',
+    },
+];
+
+const NAMED: [(KeyCode, &str); 38] = [
+    (KeyCode::KEY_ESC, "Escape"),
+    (KeyCode::KEY_BACKSPACE, "BackSpace"),
+    (KeyCode::KEY_ENTER, "Return"),
+    (KeyCode::KEY_LEFTCTRL, "Control_L"),
+    (KeyCode::KEY_RIGHTCTRL, "Control_R"),
+    (KeyCode::KEY_LEFTSHIFT, "Shift_L"),
+    (KeyCode::KEY_RIGHTSHIFT, "Shift_R"),
+    (KeyCode::KEY_LEFTALT, "Alt_L"),
+    (KeyCode::KEY_LEFTMETA, "Super_L"),
+    (KeyCode::KEY_RIGHTMETA, "Super_R"),
+    (KeyCode::KEY_CAPSLOCK, "Caps_Lock"),
+    (KeyCode::KEY_NUMLOCK, "Num_Lock"),
+    (KeyCode::KEY_SCROLLLOCK, "Scroll_Lock"),
+    (KeyCode::KEY_F1, "F1"),
+    (KeyCode::KEY_F2, "F2"),
+    (KeyCode::KEY_F3, "F3"),
+    (KeyCode::KEY_F4, "F4"),
+    (KeyCode::KEY_F5, "F5"),
+    (KeyCode::KEY_F6, "F6"),
+    (KeyCode::KEY_F7, "F7"),
+    (KeyCode::KEY_F8, "F8"),
+    (KeyCode::KEY_F9, "F9"),
+    (KeyCode::KEY_F10, "F10"),
+    (KeyCode::KEY_F11, "F11"),
+    (KeyCode::KEY_F12, "F12"),
+    (KeyCode::KEY_HOME, "Home"),
+    (KeyCode::KEY_END, "End"),
+    (KeyCode::KEY_PAGEUP, "Prior"),
+    (KeyCode::KEY_PAGEDOWN, "Next"),
+    (KeyCode::KEY_INSERT, "Insert"),
+    (KeyCode::KEY_DELETE, "Delete"),
+    (KeyCode::KEY_KPENTER, "KP_Enter"),
+    (KeyCode::KEY_SYSRQ, "Print"),
+    (KeyCode::KEY_LEFT, "Left"),
+    (KeyCode::KEY_RIGHT, "Right"),
+    (KeyCode::KEY_UP, "Up"),
+    (KeyCode::KEY_DOWN, "Down"),
+    (KeyCode::KEY_PAUSE, "Pause"),
+];
+
+const KEYPAD: [(KeyCode, &str, char); 10] = [
+    (KeyCode::KEY_KP0, "KP_Insert", '0'),
+    (KeyCode::KEY_KP1, "KP_End", '1'),
+    (KeyCode::KEY_KP2, "KP_Down", '2'),
+    (KeyCode::KEY_KP3, "KP_Next", '3'),
+    (KeyCode::KEY_KP4, "KP_Left", '4'),
+    (KeyCode::KEY_KP5, "KP_Begin", '5'),
+    (KeyCode::KEY_KP6, "KP_Right", '6'),
+    (KeyCode::KEY_KP7, "KP_Home", '7'),
+    (KeyCode::KEY_KP8, "KP_Up", '8'),
+    (KeyCode::KEY_KP9, "KP_Prior", '9'),
+];
+
+const KEYPAD_OPERATORS: [(KeyCode, char); 4] = [
+    (KeyCode::KEY_KPSLASH, '/'),
+    (KeyCode::KEY_KPASTERISK, '*'),
+    (KeyCode::KEY_KPMINUS, '-'),
+    (KeyCode::KEY_KPPLUS, '+'),
+];
+
+fn table_symbol(c: char) -> Keysym {
+    match c {
+        '\u{300}' => Keysym::named("dead_grave"),
+        '\u{301}' => Keysym::named("dead_acute"),
+        '\u{302}' => Keysym::named("dead_circumflex"),
+        '\u{303}' => Keysym::named("dead_tilde"),
+        '\u{308}' => Keysym::named("dead_diaeresis"),
+        c => Keysym::Char(c),
+    }
+}
+
+// A lower and upper case pair, so caps lock applies
+fn is_case_pair(lower: Option<&Keysym>, upper: Option<&Keysym>) -> bool {
+    match (lower.and_then(Keysym::to_char), upper.and_then(Keysym::to_char)) {
+        (Some(l), Some(u)) => l.is_lowercase() && l.to_uppercase().eq(Some(u)),
+        _ => false,
+    }
+}
+
+impl Keymap {
+    /// An empty keymap.
+    pub fn new(name: &str) -> Keymap {
+        Keymap { name: name.to_string(), keys: HashMap::new() }
+    }
+
+    /// `us`, `gb` (or `uk`), `de`, `fr` or `se`.
+    pub fn builtin(name: &str) -> Option<Keymap> {
+        let name = if name == "uk" { "gb" } else { name };
+        LAYOUTS.iter().find(|l| l.name == name).map(Keymap::from_layout)
+    }
+
+    /// Names of the built-in layouts.
+    pub fn builtin_names() -> Vec<&'static str> {
+        LAYOUTS.iter().map(|l| l.name).collect()
+    }
+
+    fn from_layout(layout: &Layout) -> Keymap {
+        let mut keymap = Keymap::new(layout.name);
+        for (codes, row) in ROWS.iter().zip(&layout.rows) {
+            for (&code, spec) in codes.iter().zip(row.split(' ')) {
+                let levels: Vec<Keysym> = spec.chars().map(table_symbol).collect();
+                let alphabetic = is_case_pair(levels.first(), levels.get(1));
+                let kind = if alphabetic { KeyKind::Alphabetic } else { KeyKind::Plain };
+                keymap.set_key(code, Key::new(kind, levels));
+            }
+        }
+        for &(code, name) in &NAMED {
+            keymap.set_key(code, Key::new(KeyKind::Plain, vec![Keysym::named(name)]));
+        }
+        keymap.set_key(KeyCode::KEY_TAB, Key::new(KeyKind::Plain, vec![Keysym::named("Tab"), Keysym::named("ISO_Left_Tab")]));
+        keymap.set_key(KeyCode::KEY_SPACE, Key::new(KeyKind::Plain, vec![Keysym::Char(' ')]));
+        let right_alt = if layout.altgr { "ISO_Level3_Shift" } else { "Alt_R" };
+        keymap.set_key(KeyCode::KEY_RIGHTALT, Key::new(KeyKind::Plain, vec![Keysym::named(right_alt)]));
+        for &(code, name, digit) in &KEYPAD {
+            keymap.set_key(code, Key::new(KeyKind::Keypad, vec![Keysym::named(name), Keysym::Char(digit)]));
+        }
+        keymap.set_key(KeyCode::KEY_KPDOT, Key::new(KeyKind::Keypad, vec![Keysym::named("KP_Delete"), Keysym::Char(layout.kp_decimal)]));
+        for &(code, c) in &KEYPAD_OPERATORS {
+            keymap.set_key(code, Key::new(KeyKind::Plain, vec![Keysym::Char(c)]));
+        }
+        keymap
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn key(&self, code: KeyCode) -> Option<&Key> {
+        self.keys.get(&code)
+    }
+
+    pub fn set_key(&mut self, code: KeyCode, key: Key) {
+        self.keys.insert(code, key);
+    }
+
+    /// Whether Right Alt is AltGr in this keymap.
+    pub fn has_altgr(&self) -> bool {
+        self.key(KeyCode::KEY_RIGHTALT)
+            .and_then(|k| k.level(0))
+            .is_some_and(|sym| sym.is_named("ISO_Level3_Shift"))
+    }
+
+    /// The symbol `code` produces with the given modifiers and locks.
+    pub fn keysym(&self, code: KeyCode, modifiers: &Modifiers, locks: &Locks) -> Option<Keysym> {
+        let key = self.key(code)?;
+        let shift = modifiers.shift();
+        if key.kind == KeyKind::Keypad {
+            let level = if locks.num != shift { 1 } else { 0 };
+            return key.level(level).or_else(|| key.level(0)).cloned();
+        }
+        let altgr = if modifiers.right_alt && self.has_altgr() { 2 } else { 0 };
+        let caps = locks.caps && match key.kind {
+            KeyKind::Alphabetic => is_case_pair(key.level(altgr), key.level(altgr + 1)),
+            _ => false,
+        };
+        let level = altgr + (shift != caps) as usize;
+        key.level(level)
+            .or_else(|| key.level(level - altgr))
+            .or_else(|| key.level(0))
+            .cloned()
+    }
+
+    /// The symbol of a press or autorepeat; `None` for releases.
+    pub fn translate(&self, ev: &KeyEvent) -> Option<Keysym> {
+        if ev.state == KeyState::Released {
+            return None;
+        }
+        self.keysym(ev.key, &ev.modifiers, &ev.locks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn type_key(keymap: &Keymap, key: KeyCode, mods: Modifiers, locks: Locks) -> Option<char> {
+        keymap.keysym(key, &mods, &locks).and_then(|sym| sym.to_char())
+    }
+
+    #[test]
+    fn layouts_test() {
+        let none = Modifiers::default();
+        let shift = Modifiers { left_shift: true, ..Default::default() };
+        let altgr = Modifiers { right_alt: true, ..Default::default() };
+        let caps = Locks { caps: true, ..Default::default() };
+        let unlocked = Locks::default();
+
+        let us = Keymap::builtin("us").unwrap();
+        assert_eq!(Some('@'), type_key(&us, KeyCode::KEY_2, shift, unlocked));
+        // No AltGr on US: Right Alt is just Alt
+        assert_eq!(Some('q'), type_key(&us, KeyCode::KEY_Q, altgr, unlocked));
+        assert_eq!(Some('Q'), type_key(&us, KeyCode::KEY_Q, none, caps));
+        assert_eq!(Some('q'), type_key(&us, KeyCode::KEY_Q, shift, caps));
+        // Caps lock leaves digits alone
+        assert_eq!(Some('1'), type_key(&us, KeyCode::KEY_1, none, caps));
+
+        let uk = Keymap::builtin("uk").unwrap();
+        assert_eq!(Some('"'), type_key(&uk, KeyCode::KEY_2, shift, unlocked));
+        assert_eq!(Some('€'), type_key(&uk, KeyCode::KEY_4, altgr, unlocked));
+
+        let de = Keymap::builtin("de").unwrap();
+        assert_eq!(Some('z'), type_key(&de, KeyCode::KEY_Y, none, unlocked));
+        assert_eq!(Some('@'), type_key(&de, KeyCode::KEY_Q, altgr, unlocked));
+        assert_eq!(Some('Ö'), type_key(&de, KeyCode::KEY_SEMICOLON, none, caps));
+        assert_eq!(Some(Keysym::named("dead_circumflex")), de.keysym(KeyCode::KEY_GRAVE, &none, &unlocked));
+
+        let fr = Keymap::builtin("fr").unwrap();
+        assert_eq!(Some('a'), type_key(&fr, KeyCode::KEY_Q, none, unlocked));
+        assert_eq!(Some('1'), type_key(&fr, KeyCode::KEY_1, shift, unlocked));
+        assert_eq!(Some('m'), type_key(&fr, KeyCode::KEY_SEMICOLON, none, unlocked));
+
+        let se = Keymap::builtin("se").unwrap();
+        assert_eq!(Some('å'), type_key(&se, KeyCode::KEY_LEFTBRACE, none, unlocked));
+        assert_eq!(Some('$'), type_key(&se, KeyCode::KEY_4, altgr, unlocked));
+        assert_eq!(Some(Keysym::named("Return")), se.keysym(KeyCode::KEY_ENTER, &none, &unlocked));
+        assert_eq!(Some(Keysym::named("ISO_Left_Tab")), se.keysym(KeyCode::KEY_TAB, &shift, &unlocked));
+    }
+
+    #[test]
+    fn keypad_test() {
+        let none = Modifiers::default();
+        let shift = Modifiers { left_shift: true, ..Default::default() };
+        let num = Locks { num: true, ..Default::default() };
+        let se = Keymap::builtin("se").unwrap();
+        assert_eq!(Some(Keysym::Char('7')), se.keysym(KeyCode::KEY_KP7, &none, &num));
+        assert_eq!(Some(Keysym::named("KP_Home")), se.keysym(KeyCode::KEY_KP7, &shift, &num));
+        assert_eq!(Some(Keysym::named("KP_Home")), se.keysym(KeyCode::KEY_KP7, &none, &Locks::default()));
+        assert_eq!(Some(Keysym::Char(',')), se.keysym(KeyCode::KEY_KPDOT, &none, &num));
+        assert_eq!(Some(Keysym::Char('+')), se.keysym(KeyCode::KEY_KPPLUS, &none, &Locks::default()));
+    }
+}
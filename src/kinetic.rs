@@ -0,0 +1,413 @@
+//! Contact velocities and kinetic scrolling.
+//!
+//! `VelocityTracker` fits a line through each contact's recent positions,
+//! using the event timestamps. `Fling` turns a release velocity into
+//! decaying scroll deltas; it reads time from a `Clock`, so tests can drive
+//! it with a `ManualClock`.
+
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use touch::{ContactState, TouchFrame};
+use TimeVal;
+
+/// A source of the current time.
+pub trait Clock {
+    fn now(&self) -> TimeVal;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> TimeVal {
+        (**self).now()
+    }
+}
+
+/// Wall-clock time, which is what evdev stamps events with unless the
+/// device was switched to another clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> TimeVal {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        TimeVal::default().plus(since_epoch)
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Cell<TimeVal>,
+}
+
+impl ManualClock {
+    pub fn new(now: TimeVal) -> ManualClock {
+        ManualClock { now: Cell::new(now) }
+    }
+
+    pub fn set(&self, now: TimeVal) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, d: Duration) {
+        self.now.set(self.now.get().plus(d));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> TimeVal {
+        self.now.get()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct History {
+    // (time, x, y), oldest first
+    samples: VecDeque<(TimeVal, f64, f64)>,
+    lifted: bool,
+}
+
+/// Per-contact velocities in device units per second, from a least-squares
+/// fit over a sliding time window.
+#[derive(Clone, Debug)]
+pub struct VelocityTracker {
+    window: Duration,
+    contacts: HashMap<i32, History>,
+}
+
+impl Default for VelocityTracker {
+    fn default() -> Self {
+        VelocityTracker::new()
+    }
+}
+
+impl VelocityTracker {
+    pub fn new() -> VelocityTracker {
+        VelocityTracker {
+            window: Duration::from_millis(100),
+            contacts: HashMap::new(),
+        }
+    }
+
+    /// How far back samples count. Defaults to 100ms.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn process(&mut self, frame: &TouchFrame) {
+        self.contacts.retain(|_, h| !h.lifted);
+        let (now, window) = (frame.time, self.window);
+        for c in &frame.contacts {
+            let history = self.contacts.entry(c.tracking_id).or_insert_with(|| History {
+                samples: VecDeque::new(),
+                lifted: false,
+            });
+            if c.state == ContactState::Down {
+                history.samples.clear();
+            }
+            // A lift counts as a sample, so a contact that rested before
+            // lifting ends up slow
+            history.samples.push_back((now, f64::from(c.x), f64::from(c.y)));
+            while history.samples.front().is_some_and(|s| now > s.0.plus(window)) {
+                history.samples.pop_front();
+            }
+            history.lifted = c.state == ContactState::Up;
+        }
+    }
+
+    /// The velocity of a contact in the last frame, including one that just
+    /// lifted. `None` until it has two samples in the window.
+    pub fn velocity(&self, tracking_id: i32) -> Option<(f64, f64)> {
+        let samples = &self.contacts.get(&tracking_id)?.samples;
+        if samples.len() < 2 {
+            return None;
+        }
+        let n = samples.len() as f64;
+        let t0 = samples.back().unwrap().0;
+        let secs = |t: TimeVal| t.micros_since(t0) as f64 / 1_000_000.0;
+        let mean_t = samples.iter().map(|s| secs(s.0)).sum::<f64>() / n;
+        let mean_x = samples.iter().map(|s| s.1).sum::<f64>() / n;
+        let mean_y = samples.iter().map(|s| s.2).sum::<f64>() / n;
+        let (mut tt, mut tx, mut ty) = (0.0, 0.0, 0.0);
+        for s in samples {
+            let dt = secs(s.0) - mean_t;
+            tt += dt * dt;
+            tx += dt * (s.1 - mean_x);
+            ty += dt * (s.2 - mean_y);
+        }
+        if tt == 0.0 {
+            return None;
+        }
+        Some((tx / tt, ty / tt))
+    }
+}
+
+/// How a fling slows down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Friction {
+    /// The velocity falls by `e^-k` every second.
+    Exponential(f64),
+    /// The speed falls by this many units per second, every second.
+    Constant(f64),
+}
+
+// Lowest coefficient `Fling` accepts; anything less would never stop
+const MIN_FRICTION: f64 = 0.01;
+
+impl Friction {
+    fn at_least(self, min: f64) -> Friction {
+        // Written so that NaN is raised too
+        let raise = |k: f64| if k >= min { k } else { min };
+        match self {
+            Friction::Exponential(k) => Friction::Exponential(raise(k)),
+            Friction::Constant(a) => Friction::Constant(raise(a)),
+        }
+    }
+
+    // Distance along the direction of travel after `t` seconds
+    fn distance(self, speed: f64, t: f64) -> f64 {
+        match self {
+            Friction::Exponential(k) => speed / k * (1.0 - (-k * t).exp()),
+            Friction::Constant(a) => speed * t - a * t * t / 2.0,
+        }
+    }
+
+    // Seconds until the speed is down to `min`
+    fn duration(self, speed: f64, min: f64) -> f64 {
+        if speed <= min {
+            return 0.0;
+        }
+        match self {
+            Friction::Exponential(k) => (speed / min).ln() / k,
+            Friction::Constant(a) => (speed - min) / a,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Flight {
+    start: i64,
+    speed: f64,
+    // Unit vector of the direction of travel
+    dir: (f64, f64),
+    // Seconds until it stops
+    duration: f64,
+    // Distance already handed out
+    travelled: f64,
+}
+
+/// Decaying scroll deltas after a release.
+pub struct Fling<C: Clock> {
+    clock: C,
+    friction: Friction,
+    min_velocity: f64,
+    flight: Option<Flight>,
+}
+
+impl<C: Clock> Fling<C> {
+    pub fn new(clock: C) -> Fling<C> {
+        Fling {
+            clock,
+            friction: Friction::Exponential(3.0),
+            min_velocity: 20.0,
+            flight: None,
+        }
+    }
+
+    /// Defaults to `Friction::Exponential(3.0)`. Coefficients below 0.01,
+    /// including zero, negative ones and NaN, are raised to 0.01 so that a
+    /// fling always slows down and stops.
+    pub fn with_friction(mut self, friction: Friction) -> Self {
+        self.friction = friction.at_least(MIN_FRICTION);
+        self
+    }
+
+    /// Flings stop once they are slower than this, in units per second, and
+    /// slower releases don't start one. Defaults to 20.
+    pub fn with_min_velocity(mut self, min_velocity: f64) -> Self {
+        self.min_velocity = min_velocity;
+        self
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Starts a fling at `velocity` units per second from now, replacing any
+    /// fling in progress.
+    pub fn start(&mut self, velocity: (f64, f64)) {
+        let speed = velocity.0.hypot(velocity.1);
+        let duration = self.friction.duration(speed, self.min_velocity);
+        self.flight = if duration > 0.0 {
+            Some(Flight {
+                start: self.clock.now().as_micros(),
+                speed,
+                dir: (velocity.0 / speed, velocity.1 / speed),
+                duration,
+                travelled: 0.0,
+            })
+        } else {
+            None
+        };
+    }
+
+    pub fn stop(&mut self) {
+        self.flight = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.flight.is_some()
+    }
+
+    /// The distance covered since the last poll, or `None` once the fling is
+    /// over. The last delta takes it exactly to where it stops.
+    pub fn poll(&mut self) -> Option<(f64, f64)> {
+        let now = self.clock.now().as_micros();
+        let friction = self.friction;
+        let f = self.flight.as_mut()?;
+        let t = ((now - f.start) as f64 / 1_000_000.0).clamp(0.0, f.duration);
+        let distance = friction.distance(f.speed, t);
+        let step = distance - f.travelled;
+        f.travelled = distance;
+        let delta = (f.dir.0 * step, f.dir.1 * step);
+        if t >= f.duration {
+            self.flight = None;
+        }
+        Some(delta)
+    }
+}
+
+/// Scrolling by dragging one contact, with a fling when it is let go.
+pub struct KineticScroll<C: Clock> {
+    tracker: VelocityTracker,
+    fling: Fling<C>,
+    // The contact doing the scrolling and where it was last
+    active: Option<(i32, (i32, i32))>,
+}
+
+impl<C: Clock> KineticScroll<C> {
+    pub fn new(clock: C) -> KineticScroll<C> {
+        KineticScroll::with_fling(Fling::new(clock))
+    }
+
+    pub fn with_fling(fling: Fling<C>) -> KineticScroll<C> {
+        KineticScroll { tracker: VelocityTracker::new(), fling, active: None }
+    }
+
+    pub fn with_tracker(mut self, tracker: VelocityTracker) -> Self {
+        self.tracker = tracker;
+        self
+    }
+
+    pub fn is_flinging(&self) -> bool {
+        self.fling.is_active()
+    }
+
+    /// The scroll delta of a frame while dragging. A new contact stops a
+    /// fling in progress; lifting the dragging one starts another.
+    pub fn process(&mut self, frame: &TouchFrame) -> Option<(f64, f64)> {
+        self.tracker.process(frame);
+        if self.active.is_none() {
+            if let Some(c) = frame.contacts.iter().find(|c| c.state == ContactState::Down) {
+                self.fling.stop();
+                self.active = Some((c.tracking_id, (c.x, c.y)));
+            }
+        }
+        let (id, (x, y)) = self.active?;
+        let c = frame.contact(id)?;
+        if c.state == ContactState::Up {
+            self.active = None;
+            if let Some(velocity) = self.tracker.velocity(id) {
+                self.fling.start(velocity);
+            }
+            return None;
+        }
+        self.active = Some((id, (c.x, c.y)));
+        if (c.x, c.y) == (x, y) {
+            None
+        } else {
+            Some((f64::from(c.x - x), f64::from(c.y - y)))
+        }
+    }
+
+    /// The fling delta since the last poll, if flinging.
+    pub fn poll(&mut self) -> Option<(f64, f64)> {
+        self.fling.poll()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use touch::Contact;
+
+    fn frame(ms: i64, state: ContactState, x: i32) -> TouchFrame {
+        TouchFrame {
+            time: TimeVal::from_micros(ms * 1000),
+            contacts: vec![Contact { state, ..Contact::new(0, 1, x, 100) }],
+        }
+    }
+
+    #[test]
+    fn velocity_test() {
+        let mut tracker = VelocityTracker::new().with_window(Duration::from_millis(50));
+        tracker.process(&frame(0, ContactState::Down, 0));
+        assert_eq!(None, tracker.velocity(1));
+        // 2 units per ms, with some jitter, after a slow start outside the window
+        for &(ms, x) in &[(100, 10), (110, 30), (120, 51), (130, 69), (140, 90)] {
+            tracker.process(&frame(ms, ContactState::Move, x));
+        }
+        let (vx, vy) = tracker.velocity(1).unwrap();
+        assert!((vx - 1990.0).abs() < 1e-6, "{}", vx);
+        assert_eq!(0.0, vy);
+
+        // Resting before the lift slows it down
+        tracker.process(&frame(190, ContactState::Up, 90));
+        assert!(tracker.velocity(1).unwrap().0 < 500.0);
+        tracker.process(&TouchFrame { time: TimeVal::from_micros(200_000), contacts: Vec::new() });
+        assert_eq!(None, tracker.velocity(1));
+    }
+
+    #[test]
+    fn fling_test() {
+        let clock = ManualClock::default();
+        let mut scroll = KineticScroll::with_fling(Fling::new(&clock).with_friction(Friction::Constant(1000.0)));
+        assert_eq!(None, scroll.process(&frame(0, ContactState::Down, 0)));
+        assert_eq!(Some((10.0, 0.0)), scroll.process(&frame(10, ContactState::Move, 10)));
+        assert_eq!(Some((10.0, 0.0)), scroll.process(&frame(20, ContactState::Move, 20)));
+        assert_eq!(None, scroll.process(&frame(20, ContactState::Up, 20)));
+        assert!(scroll.is_flinging());
+
+        // 1000 units/s, stopping at 20 units/s after 0.98s
+        clock.advance(Duration::from_millis(500));
+        let (dx, _) = scroll.poll().unwrap();
+        assert!((dx - 375.0).abs() < 1e-6, "{}", dx);
+        clock.advance(Duration::from_secs(1));
+        let (dx, _) = scroll.poll().unwrap();
+        assert!((dx - (499.8 - 375.0)).abs() < 1e-6, "{}", dx);
+        assert_eq!(None, scroll.poll());
+
+        // A new touch stops a fling
+        scroll.process(&frame(2000, ContactState::Down, 0));
+        scroll.process(&frame(2010, ContactState::Move, 10));
+        scroll.process(&frame(2010, ContactState::Up, 10));
+        assert!(scroll.is_flinging());
+        scroll.process(&frame(2100, ContactState::Down, 0));
+        assert!(!scroll.is_flinging());
+
+        // No friction still comes to a stop, going forward
+        for &friction in &[Friction::Exponential(0.0), Friction::Constant(-5.0), Friction::Constant(f64::NAN)] {
+            let mut fling = Fling::new(&clock).with_friction(friction);
+            fling.start((100.0, 0.0));
+            clock.advance(Duration::from_secs(1));
+            let (dx, _) = fling.poll().unwrap();
+            assert!(dx > 90.0 && dx <= 100.0, "{:?}: {}", friction, dx);
+            clock.advance(Duration::from_secs(10_000));
+            assert!(fling.poll().unwrap().0 > 0.0);
+            assert_eq!(None, fling.poll());
+        }
+    }
+}
@@ -4,62 +4,109 @@ extern crate libc;
 #[macro_use] extern crate newtype_derive;
 #[macro_use] extern crate enum_primitive;
 extern crate num;
-use num::FromPrimitive;
+#[cfg(feature = "serde")] extern crate serde;
+#[cfg(feature = "serde")] #[macro_use] extern crate serde_derive;
+#[cfg(feature = "serde")] extern crate serde_json;
 
 use self::libevdev_sys::evdev::*;
 use self::libevdev_sys::linux_input::*;
 use std::{ptr, fmt};
 use std::ffi::CStr;
-use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::{IntoRawFd, RawFd};
 use std::fs::{File, self};
+use std::path::Path;
 use std::collections::HashMap;
+use std::time::Duration;
+use num::FromPrimitive;
 
-mod codes;
+pub mod codes;
 use codes::*;
 
+pub mod source;
+pub mod replay;
+pub mod mock;
+pub mod touch;
+pub mod protocol_a;
+pub mod emulation;
+pub mod uinput;
+pub mod multi;
+pub mod evtest;
+pub mod names;
+pub mod calibration;
+pub mod gesture;
+pub mod regions;
+pub mod filter;
+pub mod palm;
+pub mod kinetic;
+pub mod stylus;
+pub mod arbitration;
+pub mod touchpad;
+pub mod keyboard;
+pub mod keymap;
+pub mod xkb;
+pub mod compose;
+pub mod lineedit;
+pub mod barcode;
+#[cfg(feature = "serde")]
+pub mod jsonl;
+pub use source::{EventSource, AbsInfo, DeviceDescription, LedControl};
+pub use names::lookup;
+
 #[cfg(target_pointer_width = "32")]
-type Int = i32;
+pub type Int = i32;
 
 #[cfg(target_pointer_width = "64")]
-type Int = i64;
+pub type Int = i64;
 
 #[repr(C)]
-#[derive(Clone, Copy)]
-struct TimeVal {
-	sec: Int,
-	usec: Int,
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimeVal {
+	pub sec: Int,
+	pub usec: Int,
+}
+
+impl TimeVal {
+    pub fn new(sec: Int, usec: Int) -> TimeVal {
+        TimeVal { sec, usec }
+    }
+
+    pub fn from_micros(micros: i64) -> TimeVal {
+        TimeVal {
+            sec: micros.div_euclid(1_000_000) as Int,
+            usec: micros.rem_euclid(1_000_000) as Int,
+        }
+    }
+
+    // `Int` is only narrower than i64 on 32-bit targets
+    #[allow(clippy::useless_conversion)]
+    pub fn as_micros(&self) -> i64 {
+        i64::from(self.sec) * 1_000_000 + i64::from(self.usec)
+    }
+
+    /// This time moved on by `d`.
+    pub fn plus(&self, d: Duration) -> TimeVal {
+        TimeVal::from_micros(self.as_micros() + d.as_micros() as i64)
+    }
+
+    /// Microseconds from `earlier` to this time, negative if `earlier` is later.
+    pub fn micros_since(&self, earlier: TimeVal) -> i64 {
+        self.as_micros() - earlier.as_micros()
+    }
 }
 
 impl From<(u16, u16)> for EvdevCode {
     fn from(type_and_num: (u16, u16)) -> Self {
-        match TypeCode::from_u16(type_and_num.0 as _) {
-            // TODO: Remove unwraps
-            Some(TypeCode::EV_SYN) => EvdevCode::SynCode(SynCode::from_u16(type_and_num.1).unwrap()),
-            Some(TypeCode::EV_KEY) => if let Some(kc) = KeyCode::from_u16(type_and_num.1) {
-						EvdevCode::KeyCode(kc)
-					} else {
-						println!("Unknown key code: {:?}", type_and_num);
-						EvdevCode::Undefined(type_and_num.0)
-					}
-            Some(TypeCode::EV_ABS) => if let Some(ac) = AbsCode::from_u16(type_and_num.1) {
-						EvdevCode::AbsCode(ac)
-					} else {
-						println!("Unknown abs code: {:?}", type_and_num);
-						EvdevCode::Undefined(type_and_num.0)
-					}
-            //_ => panic!(format!("EvdevCode::Undefined({:x})", type_and_num.0)),
-            _ => {
-			println!("EvdevCode::Undefined({:x})", type_and_num.0);
-			EvdevCode::Undefined(type_and_num.0)
-		}
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
+        let (ty, num) = type_and_num;
+        EvdevCode::from_raw(ty, num).unwrap_or(EvdevCode::Undefined(ty, num))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EvdevData {
-    code: EvdevCode,
-    val: i32,
+    pub code: EvdevCode,
+    pub val: i32,
 }
 
 impl From<input_event> for EvdevData {
@@ -77,20 +124,184 @@ impl fmt::Debug for TimeVal {
 	}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EvdevEvent {
-    time: TimeVal,
-    ev: EvdevData,
+    pub time: TimeVal,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub ev: EvdevData,
+}
+
+impl EvdevEvent {
+    pub fn new(time: TimeVal, code: EvdevCode, val: i32) -> EvdevEvent {
+        EvdevEvent {
+            time,
+            ev: EvdevData { code, val },
+        }
+    }
+
+    pub fn is_syn_report(&self) -> bool {
+        self.ev.code == EvdevCode::SynCode(SynCode::SYN_REPORT)
+    }
+
+    pub fn to_raw(&self) -> input_event {
+        let (type_, code) = self.ev.code.to_raw();
+        let mut ev = input_event::default();
+        ev.time.tv_sec = self.time.sec as _;
+        ev.time.tv_usec = self.time.usec as _;
+        ev.type_ = type_;
+        ev.code = code;
+        ev.value = self.ev.val;
+        ev
+    }
+}
+
+impl From<input_event> for EvdevEvent {
+    fn from(ev: input_event) -> Self {
+        EvdevEvent {
+            time: TimeVal {
+                sec: ev.time.tv_sec as _,
+                usec: ev.time.tv_usec as _,
+            },
+            ev: ev.into(),
+        }
+    }
+}
+
+/// All events up to and including a `SYN_REPORT`, stamped with the report's time.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EvdevFrame {
+    pub time: TimeVal,
+    pub events: Vec<EvdevEvent>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
 }
 
 pub struct EventDevice {
     stream: *mut libevdev,
+    name: String,
     flags: u32,
     ev: input_event,
+    syncing: bool,
+}
+
+impl Drop for EventDevice {
+    fn drop(&mut self) {
+        unsafe {
+            let fd = libevdev_get_fd(self.stream);
+            libevdev_free(self.stream);
+            if fd >= 0 {
+                libc::close(fd);
+            }
+        }
+    }
 }
 
 impl EventDevice {
-    pub fn read_name(&mut self) {}
+    pub fn input_id(&self) -> InputId {
+        unsafe {
+            InputId {
+                bustype: libevdev_get_id_bustype(self.stream) as u16,
+                vendor: libevdev_get_id_vendor(self.stream) as u16,
+                product: libevdev_get_id_product(self.stream) as u16,
+                version: libevdev_get_id_version(self.stream) as u16,
+            }
+        }
+    }
+
+    pub fn driver_version(&self) -> i32 {
+        unsafe { libevdev_get_driver_version(self.stream) }
+    }
+
+    pub fn phys(&self) -> Option<String> {
+        let phys = unsafe { libevdev_get_phys(self.stream) };
+        if phys.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(phys) }.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Grabs the device exclusively (`EVIOCGRAB`), or releases the grab.
+    pub fn grab(&mut self, grab: bool) -> Result<(), Error> {
+        let mode = if grab {
+            libevdev_grab_mode::LIBEVDEV_GRAB
+        } else {
+            libevdev_grab_mode::LIBEVDEV_UNGRAB
+        };
+        let ret = unsafe { libevdev_grab(self.stream, mode) };
+        if ret != 0 {
+            return Err(Error(format!("libevdev_grab failed: {}", ret)));
+        }
+        Ok(())
+    }
+
+    /// The current value of a key, switch, LED or axis as libevdev tracks it,
+    /// or `None` if the device doesn't have the code.
+    pub fn event_value(&self, code: EvdevCode) -> Option<i32> {
+        if !self.has_event_code(code) {
+            return None;
+        }
+        let (ty, num) = code.to_raw();
+        Some(unsafe { libevdev_get_event_value(self.stream, u32::from(ty), u32::from(num)) })
+    }
+
+    /// Lights or clears an LED (`libevdev_kernel_set_led_value`).
+    pub fn set_led(&mut self, led: LedCode, on: bool) -> Result<(), Error> {
+        let value = if on {
+            libevdev_led_value::LIBEVDEV_LED_ON
+        } else {
+            libevdev_led_value::LIBEVDEV_LED_OFF
+        };
+        let (_, num) = EvdevCode::LedCode(led).to_raw();
+        let ret = unsafe { libevdev_kernel_set_led_value(self.stream, u32::from(num), value) };
+        if ret != 0 {
+            return Err(Error(format!("libevdev_kernel_set_led_value failed: {}", ret)));
+        }
+        Ok(())
+    }
+
+    /// Whether `led` is lit, or `None` if the device has no such LED.
+    pub fn led(&self, led: LedCode) -> Option<bool> {
+        self.event_value(EvdevCode::LedCode(led)).map(|v| v != 0)
+    }
+
+    /// The LEDs lit right now.
+    pub fn lit_leds(&self) -> Vec<LedCode> {
+        (0..0x10u16)
+            .filter_map(LedCode::from_u16)
+            .filter(|&led| self.led(led) == Some(true))
+            .collect()
+    }
+
+    /// Keys held down right now, e.g. to seed a `KeyboardState` on open.
+    pub fn pressed_keys(&self) -> Vec<KeyCode> {
+        (0..0x300u16)
+            .filter_map(KeyCode::from_u16)
+            .filter(|&key| self.event_value(EvdevCode::KeyCode(key)).is_some_and(|v| v != 0))
+            .collect()
+    }
+
+    /// In non-blocking mode `read_event` returns `Ok(None)` instead of waiting.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error> {
+        let fd = unsafe { libevdev_get_fd(self.stream) };
+        let fl = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        let fl = if nonblocking { fl | libc::O_NONBLOCK } else { fl & !libc::O_NONBLOCK };
+        if unsafe { libc::fcntl(fd, libc::F_SETFL, fl) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let blocking = libevdev_read_flag::LIBEVDEV_READ_FLAG_BLOCKING as u32;
+        self.flags = if nonblocking { self.flags & !blocking } else { self.flags | blocking };
+        Ok(())
+    }
 
     pub fn read(&mut self) -> Result<EvdevEvent, String> {
         let mut ev = input_event::default();
@@ -121,6 +332,76 @@ impl EventDevice {
     }
 }
 
+impl LedControl for EventDevice {
+    fn set_led(&mut self, led: LedCode, on: bool) -> Result<(), Error> {
+        EventDevice::set_led(self, led, on)
+    }
+
+    fn led(&self, led: LedCode) -> Option<bool> {
+        EventDevice::led(self, led)
+    }
+}
+
+impl EventSource for EventDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        loop {
+            let flags = if self.syncing {
+                libevdev_read_flag::LIBEVDEV_READ_FLAG_SYNC as u32
+            } else {
+                self.flags
+            };
+            let mut ev = input_event::default();
+            let ret = unsafe { libevdev_next_event(self.stream, flags, &mut ev) };
+
+            match ret {
+                r if r == (libevdev_read_status::LIBEVDEV_READ_STATUS_SUCCESS as i32) => {
+                    return Ok(Some(ev.into()));
+                }
+                r if r == (libevdev_read_status::LIBEVDEV_READ_STATUS_SYNC as i32) => {
+                    // Either the SYN_DROPPED itself or one of the resync events
+                    self.syncing = true;
+                    return Ok(Some(ev.into()));
+                }
+                r if r == -libc::EAGAIN && self.syncing => {
+                    self.syncing = false;
+                }
+                r if r == -libc::EAGAIN => return Ok(None),
+                _ => return Err(Error(format!("failed to read event: {}", ret))),
+            }
+        }
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        unsafe { libevdev_has_event_type(self.stream, ty as u32) == 1 }
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        let (ty, code) = code.to_raw();
+        unsafe { libevdev_has_event_code(self.stream, ty as u32, code as u32) == 1 }
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        unsafe { libevdev_has_property(self.stream, prop as u32) == 1 }
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        let info = unsafe { libevdev_get_abs_info(self.stream, code as u32) };
+        if info.is_null() {
+            None
+        } else {
+            Some(unsafe { *info }.into())
+        }
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(unsafe { libevdev_get_fd(self.stream) })
+    }
+}
+
 custom_derive! {
     #[derive(Debug, NewtypeDisplay, NewtypeFrom)]
     pub struct Error(String);
@@ -132,37 +413,46 @@ impl std::convert::From<std::io::Error> for Error {
     }
 }
 
+#[cfg(feature = "serde")]
+impl std::convert::From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error(err.to_string())
+    }
+}
+
 impl std::convert::From<std::num::ParseIntError> for Error {
     fn from(err: std::num::ParseIntError) -> Error {
         Error(err.to_string())
     }
 }
 
-pub fn list_devices() -> Result<HashMap<usize, (String, EventDevice)>, Error> {    
-    let mut devices = HashMap::new();
-    for entry in fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.is_dir() {
-            continue;
-        }
+/// Opens every `/dev/input/event*` device, by index. Devices that can't be
+/// opened, typically for lack of permissions, are left out.
+pub fn list_devices() -> Result<HashMap<usize, (String, EventDevice)>, Error> {
+    scan_devices("/dev/input", |path| open_path(path).map(|dev| (dev.name().to_string(), dev)))
+}
 
-        // TODO: Remove unrwap
-        let path = path.file_name().unwrap().to_str().unwrap();
-        if !path.starts_with("event") {
-            continue;
-        }
-        if path.len() > "event".len() {
-            let num_str: &str = path.split_at("event".len()).1;
-            let num = num_str.to_string().parse::<usize>()?;
-            let dev = get_device_from_idx(num)?;
-            let name = get_name_from_device(&dev).to_string();
-            devices.insert(num, (name, dev));
+fn scan_devices<T, F>(dir: &str, mut open: F) -> Result<HashMap<usize, T>, Error>
+    where F: FnMut(&Path) -> Result<T, Error>
+{
+    let mut devices = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        let num = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("event"))
+            .and_then(|num| num.parse::<usize>().ok());
+        if let (Some(num), false) = (num, path.is_dir()) {
+            if let Ok(dev) = open(&path) {
+                devices.insert(num, dev);
+            }
         }
     }
     Ok(devices)
-} 
+}
 
 /*fn print_props(dev: &Device) {
 	println!("Properties:");
@@ -176,39 +466,98 @@ pub fn list_devices() -> Result<HashMap<usize, (String, EventDevice)>, Error> {
 
 
 fn get_device_from_idx(idx: usize) -> Result<EventDevice, Error> {
-    let file = File::open(format!("/dev/input/event{}", idx))?;
+    open_path(format!("/dev/input/event{}", idx))
+}
+
+pub fn open_path<P: AsRef<Path>>(path: P) -> Result<EventDevice, Error> {
+    let file = File::open(path)?;
     let fd = file.into_raw_fd();
 
     let mut evdev: *mut libevdev = ptr::null_mut();
     let ret = unsafe { libevdev_new_from_fd(fd, &mut evdev) };
     if ret != 0 {
+        unsafe { libc::close(fd) };
         return Err(Error(format!("libevdev_new_from_fd failed: {}", ret)));
     }
 
     Ok(EventDevice {
         stream: evdev,
+        name: get_name(evdev),
         flags: libevdev_read_flag::LIBEVDEV_READ_FLAG_NORMAL as u32 |
                libevdev_read_flag::LIBEVDEV_READ_FLAG_BLOCKING as u32,
         ev: input_event::default(),
+        syncing: false,
     })
 }
 
-fn get_name_from_device(dev: &EventDevice) -> &'static str {
-    let name = unsafe { libevdev_get_name(dev.stream) };
-    unsafe { CStr::from_ptr(name) }.to_str().unwrap()
+// Names aren't necessarily UTF-8
+fn get_name(evdev: *mut libevdev) -> String {
+    let name = unsafe { libevdev_get_name(evdev) };
+    if name.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
 }
 
 pub fn open_device(dev_nr: usize) -> Result<EventDevice, Error> {
-    let device = get_device_from_idx(dev_nr)?;
-    println!("device name: {}", get_name_from_device(&device));
+    get_device_from_idx(dev_nr)
+}
+
+/// Opens a device given as a path (`/dev/input/event3`), an index (`3` or
+/// `event3`) or a device name. Names match exactly first, then as a
+/// case-insensitive substring, which must be unambiguous.
+pub fn find_device(spec: &str) -> Result<EventDevice, Error> {
+    if spec.contains('/') {
+        return open_path(spec);
+    }
+    let idx = spec.strip_prefix("event").unwrap_or(spec);
+    if !idx.is_empty() && idx.chars().all(|c| c.is_ascii_digit()) {
+        return get_device_from_idx(idx.parse()?);
+    }
 
-    Ok(device)
+    let mut devices = list_devices()?;
+    let mut nums: Vec<usize> = devices.iter()
+        .filter(|(_, (name, _))| name == spec)
+        .map(|(num, _)| *num)
+        .collect();
+    if nums.is_empty() {
+        let needle = spec.to_lowercase();
+        nums = devices.iter()
+            .filter(|(_, (name, _))| name.to_lowercase().contains(&needle))
+            .map(|(num, _)| *num)
+            .collect();
+    }
+    nums.sort();
+    match nums.len() {
+        0 => Err(Error(format!("no device matching '{}'", spec))),
+        1 => Ok(devices.remove(&nums[0]).unwrap().1),
+        _ => Err(Error(format!("'{}' matches several devices: {}", spec,
+            nums.iter().map(|n| format!("event{}", n)).collect::<Vec<_>>().join(", ")))),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn scan_devices_test() {
+        let dir = std::env::temp_dir().join(format!("event_parse-scan-{}", std::process::id()));
+        fs::create_dir_all(dir.join("by-id")).unwrap();
+        for name in &["event0", "event1", "event12", "eventx", "mouse0"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        // event1 can't be opened, as without permissions
+        let devices = scan_devices(dir.to_str().unwrap(), |path| {
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            if name == "event1" { Err(Error("Permission denied".to_string())) } else { Ok(name) }
+        }).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let mut found: Vec<_> = devices.into_iter().collect();
+        found.sort();
+        assert_eq!(vec![(0, "event0".to_string()), (12, "event12".to_string())], found);
+    }
+
     #[test]
     fn parse_event_test() {
         let expected = AbsCode::ABS_Y;
@@ -0,0 +1,224 @@
+//! Reading lines of text typed on a keyboard, without a terminal.
+//!
+//! `LineEditor` turns key events into text through a `Keymap`, with
+//! Backspace, Delete, Left, Right, Home and End, and hands over the line
+//! when Enter is pressed. Dead keys and compose sequences work when a
+//! `ComposeTable` is given. For passwords, `display` masks the text.
+
+use compose::{ComposeStatus, ComposeTable, Composer};
+use keyboard::{KeyEvent, KeyboardState};
+use keymap::{Keymap, Keysym};
+use source::EventSource;
+use {EvdevEvent, Error};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineEvent {
+    /// The text or the cursor changed.
+    Edited,
+    /// Enter was pressed. The editor starts over with an empty line.
+    Submitted(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct LineEditor {
+    keymap: Keymap,
+    keyboard: KeyboardState,
+    composer: Option<Composer>,
+    mask: Option<char>,
+    text: Vec<char>,
+    // In characters, 0 to `text.len()`
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub fn new(keymap: Keymap) -> LineEditor {
+        LineEditor {
+            keymap,
+            keyboard: KeyboardState::new(),
+            composer: None,
+            mask: None,
+            text: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Starts from a known keyboard state, as from `KeyboardState::from_device`.
+    pub fn with_keyboard(mut self, keyboard: KeyboardState) -> Self {
+        self.keyboard = keyboard;
+        self
+    }
+
+    pub fn with_compose(mut self, table: ComposeTable) -> Self {
+        self.composer = Some(Composer::new(table));
+        self
+    }
+
+    /// Shows every character as `mask`, for passwords.
+    pub fn with_mask(mut self, mask: char) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    /// The text as it should be shown, masked if asked to.
+    pub fn display(&self) -> String {
+        match self.mask {
+            Some(mask) => self.text.iter().map(|_| mask).collect(),
+            None => self.text(),
+        }
+    }
+
+    /// The cursor position in characters.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_composing(&self) -> bool {
+        self.composer.as_ref().is_some_and(Composer::is_composing)
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        if let Some(ref mut composer) = self.composer {
+            composer.reset();
+        }
+    }
+
+    /// Feeds one event; returns what it did to the line, if anything.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Option<LineEvent> {
+        let key = self.keyboard.process(ev)?;
+        let sym = match self.composer {
+            Some(ref mut composer) => match composer.process(&self.keymap, &key)? {
+                ComposeStatus::Passthrough(sym) => sym,
+                ComposeStatus::Composed(text) => {
+                    for c in text.chars() {
+                        self.insert(c);
+                    }
+                    return Some(LineEvent::Edited);
+                }
+                ComposeStatus::Composing | ComposeStatus::Cancelled => return None,
+            },
+            None => self.keymap.translate(&key)?,
+        };
+        self.apply(&key, sym)
+    }
+
+    fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn apply(&mut self, key: &KeyEvent, sym: Keysym) -> Option<LineEvent> {
+        let name = match sym {
+            Keysym::Char(c) => {
+                // Shortcuts aren't text. Right Alt is AltGr where the keymap
+                // has one, and types characters.
+                let alt = if self.keymap.has_altgr() { key.modifiers.left_alt } else { key.modifiers.alt() };
+                if key.modifiers.ctrl() || alt || c.is_control() {
+                    return None;
+                }
+                self.insert(c);
+                return Some(LineEvent::Edited);
+            }
+            Keysym::Named(name) => name,
+        };
+        match name.trim_start_matches("KP_") {
+            "Return" | "Enter" => {
+                let line = self.text();
+                self.clear();
+                return Some(LineEvent::Submitted(line));
+            }
+            "BackSpace" if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            "Delete" if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            "Left" if self.cursor > 0 => self.cursor -= 1,
+            "Right" if self.cursor < self.text.len() => self.cursor += 1,
+            "Home" if self.cursor > 0 => self.cursor = 0,
+            "End" if self.cursor < self.text.len() => self.cursor = self.text.len(),
+            _ => return None,
+        }
+        Some(LineEvent::Edited)
+    }
+
+    /// Reads from `src` until Enter is pressed, or until the source runs dry.
+    pub fn read_line<S: EventSource>(&mut self, src: &mut S) -> Result<Option<String>, Error> {
+        while let Some(ev) = src.read_event()? {
+            if let Some(LineEvent::Submitted(line)) = self.process(&ev) {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codes::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    fn tap(src: &mut MockSource, keys: &[KeyCode]) {
+        for &key in keys {
+            src.key(key, 1).sync().key(key, 0).sync();
+        }
+    }
+
+    #[test]
+    fn editing_test() {
+        let mut src = MockSource::new(DeviceDescription::new("keyboard"));
+        tap(&mut src, &[KeyCode::KEY_H, KeyCode::KEY_E, KeyCode::KEY_L, KeyCode::KEY_O, KeyCode::KEY_LEFT,
+                        KeyCode::KEY_L, KeyCode::KEY_HOME, KeyCode::KEY_DELETE, KeyCode::KEY_LEFT]);
+        src.key(KeyCode::KEY_LEFTSHIFT, 1).sync();
+        tap(&mut src, &[KeyCode::KEY_J]);
+        src.key(KeyCode::KEY_LEFTSHIFT, 0).sync();
+        src.key(KeyCode::KEY_LEFTCTRL, 1).sync();
+        tap(&mut src, &[KeyCode::KEY_C]);
+        src.key(KeyCode::KEY_LEFTCTRL, 0).sync();
+        src.key(KeyCode::KEY_RIGHTALT, 1).sync();
+        tap(&mut src, &[KeyCode::KEY_C]);
+        src.key(KeyCode::KEY_RIGHTALT, 0).sync();
+        tap(&mut src, &[KeyCode::KEY_END, KeyCode::KEY_BACKSPACE, KeyCode::KEY_Y, KeyCode::KEY_ENTER,
+                        KeyCode::KEY_O, KeyCode::KEY_K, KeyCode::KEY_KPENTER, KeyCode::KEY_X]);
+
+        let mut editor = LineEditor::new(Keymap::builtin("us").unwrap());
+        assert_eq!(Some("Jelly".to_string()), editor.read_line(&mut src).unwrap());
+        assert_eq!(("", 0), (editor.text().as_str(), editor.cursor()));
+        assert_eq!(Some("ok".to_string()), editor.read_line(&mut src).unwrap());
+        assert_eq!(None, editor.read_line(&mut src).unwrap());
+        assert_eq!(("x", 1), (editor.text().as_str(), editor.cursor()));
+    }
+
+    #[test]
+    fn password_and_compose_test() {
+        let mut src = MockSource::new(DeviceDescription::new("keyboard"));
+        // A dead acute, then e, on a Swedish keyboard
+        tap(&mut src, &[KeyCode::KEY_EQUAL, KeyCode::KEY_E]);
+        // AltGr types, Alt doesn't
+        src.key(KeyCode::KEY_RIGHTALT, 1).sync();
+        tap(&mut src, &[KeyCode::KEY_2]);
+        src.key(KeyCode::KEY_RIGHTALT, 0).sync();
+        src.key(KeyCode::KEY_LEFTALT, 1).sync();
+        tap(&mut src, &[KeyCode::KEY_2]);
+        src.key(KeyCode::KEY_LEFTALT, 0).sync();
+        tap(&mut src, &[KeyCode::KEY_EQUAL]);
+        let mut editor = LineEditor::new(Keymap::builtin("se").unwrap())
+            .with_compose(ComposeTable::dead_keys())
+            .with_mask('*');
+        let mut events = Vec::new();
+        while let Some(ev) = src.read_event().unwrap() {
+            events.extend(editor.process(&ev));
+        }
+        assert_eq!(vec![LineEvent::Edited, LineEvent::Edited], events);
+        assert!(editor.is_composing());
+        assert_eq!(("é@", "**"), (editor.text().as_str(), editor.display().as_str()));
+    }
+}
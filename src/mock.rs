@@ -0,0 +1,119 @@
+use std::collections::{HashSet, VecDeque};
+
+use codes::*;
+use source::{AbsInfo, DeviceDescription, EventSource, LedControl};
+use {EvdevEvent, Error, TimeVal};
+
+/// An in-memory, scripted event source for tests.
+///
+/// Events are stamped with an internal clock that only moves when `advance` is
+/// called, so scripts are fully deterministic.
+pub struct MockSource {
+    desc: DeviceDescription,
+    events: VecDeque<EvdevEvent>,
+    now: TimeVal,
+    leds: HashSet<LedCode>,
+}
+
+impl MockSource {
+    pub fn new(desc: DeviceDescription) -> MockSource {
+        MockSource {
+            desc,
+            events: VecDeque::new(),
+            now: TimeVal::default(),
+            leds: HashSet::new(),
+        }
+    }
+
+    pub fn description(&self) -> &DeviceDescription {
+        &self.desc
+    }
+
+    pub fn now(&self) -> TimeVal {
+        self.now
+    }
+
+    pub fn advance(&mut self, micros: i64) -> &mut Self {
+        self.now = TimeVal::from_micros(self.now.as_micros() + micros);
+        self
+    }
+
+    pub fn push(&mut self, code: EvdevCode, val: i32) -> &mut Self {
+        let ev = EvdevEvent::new(self.now, code, val);
+        self.events.push_back(ev);
+        self
+    }
+
+    pub fn push_event(&mut self, ev: EvdevEvent) -> &mut Self {
+        self.events.push_back(ev);
+        self
+    }
+
+    pub fn key(&mut self, code: KeyCode, val: i32) -> &mut Self {
+        self.push(EvdevCode::KeyCode(code), val)
+    }
+
+    pub fn abs(&mut self, code: AbsCode, val: i32) -> &mut Self {
+        self.push(EvdevCode::AbsCode(code), val)
+    }
+
+    pub fn rel(&mut self, code: RelCode, val: i32) -> &mut Self {
+        self.push(EvdevCode::RelCode(code), val)
+    }
+
+    pub fn sync(&mut self) -> &mut Self {
+        self.push(EvdevCode::SynCode(SynCode::SYN_REPORT), 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// LEDs the description has can be set and read back.
+impl LedControl for MockSource {
+    fn set_led(&mut self, led: LedCode, on: bool) -> Result<(), Error> {
+        if !self.desc.has_event_code(EvdevCode::LedCode(led)) {
+            return Err(Error(format!("{} has no {:?}", self.desc.name, led)));
+        }
+        if on {
+            self.leds.insert(led);
+        } else {
+            self.leds.remove(&led);
+        }
+        Ok(())
+    }
+
+    fn led(&self, led: LedCode) -> Option<bool> {
+        if !self.desc.has_event_code(EvdevCode::LedCode(led)) {
+            return None;
+        }
+        Some(self.leds.contains(&led))
+    }
+}
+
+impl EventSource for MockSource {
+    fn name(&self) -> &str {
+        &self.desc.name
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        Ok(self.events.pop_front())
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.desc.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        self.desc.has_event_code(code)
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.desc.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        self.desc.abs_info(code)
+    }
+}
@@ -0,0 +1,89 @@
+use libc;
+use std::io;
+
+use source::EventSource;
+use {EvdevEvent, Error};
+
+/// Reads from several sources at once, tagging each event with the index of
+/// the source it came from.
+///
+/// Sources with a file descriptor must be non-blocking (see
+/// `EventDevice::set_nonblocking`); when none of them has data the reader
+/// sleeps in `poll(2)`. Events of one frame are never interleaved with another
+/// source's events.
+pub struct MultiSource<S> {
+    sources: Vec<S>,
+    next: usize,
+}
+
+impl<S: EventSource> MultiSource<S> {
+    pub fn new(sources: Vec<S>) -> MultiSource<S> {
+        MultiSource { sources, next: 0 }
+    }
+
+    pub fn sources(&self) -> &[S] {
+        &self.sources
+    }
+
+    pub fn source_mut(&mut self, idx: usize) -> &mut S {
+        &mut self.sources[idx]
+    }
+
+    pub fn into_sources(self) -> Vec<S> {
+        self.sources
+    }
+
+    /// Next event from any source, or `None` once every source without a file
+    /// descriptor is exhausted and there are no sources to wait on.
+    pub fn read_event(&mut self) -> Result<Option<(usize, EvdevEvent)>, Error> {
+        let n = self.sources.len();
+        loop {
+            for k in 0..n {
+                let idx = (self.next + k) % n;
+                if let Some(ev) = self.sources[idx].read_event()? {
+                    // Stay on this source until its frame is complete
+                    self.next = if ev.is_syn_report() { (idx + 1) % n } else { idx };
+                    return Ok(Some((idx, ev)));
+                }
+            }
+
+            let mut fds: Vec<libc::pollfd> = self.sources.iter()
+                .filter_map(|s| s.raw_fd())
+                .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+                .collect();
+            if fds.is_empty() {
+                return Ok(None);
+            }
+            let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codes::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn frames_not_interleaved_test() {
+        let mut a = MockSource::new(DeviceDescription::new("a"));
+        a.abs(AbsCode::ABS_X, 1).abs(AbsCode::ABS_Y, 2).sync();
+        let mut b = MockSource::new(DeviceDescription::new("b"));
+        b.key(KeyCode::KEY_A, 1).sync();
+
+        let mut multi = MultiSource::new(vec![a, b]);
+        let mut order = Vec::new();
+        while let Some((idx, _)) = multi.read_event().unwrap() {
+            order.push(idx);
+        }
+        assert_eq!(vec![0, 0, 0, 1, 1], order);
+    }
+}
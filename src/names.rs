@@ -0,0 +1,224 @@
+//! Symbolic names for event types and codes.
+//!
+//! Parsing is case-insensitive, the type prefix may be left out (`"volumeup"`
+//! parses as `KEY_VOLUMEUP` when a `KeyCode` is expected) and the kernel's
+//! alias names are resolved (`KEY_COFFEE` is `KEY_SCREENLOCK`).
+
+use num::FromPrimitive;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use codes::*;
+use Error;
+
+/// Alias names from `input-event-codes.h` and the names they stand for.
+const ALIASES: &[(&str, &str)] = &[
+    ("KEY_HANGUEL", "KEY_HANGEUL"),
+    ("KEY_COFFEE", "KEY_SCREENLOCK"),
+    ("KEY_DIRECTION", "KEY_ROTATE_DISPLAY"),
+    ("KEY_BRIGHTNESS_ZERO", "KEY_BRIGHTNESS_AUTO"),
+    ("KEY_WIMAX", "KEY_WWAN"),
+    ("KEY_BRIGHTNESS_TOGGLE", "KEY_DISPLAYTOGGLE"),
+    ("KEY_MIN_INTERESTING", "KEY_MUTE"),
+    ("BTN_MISC", "BTN_0"),
+    ("BTN_MOUSE", "BTN_LEFT"),
+    ("BTN_JOYSTICK", "BTN_TRIGGER"),
+    ("BTN_GAMEPAD", "BTN_A"),
+    ("BTN_SOUTH", "BTN_A"),
+    ("BTN_EAST", "BTN_B"),
+    ("BTN_NORTH", "BTN_X"),
+    ("BTN_WEST", "BTN_Y"),
+    ("BTN_DIGI", "BTN_TOOL_PEN"),
+    ("BTN_WHEEL", "BTN_GEAR_DOWN"),
+    ("BTN_TRIGGER_HAPPY", "BTN_TRIGGER_HAPPY1"),
+    ("SW_RADIO", "SW_RFKILL_ALL"),
+    ("ABS_HAT0X", "ABS_HAT"),
+];
+
+fn resolve_alias(name: &str) -> Option<&'static str> {
+    ALIASES.iter()
+        .find(|&&(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|&(_, canonical)| canonical)
+}
+
+/// A code enum with symbolic names, all values below `MAX`.
+trait Named: Sized + Copy + fmt::Debug + 'static {
+    const MAX: u16;
+    const PREFIXES: &'static [&'static str];
+
+    /// Lowercase names to codes, built on first use.
+    fn names() -> &'static HashMap<String, Self>;
+
+    fn find_exact(name: &str) -> Option<Self> {
+        Self::names().get(&name.to_ascii_lowercase()).cloned()
+    }
+
+    /// Full name, then name without prefix, then alias.
+    fn find(name: &str) -> Option<Self> {
+        let name = name.trim();
+        Self::find_exact(name)
+            .or_else(|| Self::PREFIXES.iter().filter_map(|p| Self::find_exact(&format!("{}{}", p, name))).next())
+            .or_else(|| {
+                let alias = resolve_alias(name).or_else(|| {
+                    Self::PREFIXES.iter().filter_map(|p| resolve_alias(&format!("{}{}", p, name))).next()
+                })?;
+                Self::find_exact(alias)
+            })
+    }
+}
+
+macro_rules! named {
+    ($t:ident, $max:expr, [$($prefix:expr),*]) => {
+        impl Named for $t {
+            const MAX: u16 = $max;
+            const PREFIXES: &'static [&'static str] = &[$($prefix),*];
+
+            fn names() -> &'static HashMap<String, $t> {
+                static NAMES: OnceLock<HashMap<String, $t>> = OnceLock::new();
+                NAMES.get_or_init(|| {
+                    (0..=Self::MAX).filter_map($t::from_u16)
+                        .map(|c| (format!("{:?}", c).to_ascii_lowercase(), c))
+                        .collect()
+                })
+            }
+        }
+
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(self, f)
+            }
+        }
+
+        impl FromStr for $t {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<$t, Error> {
+                $t::find(s).ok_or_else(|| Error(format!("unknown {} name '{}'", stringify!($t), s)))
+            }
+        }
+    };
+}
+
+named!(TypeCode, 0x1f, ["EV_"]);
+named!(SynCode, 0x0f, ["SYN_"]);
+named!(KeyCode, 0x2ff, ["KEY_", "BTN_"]);
+named!(RelCode, 0x0f, ["REL_"]);
+named!(AbsCode, 0x3f, ["ABS_"]);
+named!(MscCode, 0x07, ["MSC_"]);
+named!(SwiCode, 0x10, ["SW_"]);
+named!(LedCode, 0x0f, ["LED_"]);
+named!(SndCode, 0x07, ["SND_"]);
+named!(RepCode, 0x01, ["REP_"]);
+named!(PropCode, 0x1f, ["INPUT_PROP_"]);
+
+/// Codes print as their bare name (`KEY_A`); codes without a name as `?`,
+/// like evtest does.
+impl fmt::Display for EvdevCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvdevCode::SynCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::KeyCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::RelCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::AbsCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::MscCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::SwiCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::LedCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::SndCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::RepCode(c) => fmt::Display::fmt(&c, f),
+            EvdevCode::Undefined(..) => f.write_str("?"),
+        }
+    }
+}
+
+fn code_of_type(ty: TypeCode, name: &str) -> Option<EvdevCode> {
+    match ty {
+        TypeCode::EV_SYN => SynCode::find(name).map(EvdevCode::SynCode),
+        TypeCode::EV_KEY => KeyCode::find(name).map(EvdevCode::KeyCode),
+        TypeCode::EV_REL => RelCode::find(name).map(EvdevCode::RelCode),
+        TypeCode::EV_ABS => AbsCode::find(name).map(EvdevCode::AbsCode),
+        TypeCode::EV_MSC => MscCode::find(name).map(EvdevCode::MscCode),
+        TypeCode::EV_SW => SwiCode::find(name).map(EvdevCode::SwiCode),
+        TypeCode::EV_LED => LedCode::find(name).map(EvdevCode::LedCode),
+        TypeCode::EV_SND => SndCode::find(name).map(EvdevCode::SndCode),
+        TypeCode::EV_REP => RepCode::find(name).map(EvdevCode::RepCode),
+        _ => None,
+    }
+}
+
+fn code_of_type_exact(ty: TypeCode, name: &str) -> Option<EvdevCode> {
+    match ty {
+        TypeCode::EV_SYN => SynCode::find_exact(name).map(EvdevCode::SynCode),
+        TypeCode::EV_KEY => KeyCode::find_exact(name).map(EvdevCode::KeyCode),
+        TypeCode::EV_REL => RelCode::find_exact(name).map(EvdevCode::RelCode),
+        TypeCode::EV_ABS => AbsCode::find_exact(name).map(EvdevCode::AbsCode),
+        TypeCode::EV_MSC => MscCode::find_exact(name).map(EvdevCode::MscCode),
+        TypeCode::EV_SW => SwiCode::find_exact(name).map(EvdevCode::SwiCode),
+        TypeCode::EV_LED => LedCode::find_exact(name).map(EvdevCode::LedCode),
+        TypeCode::EV_SND => SndCode::find_exact(name).map(EvdevCode::SndCode),
+        TypeCode::EV_REP => RepCode::find_exact(name).map(EvdevCode::RepCode),
+        _ => None,
+    }
+}
+
+const CODE_TYPES: &[TypeCode] = &[
+    TypeCode::EV_SYN, TypeCode::EV_KEY, TypeCode::EV_REL, TypeCode::EV_ABS, TypeCode::EV_MSC,
+    TypeCode::EV_SW, TypeCode::EV_LED, TypeCode::EV_SND, TypeCode::EV_REP,
+];
+
+/// A full name like `KEY_A` or `abs_mt_slot`. Without a prefix the name must
+/// belong to exactly one type: `"volumeup"` works, `"X"` (`REL_X` or `ABS_X`)
+/// does not.
+impl FromStr for EvdevCode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<EvdevCode, Error> {
+        let s = s.trim();
+        if let Some(code) = CODE_TYPES.iter().find_map(|&ty| code_of_type_exact(ty, s)) {
+            return Ok(code);
+        }
+        let found: Vec<_> = CODE_TYPES.iter().filter_map(|&ty| code_of_type(ty, s)).collect();
+        match found.len() {
+            0 => Err(Error(format!("unknown event code name '{}'", s))),
+            1 => Ok(found[0]),
+            _ => Err(Error(format!("'{}' is ambiguous: {}", s,
+                found.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ")))),
+        }
+    }
+}
+
+/// Looks up a code by type and code name, like libevdev's
+/// `libevdev_event_code_from_name`: `lookup("EV_KEY", "KEY_A")`. Both names are
+/// case-insensitive and may omit their prefix, so `lookup("key", "a")` works too.
+pub fn lookup(ty: &str, code: &str) -> Option<EvdevCode> {
+    code_of_type(TypeCode::find(ty)?, code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup_test() {
+        assert_eq!(Some(EvdevCode::KeyCode(KeyCode::KEY_A)), lookup("EV_KEY", "KEY_A"));
+        assert_eq!(Some(EvdevCode::KeyCode(KeyCode::KEY_A)), lookup("key", "a"));
+        assert_eq!(Some(EvdevCode::KeyCode(KeyCode::BTN_TOUCH)), lookup("EV_KEY", "touch"));
+        assert_eq!(Some(EvdevCode::AbsCode(AbsCode::ABS_X)), lookup("EV_ABS", "x"));
+        assert_eq!(Some(EvdevCode::KeyCode(KeyCode::KEY_SCREENLOCK)), lookup("EV_KEY", "KEY_COFFEE"));
+        assert_eq!(Some(EvdevCode::KeyCode(KeyCode::BTN_A)), lookup("EV_KEY", "south"));
+        assert_eq!(None, lookup("EV_REL", "KEY_A"));
+        assert_eq!(None, lookup("EV_BOGUS", "KEY_A"));
+    }
+
+    #[test]
+    fn from_str_test() {
+        assert_eq!(KeyCode::KEY_VOLUMEUP, "key_volumeup".parse().unwrap());
+        assert_eq!(TypeCode::EV_ABS, "abs".parse().unwrap());
+        assert_eq!(EvdevCode::AbsCode(AbsCode::ABS_MT_POSITION_X), "ABS_MT_POSITION_X".parse().unwrap());
+        assert_eq!(EvdevCode::KeyCode(KeyCode::KEY_VOLUMEUP), "volumeup".parse().unwrap());
+        assert!("X".parse::<EvdevCode>().is_err());
+        assert!("KEY_NOPE".parse::<KeyCode>().is_err());
+        assert_eq!("KEY_A", EvdevCode::KeyCode(KeyCode::KEY_A).to_string());
+    }
+}
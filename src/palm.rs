@@ -0,0 +1,235 @@
+//! Telling fingers from palms and pens, and suppressing palms.
+//!
+//! `PalmRejection` is a `TouchFilter`. Once a contact is taken for a palm it
+//! stays one until it lifts; a contact that grows into a palm after it was
+//! reported is lifted early. Every classification is kept as a `PalmDecision`
+//! for the application to log.
+
+use std::collections::HashMap;
+
+use filter::TouchFilter;
+use source::{position_axes, AbsInfo, EventSource};
+use touch::{Contact, ContactState, TouchFrame};
+use {Error, TimeVal};
+
+/// `ABS_MT_TOOL_TYPE` values.
+pub const MT_TOOL_FINGER: i32 = 0;
+pub const MT_TOOL_PEN: i32 = 1;
+pub const MT_TOOL_PALM: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ContactClass {
+    Finger,
+    Palm,
+    Pen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PalmReason {
+    /// The device itself reported `MT_TOOL_PALM`.
+    ToolType,
+    Size,
+    Pressure,
+    /// Went down next to a border.
+    Edge,
+}
+
+/// A contact was classified, or its class changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PalmDecision {
+    pub time: TimeVal,
+    pub tracking_id: i32,
+    pub class: ContactClass,
+    /// Set for palms.
+    pub reason: Option<PalmReason>,
+    /// The contact's `ABS_MT_TOUCH_MAJOR` (or `WIDTH_MAJOR`) in millimetres.
+    pub size_mm: f64,
+    pub pressure: i32,
+}
+
+/// Thresholds beyond which a contact counts as a palm; `None` turns a check off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PalmConfig {
+    pub max_size_mm: Option<f64>,
+    pub max_pressure: Option<i32>,
+    /// Contacts going down this close to the left or right border.
+    pub edge_mm: Option<f64>,
+}
+
+impl Default for PalmConfig {
+    fn default() -> Self {
+        PalmConfig {
+            max_size_mm: Some(20.0),
+            max_pressure: None,
+            edge_mm: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tracked {
+    class: ContactClass,
+    reported: bool,
+}
+
+pub struct PalmRejection {
+    config: PalmConfig,
+    x: AbsInfo,
+    // Device units per millimetre; sizes use the x resolution, like the kernel
+    res: f64,
+    contacts: HashMap<i32, Tracked>,
+    decisions: Vec<PalmDecision>,
+}
+
+impl PalmRejection {
+    /// Without an x resolution, one device unit counts as a millimetre; use
+    /// `with_resolution` for such devices.
+    pub fn new(x: AbsInfo) -> PalmRejection {
+        PalmRejection {
+            config: PalmConfig::default(),
+            x,
+            res: x.units_per_mm(),
+            contacts: HashMap::new(),
+            decisions: Vec::new(),
+        }
+    }
+
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<PalmRejection, Error> {
+        position_axes(src).map(|(x, _)| PalmRejection::new(x))
+    }
+
+    pub fn with_config(mut self, config: PalmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_resolution(mut self, units_per_mm: f64) -> Self {
+        self.res = units_per_mm;
+        self
+    }
+
+    /// The class of a contact on the surface.
+    pub fn class(&self, tracking_id: i32) -> Option<ContactClass> {
+        self.contacts.get(&tracking_id).map(|t| t.class)
+    }
+
+    /// Decisions since the last call, oldest first.
+    pub fn take_decisions(&mut self) -> Vec<PalmDecision> {
+        ::std::mem::take(&mut self.decisions)
+    }
+
+    fn size_mm(&self, c: &Contact) -> f64 {
+        let major = if c.touch_major > 0 { c.touch_major } else { c.width_major };
+        f64::from(major) / self.res
+    }
+
+    fn classify(&self, c: &Contact) -> (ContactClass, Option<PalmReason>) {
+        if c.tool_type == MT_TOOL_PALM {
+            return (ContactClass::Palm, Some(PalmReason::ToolType));
+        }
+        if c.tool_type == MT_TOOL_PEN {
+            return (ContactClass::Pen, None);
+        }
+        if self.config.max_size_mm.is_some_and(|max| self.size_mm(c) > max) {
+            return (ContactClass::Palm, Some(PalmReason::Size));
+        }
+        if self.config.max_pressure.is_some_and(|max| c.pressure > max) {
+            return (ContactClass::Palm, Some(PalmReason::Pressure));
+        }
+        if let (ContactState::Down, Some(edge)) = (c.state, self.config.edge_mm) {
+            let left = f64::from(c.x - self.x.minimum) / self.res;
+            let right = f64::from(self.x.maximum - c.x) / self.res;
+            if left.min(right) <= edge {
+                return (ContactClass::Palm, Some(PalmReason::Edge));
+            }
+        }
+        (ContactClass::Finger, None)
+    }
+}
+
+impl TouchFilter for PalmRejection {
+    fn filter(&mut self, frame: TouchFrame) -> TouchFrame {
+        let mut out = Vec::new();
+        for mut c in frame.contacts {
+            let prev = self.contacts.get(&c.tracking_id).cloned();
+            let (class, reason) = match prev {
+                Some(Tracked { class: ContactClass::Palm, .. }) => (ContactClass::Palm, None),
+                _ => self.classify(&c),
+            };
+            if prev.map(|t| t.class) != Some(class) {
+                self.decisions.push(PalmDecision {
+                    time: frame.time,
+                    tracking_id: c.tracking_id,
+                    class,
+                    reason,
+                    size_mm: self.size_mm(&c),
+                    pressure: c.pressure,
+                });
+            }
+            let reported = prev.is_some_and(|t| t.reported);
+            let lifted = c.state == ContactState::Up;
+            if class != ContactClass::Palm || reported {
+                if class == ContactClass::Palm {
+                    // Turned into a palm after it was reported
+                    c.state = ContactState::Up;
+                }
+                out.push(c);
+            }
+            if lifted {
+                self.contacts.remove(&c.tracking_id);
+            } else {
+                let reported = class != ContactClass::Palm;
+                self.contacts.insert(c.tracking_id, Tracked { class, reported });
+            }
+        }
+        TouchFrame { time: frame.time, contacts: out }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(contacts: Vec<Contact>) -> TouchFrame {
+        TouchFrame { time: TimeVal::default(), contacts }
+    }
+
+    #[test]
+    fn classify_test() {
+        let x = AbsInfo { maximum: 1000, resolution: 10, ..Default::default() };
+        let mut palm = PalmRejection::new(x).with_config(PalmConfig {
+            max_size_mm: Some(20.0),
+            max_pressure: Some(200),
+            edge_mm: Some(3.0),
+        });
+        let finger = Contact { touch_major: 80, ..Contact::new(0, 1, 500, 500) };
+        let big = Contact { touch_major: 300, ..Contact::new(1, 2, 300, 300) };
+        let pen = Contact { tool_type: MT_TOOL_PEN, ..Contact::new(2, 3, 600, 600) };
+        let edge = Contact::new(3, 4, 990, 500);
+        let out = palm.filter(frame(vec![finger, big, pen, edge]));
+        assert_eq!(vec![1, 3], out.contacts.iter().map(|c| c.tracking_id).collect::<Vec<_>>());
+        assert_eq!(Some(ContactClass::Pen), palm.class(3));
+
+        // The finger is pressed flat and becomes a palm
+        let pressed = Contact { state: ContactState::Move, pressure: 250, ..finger };
+        let out = palm.filter(frame(vec![pressed]));
+        assert_eq!(ContactState::Up, out.contacts[0].state);
+        // Its real lift and the big contact's are not reported again
+        let out = palm.filter(frame(vec![Contact { state: ContactState::Up, ..pressed },
+                                         Contact { state: ContactState::Up, ..big }]));
+        assert!(out.contacts.is_empty());
+
+        let reasons: Vec<_> = palm.take_decisions().iter().map(|d| (d.tracking_id, d.class, d.reason)).collect();
+        assert_eq!(vec![
+            (1, ContactClass::Finger, None),
+            (2, ContactClass::Palm, Some(PalmReason::Size)),
+            (3, ContactClass::Pen, None),
+            (4, ContactClass::Palm, Some(PalmReason::Edge)),
+            (1, ContactClass::Palm, Some(PalmReason::Pressure)),
+        ], reasons);
+    }
+}
@@ -0,0 +1,329 @@
+//! Multitouch protocol A to protocol B conversion.
+//!
+//! Protocol A devices report every contact anonymously in each frame,
+//! separated by `SYN_MT_REPORT`. `ProtocolA` gives the contacts tracking IDs
+//! by matching each one to the nearest contact of the previous frame, and
+//! reports them with `ABS_MT_SLOT`/`ABS_MT_TRACKING_ID` like a protocol B
+//! device.
+
+use std::collections::VecDeque;
+
+use codes::*;
+use source::{AbsInfo, EventSource};
+use {EvdevEvent, Error, TimeVal};
+
+// Tracking IDs some protocol A devices send are collected with the axes, as
+// a matching hint; they are never passed on.
+fn is_contact_axis(code: AbsCode) -> bool {
+    let num = code as u16;
+    num >= AbsCode::ABS_MT_TOUCH_MAJOR as u16 && num <= AbsCode::ABS_MT_TOOL_Y as u16
+        && code != AbsCode::ABS_MT_SLOT
+}
+
+type Point = Vec<(AbsCode, i32)>;
+
+fn axis(point: &[(AbsCode, i32)], code: AbsCode) -> Option<i32> {
+    point.iter().find(|&&(c, _)| c == code).map(|&(_, v)| v)
+}
+
+fn position(point: &[(AbsCode, i32)]) -> (f64, f64) {
+    (f64::from(axis(point, AbsCode::ABS_MT_POSITION_X).unwrap_or(0)),
+     f64::from(axis(point, AbsCode::ABS_MT_POSITION_Y).unwrap_or(0)))
+}
+
+#[derive(Clone, Debug)]
+struct Tracked {
+    slot: i32,
+    point: Point,
+}
+
+/// Converts protocol A events to protocol B. Other events pass through.
+#[derive(Clone, Debug)]
+pub struct ProtocolA {
+    current: Point,
+    points: Vec<Point>,
+    tracked: Vec<Tracked>,
+    next_id: i32,
+    max_distance: Option<f64>,
+    max_slots: i32,
+}
+
+impl Default for ProtocolA {
+    fn default() -> Self {
+        ProtocolA::new()
+    }
+}
+
+impl ProtocolA {
+    pub fn new() -> ProtocolA {
+        ProtocolA {
+            current: Vec::new(),
+            points: Vec::new(),
+            tracked: Vec::new(),
+            next_id: 0,
+            max_distance: None,
+            max_slots: 10,
+        }
+    }
+
+    /// Contacts that moved further than this many device units between frames
+    /// are taken to be a new contact.
+    pub fn with_max_distance(mut self, units: f64) -> Self {
+        self.max_distance = Some(units);
+        self
+    }
+
+    /// Contacts beyond this many are dropped. Defaults to 10.
+    pub fn with_max_slots(mut self, slots: i32) -> Self {
+        self.max_slots = slots;
+        self
+    }
+
+    pub fn max_slots(&self) -> i32 {
+        self.max_slots
+    }
+
+    /// Feeds one event and returns the events to pass on. Contact updates are
+    /// held back until the frame's `SYN_REPORT`.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Vec<EvdevEvent> {
+        match ev.ev.code {
+            EvdevCode::AbsCode(code) if is_contact_axis(code) => {
+                match self.current.iter_mut().find(|e| e.0 == code) {
+                    Some(entry) => entry.1 = ev.ev.val,
+                    None => self.current.push((code, ev.ev.val)),
+                }
+                Vec::new()
+            }
+            EvdevCode::SynCode(SynCode::SYN_MT_REPORT) => {
+                if !self.current.is_empty() {
+                    let point = ::std::mem::take(&mut self.current);
+                    self.points.push(point);
+                }
+                Vec::new()
+            }
+            EvdevCode::SynCode(SynCode::SYN_REPORT) => {
+                let mut out = self.flush(ev.time);
+                out.push(ev.clone());
+                out
+            }
+            _ => vec![ev.clone()],
+        }
+    }
+
+    fn flush(&mut self, time: TimeVal) -> Vec<EvdevEvent> {
+        self.current.clear();
+        let points = ::std::mem::take(&mut self.points);
+
+        // Greedy nearest-neighbour matching, closest pairs first
+        let mut pairs = Vec::new();
+        for (t, tracked) in self.tracked.iter().enumerate() {
+            let (tx, ty) = position(&tracked.point);
+            for (p, point) in points.iter().enumerate() {
+                let ids = (axis(&tracked.point, AbsCode::ABS_MT_TRACKING_ID), axis(point, AbsCode::ABS_MT_TRACKING_ID));
+                match ids {
+                    // The device's own tracking wins over distance
+                    (Some(a), Some(b)) if a == b => pairs.push((-1.0, t, p)),
+                    (Some(_), Some(_)) => {}
+                    _ => {
+                        let (px, py) = position(point);
+                        let dist = (px - tx).hypot(py - ty);
+                        if self.max_distance.is_none_or(|max| dist <= max) {
+                            pairs.push((dist, t, p));
+                        }
+                    }
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut matched_tracked = vec![None; self.tracked.len()];
+        let mut matched_point = vec![false; points.len()];
+        for (_, t, p) in pairs {
+            if matched_tracked[t].is_none() && !matched_point[p] {
+                matched_tracked[t] = Some(p);
+                matched_point[p] = true;
+            }
+        }
+
+        let mut out = Vec::new();
+        let abs = |out: &mut Vec<EvdevEvent>, code: AbsCode, val: i32| {
+            out.push(EvdevEvent::new(time, EvdevCode::AbsCode(code), val));
+        };
+        let old = ::std::mem::take(&mut self.tracked);
+        for (tracked, matched) in old.into_iter().zip(matched_tracked) {
+            abs(&mut out, AbsCode::ABS_MT_SLOT, tracked.slot);
+            match matched {
+                Some(p) => {
+                    for &(code, val) in &points[p] {
+                        if code != AbsCode::ABS_MT_TRACKING_ID && axis(&tracked.point, code) != Some(val) {
+                            abs(&mut out, code, val);
+                        }
+                    }
+                    self.tracked.push(Tracked { point: points[p].clone(), ..tracked });
+                }
+                None => abs(&mut out, AbsCode::ABS_MT_TRACKING_ID, -1),
+            }
+        }
+        for (point, _) in points.into_iter().zip(matched_point).filter(|&(_, m)| !m) {
+            let slot = match (0..self.max_slots).find(|s| self.tracked.iter().all(|t| t.slot != *s)) {
+                Some(slot) => slot,
+                None => break,
+            };
+            let id = self.next_id;
+            self.next_id = (self.next_id + 1) & 0xffff;
+            abs(&mut out, AbsCode::ABS_MT_SLOT, slot);
+            abs(&mut out, AbsCode::ABS_MT_TRACKING_ID, id);
+            for &(code, val) in point.iter().filter(|&&(code, _)| code != AbsCode::ABS_MT_TRACKING_ID) {
+                abs(&mut out, code, val);
+            }
+            self.tracked.push(Tracked { slot, point });
+        }
+        self.tracked.sort_by_key(|t| t.slot);
+        out
+    }
+}
+
+/// Wraps a protocol A source so it reads like a protocol B one.
+pub struct ProtocolASource<S> {
+    src: S,
+    conv: ProtocolA,
+    queue: VecDeque<EvdevEvent>,
+}
+
+impl<S: EventSource> ProtocolASource<S> {
+    pub fn new(src: S) -> ProtocolASource<S> {
+        ProtocolASource::with_converter(src, ProtocolA::new())
+    }
+
+    pub fn with_converter(src: S, conv: ProtocolA) -> ProtocolASource<S> {
+        ProtocolASource { src, conv, queue: VecDeque::new() }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.src
+    }
+}
+
+impl<S: EventSource> EventSource for ProtocolASource<S> {
+    fn name(&self) -> &str {
+        self.src.name()
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        while self.queue.is_empty() {
+            match self.src.read_event()? {
+                Some(ev) => self.queue.extend(self.conv.process(&ev)),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.queue.pop_front())
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.src.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        match code {
+            EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT) | EvdevCode::AbsCode(AbsCode::ABS_MT_TRACKING_ID) => true,
+            EvdevCode::SynCode(SynCode::SYN_MT_REPORT) => false,
+            code => self.src.has_event_code(code),
+        }
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.src.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        match code {
+            AbsCode::ABS_MT_SLOT => Some(AbsInfo { maximum: self.conv.max_slots() - 1, ..Default::default() }),
+            AbsCode::ABS_MT_TRACKING_ID => Some(AbsInfo { maximum: 0xffff, ..Default::default() }),
+            code => self.src.abs_info(code),
+        }
+    }
+
+    fn raw_fd(&self) -> Option<::std::os::unix::io::RawFd> {
+        self.src.raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+    use touch::{ContactState, TouchFrame, TouchTracker};
+
+    fn report(src: &mut MockSource, x: i32, y: i32) {
+        src.abs(AbsCode::ABS_MT_POSITION_X, x)
+            .abs(AbsCode::ABS_MT_POSITION_Y, y)
+            .push(EvdevCode::SynCode(SynCode::SYN_MT_REPORT), 0);
+    }
+
+    #[test]
+    fn nearest_neighbour_test() {
+        let mut src = MockSource::new(DeviceDescription::new("protocol A"));
+        report(&mut src, 100, 100);
+        report(&mut src, 500, 500);
+        src.sync().advance(10_000);
+        // Reported in the other order
+        report(&mut src, 510, 505);
+        report(&mut src, 110, 100);
+        src.sync().advance(10_000);
+        report(&mut src, 520, 510);
+        src.sync().advance(10_000);
+        src.push(EvdevCode::SynCode(SynCode::SYN_MT_REPORT), 0).sync();
+
+        let mut src = ProtocolASource::new(src);
+        assert!(src.has_event_code(EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT)));
+        let mut tracker = TouchTracker::new();
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(2, frame.active_count());
+        let (a, b) = (frame.contacts[0].tracking_id, frame.contacts[1].tracking_id);
+        assert_eq!(100, frame.contact(a).unwrap().x);
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!((110, 100), (frame.contact(a).unwrap().x, frame.contact(a).unwrap().y));
+        assert_eq!((510, 505), (frame.contact(b).unwrap().x, frame.contact(b).unwrap().y));
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(ContactState::Up, frame.contact(a).unwrap().state);
+        assert_eq!(520, frame.contact(b).unwrap().x);
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(ContactState::Up, frame.contact(b).unwrap().state);
+        assert!(tracker.contacts().is_empty());
+    }
+
+    #[test]
+    fn tracking_id_test() {
+        let mut src = MockSource::new(DeviceDescription::new("protocol A"));
+        let contact = |src: &mut MockSource, id: i32, x: i32| {
+            src.abs(AbsCode::ABS_MT_TRACKING_ID, id);
+            report(src, x, 100);
+        };
+        // The first tracking ID comes before the device shows it's protocol A
+        contact(&mut src, 7, 100);
+        contact(&mut src, 8, 200);
+        src.sync().advance(10_000);
+        // Crossing over: closest is not the same contact
+        contact(&mut src, 8, 110);
+        contact(&mut src, 7, 190);
+        src.sync();
+
+        let mut tracker = TouchTracker::new();
+        let mut frames = Vec::new();
+        while let Some(frame) = tracker.next_frame(&mut src).unwrap() {
+            frames.push(frame);
+        }
+        let xs = |frame: &TouchFrame| {
+            let mut xs: Vec<_> = frame.contacts.iter().map(|c| (c.tracking_id, c.state, c.x)).collect();
+            xs.sort_by_key(|c| c.0);
+            xs
+        };
+        assert_eq!(2, frames.len());
+        assert_eq!(vec![(0, ContactState::Down, 100), (1, ContactState::Down, 200)], xs(&frames[0]));
+        assert_eq!(vec![(0, ContactState::Move, 190), (1, ContactState::Move, 110)], xs(&frames[1]));
+    }
+}
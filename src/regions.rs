@@ -0,0 +1,229 @@
+//! Hit-testing touches against named screen rectangles.
+//!
+//! A contact that goes down inside a region captures it: the region gets the
+//! press, and then either the release, or a cancel as soon as the contact
+//! leaves it. Later regions are on top of earlier ones.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use calibration::Calibration;
+use source::EventSource;
+use touch::{Contact, ContactState, TouchFrame, TouchTracker};
+use Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RegionAction {
+    Press,
+    Release,
+    /// The contact left the region, or the region was removed, while pressed.
+    Cancel,
+}
+
+type Callback = Box<dyn FnMut(RegionAction, &Contact)>;
+
+struct Region {
+    name: String,
+    rect: Rect,
+    callback: Callback,
+}
+
+/// The registered regions and the contacts currently captured by them.
+#[derive(Default)]
+pub struct Regions {
+    regions: Vec<Region>,
+    // Tracking id to the name of the region it captured
+    captures: HashMap<i32, String>,
+    calibration: Option<Calibration>,
+    tracker: TouchTracker,
+}
+
+impl fmt::Debug for Regions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Regions")
+            .field("regions", &self.regions.iter().map(|r| (&r.name, r.rect)).collect::<Vec<_>>())
+            .field("captures", &self.captures)
+            .finish()
+    }
+}
+
+impl Regions {
+    pub fn new() -> Regions {
+        Regions::default()
+    }
+
+    /// Maps incoming frames to screen pixels before hit-testing. Without a
+    /// calibration, frames must already be in screen coordinates.
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Registers a region on top of the existing ones, replacing any region
+    /// of the same name.
+    pub fn add<F>(&mut self, name: &str, rect: Rect, callback: F)
+        where F: FnMut(RegionAction, &Contact) + 'static
+    {
+        self.remove(name);
+        self.regions.push(Region { name: name.to_string(), rect, callback: Box::new(callback) });
+    }
+
+    /// Removes a region, cancelling the contacts it captured. Returns whether
+    /// it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let idx = match self.regions.iter().position(|r| r.name == name) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let mut region = self.regions.remove(idx);
+        let cancelled: Vec<i32> = self.captures.iter()
+            .filter(|&(_, n)| *n == name)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in cancelled {
+            self.captures.remove(&id);
+            let contact = Contact::new(-1, id, region.rect.x, region.rect.y);
+            (region.callback)(RegionAction::Cancel, &contact);
+        }
+        true
+    }
+
+    pub fn rect(&self, name: &str) -> Option<Rect> {
+        self.regions.iter().find(|r| r.name == name).map(|r| r.rect)
+    }
+
+    /// The topmost region containing the point.
+    pub fn hit(&self, x: i32, y: i32) -> Option<&str> {
+        self.regions.iter().rev().find(|r| r.rect.contains(x, y)).map(|r| &r.name[..])
+    }
+
+    /// Whether some contact is pressing the region.
+    pub fn is_pressed(&self, name: &str) -> bool {
+        self.captures.values().any(|n| n == name)
+    }
+
+    /// Dispatches one frame, mapping it through the calibration first.
+    pub fn process(&mut self, frame: &TouchFrame) {
+        let mapped;
+        let frame = match self.calibration {
+            Some(ref cal) => {
+                mapped = cal.map_frame(frame);
+                &mapped
+            }
+            None => frame,
+        };
+        for c in &frame.contacts {
+            self.dispatch(c);
+        }
+    }
+
+    fn dispatch(&mut self, c: &Contact) {
+        let action = match (c.state, self.captures.get(&c.tracking_id)) {
+            (ContactState::Down, None) => match self.hit(c.x, c.y) {
+                Some(name) => {
+                    let name = name.to_string();
+                    self.captures.insert(c.tracking_id, name);
+                    RegionAction::Press
+                }
+                None => return,
+            },
+            // Lifted or dragged off the region
+            (_, Some(name)) if !self.rect(name).is_some_and(|r| r.contains(c.x, c.y)) => RegionAction::Cancel,
+            (ContactState::Up, Some(_)) => RegionAction::Release,
+            _ => return,
+        };
+        let name = match action {
+            RegionAction::Press => self.captures[&c.tracking_id].clone(),
+            _ => self.captures.remove(&c.tracking_id).unwrap(),
+        };
+        if let Some(region) = self.regions.iter_mut().find(|r| r.name == name) {
+            (region.callback)(action, c);
+        }
+    }
+
+    /// Reads the next touch frame from `src` and dispatches it. Returns the
+    /// frame, or `None` once the source runs dry.
+    pub fn next_frame<S: EventSource>(&mut self, src: &mut S) -> Result<Option<TouchFrame>, Error> {
+        let frame = self.tracker.next_frame(src)?;
+        if let Some(ref frame) = frame {
+            self.process(frame);
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use TimeVal;
+
+    fn frame(contacts: Vec<Contact>) -> TouchFrame {
+        TouchFrame { time: TimeVal::default(), contacts }
+    }
+
+    fn at(id: i32, state: ContactState, x: i32, y: i32) -> Contact {
+        Contact { state, ..Contact::new(0, id, x, y) }
+    }
+
+    #[test]
+    fn capture_test() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut regions = Regions::new();
+        for &(name, rect) in &[("ok", Rect::new(0, 0, 100, 50)), ("popup", Rect::new(50, 0, 100, 50))] {
+            let log = log.clone();
+            regions.add(name, rect, move |action, _| log.borrow_mut().push((name, action)));
+        }
+        assert_eq!(Some("popup"), regions.hit(60, 10));
+
+        regions.process(&frame(vec![at(1, ContactState::Down, 10, 10)]));
+        assert!(regions.is_pressed("ok"));
+        regions.process(&frame(vec![at(1, ContactState::Move, 20, 10)]));
+        regions.process(&frame(vec![at(1, ContactState::Up, 20, 10)]));
+
+        // Dragged off the button, then released elsewhere
+        regions.process(&frame(vec![at(2, ContactState::Down, 60, 10)]));
+        regions.process(&frame(vec![at(2, ContactState::Move, 60, 80)]));
+        regions.process(&frame(vec![at(2, ContactState::Up, 60, 20)]));
+
+        // Lifted off the button without a move in between
+        regions.process(&frame(vec![at(4, ContactState::Down, 10, 10)]));
+        regions.process(&frame(vec![at(4, ContactState::Up, 10, 80)]));
+
+        regions.process(&frame(vec![at(3, ContactState::Down, 10, 10)]));
+        assert!(regions.remove("ok"));
+        assert!(!regions.remove("ok"));
+
+        assert_eq!(vec![
+            ("ok", RegionAction::Press),
+            ("ok", RegionAction::Release),
+            ("popup", RegionAction::Press),
+            ("popup", RegionAction::Cancel),
+            ("ok", RegionAction::Press),
+            ("ok", RegionAction::Cancel),
+            ("ok", RegionAction::Press),
+            ("ok", RegionAction::Cancel),
+        ], *log.borrow());
+    }
+}
@@ -0,0 +1,110 @@
+use libevdev_sys::linux_input::input_event;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::{mem, ptr, slice};
+
+use codes::*;
+use source::{AbsInfo, DeviceDescription, EventSource};
+use {EvdevEvent, Error};
+
+const RAW_EVENT_SIZE: usize = mem::size_of::<input_event>();
+
+/// Reads one kernel `struct input_event`, as found in a dump of `/dev/input/eventN`.
+/// Returns `None` on a clean end of stream.
+pub fn read_raw_event<R: Read>(reader: &mut R) -> io::Result<Option<input_event>> {
+    let mut buf = [0u8; RAW_EVENT_SIZE];
+    let mut filled = 0;
+    while filled < RAW_EVENT_SIZE {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated input_event")),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(unsafe { ptr::read_unaligned(buf.as_ptr() as *const input_event) }))
+}
+
+pub fn write_raw_event<W: Write>(writer: &mut W, ev: &EvdevEvent) -> io::Result<()> {
+    let raw = ev.to_raw();
+    let bytes = unsafe { slice::from_raw_parts(&raw as *const input_event as *const u8, RAW_EVENT_SIZE) };
+    writer.write_all(bytes)
+}
+
+/// Replays a raw event dump. The dump carries no capabilities, so those come
+/// from the `DeviceDescription` given on construction.
+pub struct ReplaySource<R> {
+    reader: R,
+    desc: DeviceDescription,
+}
+
+impl<R: Read> ReplaySource<R> {
+    pub fn new(reader: R, desc: DeviceDescription) -> ReplaySource<R> {
+        ReplaySource {
+            reader,
+            desc,
+        }
+    }
+
+    pub fn description(&self) -> &DeviceDescription {
+        &self.desc
+    }
+}
+
+impl ReplaySource<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P, desc: DeviceDescription) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(ReplaySource::new(BufReader::new(file), desc))
+    }
+}
+
+impl<R: Read> EventSource for ReplaySource<R> {
+    fn name(&self) -> &str {
+        &self.desc.name
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        Ok(read_raw_event(&mut self.reader)?.map(EvdevEvent::from))
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.desc.has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        self.desc.has_event_code(code)
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        self.desc.has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        self.desc.abs_info(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use TimeVal;
+
+    #[test]
+    fn raw_roundtrip_test() {
+        let events = vec![
+            EvdevEvent::new(TimeVal::new(12, 345), EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT), 1),
+            EvdevEvent::new(TimeVal::new(12, 345), EvdevCode::SynCode(SynCode::SYN_REPORT), 0),
+        ];
+        let mut buf = Vec::new();
+        for ev in &events {
+            write_raw_event(&mut buf, ev).unwrap();
+        }
+
+        let mut replay = ReplaySource::new(&buf[..], DeviceDescription::new("dump"));
+        assert_eq!(Some(events[0].clone()), replay.read_event().unwrap());
+        assert_eq!(Some(events[1].clone()), replay.read_event().unwrap());
+        assert_eq!(None, replay.read_event().unwrap());
+    }
+}
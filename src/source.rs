@@ -0,0 +1,216 @@
+use libevdev_sys::linux_input::input_absinfo;
+use num::FromPrimitive;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::RawFd;
+
+use codes::*;
+use {EvdevEvent, EvdevFrame, Error};
+
+/// Range and resolution of an absolute axis, as reported by `EVIOCGABS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AbsInfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    /// Units per millimetre (units per radian for rotational axes), 0 if unknown.
+    pub resolution: i32,
+}
+
+impl AbsInfo {
+    /// The resolution, or 1 if unknown so that a device unit counts as a
+    /// millimetre.
+    pub fn units_per_mm(&self) -> f64 {
+        if self.resolution > 0 { f64::from(self.resolution) } else { 1.0 }
+    }
+}
+
+impl From<input_absinfo> for AbsInfo {
+    fn from(info: input_absinfo) -> Self {
+        AbsInfo {
+            value: info.value,
+            minimum: info.minimum,
+            maximum: info.maximum,
+            fuzz: info.fuzz,
+            flat: info.flat,
+            resolution: info.resolution,
+        }
+    }
+}
+
+/// Anything that produces evdev events: a live device, a recording or a mock.
+pub trait EventSource {
+    fn name(&self) -> &str;
+
+    /// Next event, or `None` if no event is available (end of a replay, empty
+    /// non-blocking device).
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error>;
+
+    fn has_event_type(&self, ty: TypeCode) -> bool;
+    fn has_event_code(&self, code: EvdevCode) -> bool;
+    fn has_property(&self, prop: PropCode) -> bool;
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo>;
+
+    /// File descriptor to wait on for new events, if the source has one.
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Reads events up to the next `SYN_REPORT`. A trailing incomplete frame is
+    /// returned as is when the source runs dry.
+    fn read_frame(&mut self) -> Result<Option<EvdevFrame>, Error> {
+        let mut events = Vec::new();
+        loop {
+            match self.read_event()? {
+                Some(ev) => {
+                    if ev.is_syn_report() {
+                        return Ok(Some(EvdevFrame { time: ev.time, events }));
+                    }
+                    events.push(ev);
+                }
+                None if events.is_empty() => return Ok(None),
+                None => {
+                    let time = events[events.len() - 1].time;
+                    return Ok(Some(EvdevFrame { time, events }));
+                }
+            }
+        }
+    }
+}
+
+impl<S: EventSource + ?Sized> EventSource for &mut S {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn read_event(&mut self) -> Result<Option<EvdevEvent>, Error> {
+        (**self).read_event()
+    }
+
+    fn has_event_type(&self, ty: TypeCode) -> bool {
+        (**self).has_event_type(ty)
+    }
+
+    fn has_event_code(&self, code: EvdevCode) -> bool {
+        (**self).has_event_code(code)
+    }
+
+    fn has_property(&self, prop: PropCode) -> bool {
+        (**self).has_property(prop)
+    }
+
+    fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        (**self).abs_info(code)
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        (**self).raw_fd()
+    }
+
+    fn read_frame(&mut self) -> Result<Option<EvdevFrame>, Error> {
+        (**self).read_frame()
+    }
+}
+
+/// The x and y position axes of a touch device: the multitouch ones if the
+/// device has them, the single-touch ones otherwise.
+pub fn position_axes<S: EventSource + ?Sized>(src: &S) -> Result<(AbsInfo, AbsInfo), Error> {
+    let axes = |x, y| src.abs_info(x).and_then(|x| src.abs_info(y).map(|y| (x, y)));
+    axes(AbsCode::ABS_MT_POSITION_X, AbsCode::ABS_MT_POSITION_Y)
+        .or_else(|| axes(AbsCode::ABS_X, AbsCode::ABS_Y))
+        .ok_or_else(|| Error(format!("{} has no absolute position axes", src.name())))
+}
+
+/// Devices whose LEDs can be set, such as keyboards.
+pub trait LedControl {
+    fn set_led(&mut self, led: LedCode, on: bool) -> Result<(), Error>;
+
+    /// Whether `led` is lit, or `None` if the device has no such LED.
+    fn led(&self, led: LedCode) -> Option<bool>;
+}
+
+/// A static description of a device's capabilities, used by sources that have
+/// no kernel device behind them.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceDescription {
+    pub name: String,
+    pub codes: HashSet<EvdevCode>,
+    pub props: HashSet<PropCode>,
+    pub abs: HashMap<AbsCode, AbsInfo>,
+}
+
+impl DeviceDescription {
+    pub fn new(name: &str) -> DeviceDescription {
+        DeviceDescription {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_code(mut self, code: EvdevCode) -> Self {
+        self.codes.insert(code);
+        self
+    }
+
+    pub fn with_property(mut self, prop: PropCode) -> Self {
+        self.props.insert(prop);
+        self
+    }
+
+    pub fn with_abs(mut self, code: AbsCode, info: AbsInfo) -> Self {
+        self.codes.insert(EvdevCode::AbsCode(code));
+        self.abs.insert(code, info);
+        self
+    }
+
+    /// Snapshots the capabilities of another source, e.g. before recording it.
+    pub fn from_source<S: EventSource + ?Sized>(src: &S) -> DeviceDescription {
+        let mut desc = DeviceDescription::new(src.name());
+        for num in 0..0x300u16 {
+            for &ty in &[TypeCode::EV_SYN, TypeCode::EV_KEY, TypeCode::EV_REL, TypeCode::EV_ABS,
+                         TypeCode::EV_MSC, TypeCode::EV_SW, TypeCode::EV_LED, TypeCode::EV_SND,
+                         TypeCode::EV_REP] {
+                let code = EvdevCode::from_raw(ty as u16, num);
+                if let Some(code) = code {
+                    if src.has_event_code(code) {
+                        desc.codes.insert(code);
+                    }
+                }
+            }
+        }
+        for num in 0..0x20u16 {
+            if let Some(prop) = PropCode::from_u16(num) {
+                if src.has_property(prop) {
+                    desc.props.insert(prop);
+                }
+            }
+        }
+        for num in 0..0x40u16 {
+            if let Some(code) = AbsCode::from_u16(num) {
+                if let Some(info) = src.abs_info(code) {
+                    desc.abs.insert(code, info);
+                }
+            }
+        }
+        desc
+    }
+
+    pub fn has_event_type(&self, ty: TypeCode) -> bool {
+        self.codes.iter().any(|c| c.type_code() == Some(ty))
+    }
+
+    pub fn has_event_code(&self, code: EvdevCode) -> bool {
+        self.codes.contains(&code)
+    }
+
+    pub fn has_property(&self, prop: PropCode) -> bool {
+        self.props.contains(&prop)
+    }
+
+    pub fn abs_info(&self, code: AbsCode) -> Option<AbsInfo> {
+        self.abs.get(&code).cloned()
+    }
+}
@@ -0,0 +1,379 @@
+//! Pens on graphics tablets and pen-enabled panels.
+//!
+//! `StylusTracker` follows the `BTN_TOOL_*` proximity keys, `BTN_TOUCH` and
+//! the stylus buttons, and reports what changed in each frame as
+//! `StylusEvent`s. Tilt is converted to degrees and pressure is normalised
+//! and mapped through a `PressureCurve`.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use codes::*;
+use source::{AbsInfo, EventSource};
+use {EvdevEvent, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ToolType {
+    Pen,
+    Eraser,
+    Brush,
+    Pencil,
+    Airbrush,
+    Mouse,
+    Lens,
+}
+
+impl ToolType {
+    pub fn from_key(key: KeyCode) -> Option<ToolType> {
+        match key {
+            KeyCode::BTN_TOOL_PEN => Some(ToolType::Pen),
+            KeyCode::BTN_TOOL_RUBBER => Some(ToolType::Eraser),
+            KeyCode::BTN_TOOL_BRUSH => Some(ToolType::Brush),
+            KeyCode::BTN_TOOL_PENCIL => Some(ToolType::Pencil),
+            KeyCode::BTN_TOOL_AIRBRUSH => Some(ToolType::Airbrush),
+            KeyCode::BTN_TOOL_MOUSE => Some(ToolType::Mouse),
+            KeyCode::BTN_TOOL_LENS => Some(ToolType::Lens),
+            _ => None,
+        }
+    }
+}
+
+/// Axis values of the tool in proximity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ToolAxes {
+    pub x: i32,
+    pub y: i32,
+    /// 0 to 1, after the pressure curve.
+    pub pressure: f64,
+    /// Raw `ABS_DISTANCE`, 0 if the device has none.
+    pub distance: i32,
+    /// `ABS_TILT_X` and `ABS_TILT_Y` in degrees, positive to the right and
+    /// towards the user.
+    pub tilt: (f64, f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StylusEvent {
+    ProximityIn { tool: ToolType, axes: ToolAxes },
+    ProximityOut { tool: ToolType },
+    Tip { down: bool, axes: ToolAxes },
+    /// `BTN_STYLUS`, `BTN_STYLUS2` or `BTN_STYLUS3`.
+    Button { button: KeyCode, pressed: bool },
+    /// Axes changed without a proximity or tip change.
+    Axis { axes: ToolAxes },
+}
+
+/// A cubic Bézier from (0, 0) to (1, 1) through two control points, like CSS
+/// `cubic-bezier()` and the pressure curves of desktop tablet settings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PressureCurve {
+    pub p1: (f64, f64),
+    pub p2: (f64, f64),
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        PressureCurve::linear()
+    }
+}
+
+fn bezier(a: f64, b: f64, t: f64) -> f64 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * a + 3.0 * u * t * t * b + t * t * t
+}
+
+impl PressureCurve {
+    /// Control point x coordinates are clamped to 0..1 so the curve stays a
+    /// function of pressure.
+    pub fn new(p1: (f64, f64), p2: (f64, f64)) -> PressureCurve {
+        PressureCurve {
+            p1: (p1.0.clamp(0.0, 1.0), p1.1),
+            p2: (p2.0.clamp(0.0, 1.0), p2.1),
+        }
+    }
+
+    pub fn linear() -> PressureCurve {
+        PressureCurve::new((0.0, 0.0), (1.0, 1.0))
+    }
+
+    /// Maps a pressure in 0..1.
+    pub fn map(&self, pressure: f64) -> f64 {
+        let p = pressure.clamp(0.0, 1.0);
+        if p == 0.0 || p == 1.0 {
+            return p;
+        }
+        // x(t) rises monotonically, so bisect for the t that gives p
+        let (mut lo, mut hi) = (0.0, 1.0);
+        for _ in 0..40 {
+            let mid = (lo + hi) / 2.0;
+            if bezier(self.p1.0, self.p2.0, mid) < p {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        bezier(self.p1.1, self.p2.1, (lo + hi) / 2.0).clamp(0.0, 1.0)
+    }
+}
+
+const BUTTONS: [KeyCode; 3] = [KeyCode::BTN_STYLUS, KeyCode::BTN_STYLUS2, KeyCode::BTN_STYLUS3];
+
+/// Turns tablet events into `StylusEvent`s.
+#[derive(Clone, Debug, Default)]
+pub struct StylusTracker {
+    abs: HashMap<AbsCode, AbsInfo>,
+    curve: PressureCurve,
+    values: HashMap<AbsCode, i32>,
+    dirty: bool,
+    tool: Option<ToolType>,
+    // Proximity keys seen in this frame
+    tool_keys: Vec<(ToolType, bool)>,
+    touching: bool,
+    touch_key: Option<bool>,
+    buttons: Vec<KeyCode>,
+    button_keys: Vec<(KeyCode, bool)>,
+    reported: Option<ToolAxes>,
+}
+
+impl StylusTracker {
+    pub fn new() -> StylusTracker {
+        StylusTracker::default()
+    }
+
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> StylusTracker {
+        let mut tracker = StylusTracker::new();
+        for &code in &[AbsCode::ABS_X, AbsCode::ABS_Y, AbsCode::ABS_PRESSURE,
+                       AbsCode::ABS_DISTANCE, AbsCode::ABS_TILT_X, AbsCode::ABS_TILT_Y] {
+            if let Some(info) = src.abs_info(code) {
+                tracker = tracker.with_abs(code, info);
+            }
+        }
+        tracker
+    }
+
+    /// Range and resolution of an axis. Pressure needs its range to be
+    /// normalised; tilt without a resolution is taken to span ±64°, as in
+    /// libinput.
+    pub fn with_abs(mut self, code: AbsCode, info: AbsInfo) -> Self {
+        self.abs.insert(code, info);
+        self.values.insert(code, info.value);
+        self
+    }
+
+    pub fn with_pressure_curve(mut self, curve: PressureCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// The tool in proximity.
+    pub fn tool(&self) -> Option<ToolType> {
+        self.tool
+    }
+
+    pub fn is_touching(&self) -> bool {
+        self.touching
+    }
+
+    pub fn axes(&self) -> ToolAxes {
+        let value = |code| self.values.get(&code).cloned().unwrap_or(0);
+        let pressure = match self.abs.get(&AbsCode::ABS_PRESSURE) {
+            Some(info) if info.maximum > info.minimum => {
+                let p = f64::from(value(AbsCode::ABS_PRESSURE) - info.minimum)
+                    / f64::from(info.maximum - info.minimum);
+                self.curve.map(p)
+            }
+            _ => 0.0,
+        };
+        ToolAxes {
+            x: value(AbsCode::ABS_X),
+            y: value(AbsCode::ABS_Y),
+            pressure,
+            distance: value(AbsCode::ABS_DISTANCE),
+            tilt: (self.tilt(AbsCode::ABS_TILT_X), self.tilt(AbsCode::ABS_TILT_Y)),
+        }
+    }
+
+    fn tilt(&self, code: AbsCode) -> f64 {
+        let value = f64::from(self.values.get(&code).cloned().unwrap_or(0));
+        match self.abs.get(&code) {
+            Some(info) if info.resolution > 0 => value / f64::from(info.resolution) * 180.0 / PI,
+            Some(info) if info.maximum > info.minimum => {
+                let (min, max) = (f64::from(info.minimum), f64::from(info.maximum));
+                ((value - min) / (max - min) * 2.0 - 1.0) * 64.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Feeds one event; returns what changed when a `SYN_REPORT` ends the
+    /// frame.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Vec<StylusEvent> {
+        let pressed = ev.ev.val != 0;
+        match ev.ev.code {
+            EvdevCode::AbsCode(code) => {
+                self.values.insert(code, ev.ev.val);
+                self.dirty = true;
+            }
+            EvdevCode::KeyCode(KeyCode::BTN_TOUCH) => self.touch_key = Some(pressed),
+            EvdevCode::KeyCode(key) if BUTTONS.contains(&key) => self.button_keys.push((key, pressed)),
+            EvdevCode::KeyCode(key) => {
+                if let Some(tool) = ToolType::from_key(key) {
+                    self.tool_keys.push((tool, pressed));
+                }
+            }
+            EvdevCode::SynCode(SynCode::SYN_REPORT) => return self.flush(),
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    /// Reads from `src` until a frame produces events, or until the source
+    /// runs dry.
+    pub fn next_events<S: EventSource>(&mut self, src: &mut S) -> Result<Vec<StylusEvent>, Error> {
+        while let Some(ev) = src.read_event()? {
+            let events = self.process(&ev);
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn flush(&mut self) -> Vec<StylusEvent> {
+        let mut tool = self.tool;
+        for (t, pressed) in self.tool_keys.drain(..) {
+            if pressed {
+                tool = Some(t);
+            } else if tool == Some(t) {
+                tool = None;
+            }
+        }
+        let touching = self.touch_key.take().unwrap_or(self.touching) && tool.is_some();
+        let axes = self.axes();
+        let mut out = Vec::new();
+
+        if tool != self.tool {
+            if let Some(old) = self.tool {
+                // Lift and release everything before leaving
+                if self.touching {
+                    out.push(StylusEvent::Tip { down: false, axes });
+                    self.touching = false;
+                }
+                for button in self.buttons.drain(..) {
+                    out.push(StylusEvent::Button { button, pressed: false });
+                }
+                out.push(StylusEvent::ProximityOut { tool: old });
+                self.reported = None;
+            }
+            if let Some(tool) = tool {
+                out.push(StylusEvent::ProximityIn { tool, axes });
+                self.reported = Some(axes);
+            }
+            self.tool = tool;
+        }
+        if self.tool.is_none() {
+            self.button_keys.clear();
+            self.dirty = false;
+            return out;
+        }
+
+        if touching != self.touching {
+            out.push(StylusEvent::Tip { down: touching, axes });
+            self.touching = touching;
+            self.reported = Some(axes);
+        }
+        for (button, pressed) in self.button_keys.drain(..) {
+            if pressed != self.buttons.contains(&button) {
+                if pressed {
+                    self.buttons.push(button);
+                } else {
+                    self.buttons.retain(|&b| b != button);
+                }
+                out.push(StylusEvent::Button { button, pressed });
+            }
+        }
+        if self.dirty && self.reported != Some(axes) {
+            out.push(StylusEvent::Axis { axes });
+            self.reported = Some(axes);
+        }
+        self.dirty = false;
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn pressure_curve_test() {
+        let linear = PressureCurve::linear();
+        assert!((linear.map(0.3) - 0.3).abs() < 1e-6);
+        // Softer: little force goes a long way
+        let soft = PressureCurve::new((0.0, 0.75), (0.25, 1.0));
+        assert!(soft.map(0.2) > 0.6);
+        assert_eq!(0.0, soft.map(0.0));
+        assert_eq!(1.0, soft.map(1.5));
+    }
+
+    #[test]
+    fn stylus_test() {
+        let desc = DeviceDescription::new("tablet")
+            .with_abs(AbsCode::ABS_X, AbsInfo { maximum: 20000, ..Default::default() })
+            .with_abs(AbsCode::ABS_Y, AbsInfo { maximum: 12000, ..Default::default() })
+            .with_abs(AbsCode::ABS_PRESSURE, AbsInfo { maximum: 2047, ..Default::default() })
+            // 57 units per radian: one unit is about a degree
+            .with_abs(AbsCode::ABS_TILT_X, AbsInfo { minimum: -90, maximum: 90, resolution: 57, ..Default::default() })
+            .with_abs(AbsCode::ABS_TILT_Y, AbsInfo { minimum: -64, maximum: 63, ..Default::default() });
+        let mut src = MockSource::new(desc);
+        src.key(KeyCode::BTN_TOOL_PEN, 1)
+            .abs(AbsCode::ABS_X, 100)
+            .abs(AbsCode::ABS_TILT_X, 57)
+            .sync()
+            .key(KeyCode::BTN_TOUCH, 1)
+            .abs(AbsCode::ABS_PRESSURE, 2047)
+            .sync()
+            .key(KeyCode::BTN_STYLUS, 1)
+            .abs(AbsCode::ABS_X, 110)
+            .sync()
+            .key(KeyCode::BTN_TOUCH, 0)
+            .key(KeyCode::BTN_TOOL_PEN, 0)
+            .abs(AbsCode::ABS_PRESSURE, 0)
+            .sync();
+
+        let mut tracker = StylusTracker::for_source(&src);
+        let events = tracker.next_events(&mut src).unwrap();
+        match events[..] {
+            [StylusEvent::ProximityIn { tool: ToolType::Pen, axes }] => {
+                assert_eq!(100, axes.x);
+                assert!((axes.tilt.0 - 180.0 / PI).abs() < 1e-6);
+                // Without a resolution the range is ±64°
+                assert!(axes.tilt.1.abs() < 1.0);
+            }
+            _ => panic!("{:?}", events),
+        }
+        match tracker.next_events(&mut src).unwrap()[..] {
+            [StylusEvent::Tip { down: true, axes }] => assert_eq!(1.0, axes.pressure),
+            ref events => panic!("{:?}", events),
+        }
+        match tracker.next_events(&mut src).unwrap()[..] {
+            [StylusEvent::Button { button: KeyCode::BTN_STYLUS, pressed: true }, StylusEvent::Axis { axes }] => {
+                assert_eq!(110, axes.x)
+            }
+            ref events => panic!("{:?}", events),
+        }
+        match tracker.next_events(&mut src).unwrap()[..] {
+            [StylusEvent::Tip { down: false, .. },
+             StylusEvent::Button { button: KeyCode::BTN_STYLUS, pressed: false },
+             StylusEvent::ProximityOut { tool: ToolType::Pen }] => {}
+            ref events => panic!("{:?}", events),
+        }
+        assert_eq!(None, tracker.tool());
+    }
+}
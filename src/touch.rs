@@ -0,0 +1,401 @@
+use std::mem;
+
+use codes::*;
+use protocol_a::ProtocolA;
+use source::EventSource;
+use {EvdevEvent, Error, TimeVal};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ContactState {
+    Down,
+    Move,
+    Up,
+}
+
+/// One touch point, assembled from the `ABS_MT_*` values of its slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Contact {
+    pub slot: i32,
+    pub tracking_id: i32,
+    pub state: ContactState,
+    pub x: i32,
+    pub y: i32,
+    pub pressure: i32,
+    pub touch_major: i32,
+    pub touch_minor: i32,
+    pub width_major: i32,
+    pub width_minor: i32,
+    pub orientation: i32,
+    pub tool_type: i32,
+    pub distance: i32,
+}
+
+impl Contact {
+    pub fn new(slot: i32, tracking_id: i32, x: i32, y: i32) -> Contact {
+        Contact {
+            slot,
+            tracking_id,
+            state: ContactState::Down,
+            x,
+            y,
+            pressure: 0,
+            touch_major: 0,
+            touch_minor: 0,
+            width_major: 0,
+            width_minor: 0,
+            orientation: 0,
+            tool_type: 0,
+            distance: 0,
+        }
+    }
+}
+
+/// The contacts touched by one `SYN_REPORT`. Contacts that lifted in this frame
+/// are included once with `ContactState::Up`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TouchFrame {
+    pub time: TimeVal,
+    pub contacts: Vec<Contact>,
+}
+
+impl TouchFrame {
+    pub fn contact(&self, tracking_id: i32) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.tracking_id == tracking_id)
+    }
+
+    /// Number of contacts still on the surface after this frame.
+    pub fn active_count(&self) -> usize {
+        self.contacts.iter().filter(|c| c.state != ContactState::Up).count()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    contact: Contact,
+    active: bool,
+    dirty: bool,
+}
+
+/// Tracks multitouch slots (protocol B) and turns events into `TouchFrame`s.
+///
+/// Devices that only report `ABS_X`/`ABS_Y` and `BTN_TOUCH` are tracked as a
+/// single contact in slot 0. Protocol A devices are detected by their first
+/// `SYN_MT_REPORT` and converted with `ProtocolA`; their single-touch
+/// emulation is ignored.
+pub struct TouchTracker {
+    slots: Vec<Slot>,
+    current: usize,
+    multitouch: bool,
+    // Seen an ABS_MT_SLOT, so this is protocol B
+    slotted: bool,
+    next_id: i32,
+    lifted: Vec<Contact>,
+    protocol_a: ProtocolA,
+    is_protocol_a: bool,
+}
+
+impl Default for TouchTracker {
+    fn default() -> Self {
+        TouchTracker::new()
+    }
+}
+
+impl TouchTracker {
+    pub fn new() -> TouchTracker {
+        TouchTracker {
+            slots: Vec::new(),
+            current: 0,
+            multitouch: false,
+            slotted: false,
+            next_id: 0,
+            lifted: Vec::new(),
+            protocol_a: ProtocolA::new(),
+            is_protocol_a: false,
+        }
+    }
+
+    /// Contacts currently on the surface.
+    pub fn contacts(&self) -> Vec<Contact> {
+        self.slots.iter().filter(|s| s.active).map(|s| s.contact).collect()
+    }
+
+    /// Feeds one event; returns a frame when a `SYN_REPORT` completes a change.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Option<TouchFrame> {
+        if ev.ev.code == EvdevCode::SynCode(SynCode::SYN_MT_REPORT) && !self.is_protocol_a {
+            self.is_protocol_a = true;
+            self.drop_single_touch();
+        }
+        if self.is_protocol_a {
+            let mut frame = None;
+            for converted in self.protocol_a.process(ev) {
+                // The single-touch emulation would show up as another contact
+                if is_single_touch(converted.ev.code) {
+                    continue;
+                }
+                frame = frame.or(self.process_slots(&converted));
+            }
+            return frame;
+        }
+        if ev.ev.code == EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT) {
+            self.slotted = true;
+        }
+        if let (false, EvdevCode::AbsCode(_)) = (self.slotted, ev.ev.code) {
+            // Until a slot shows up this may still be protocol A, whose first
+            // contact is only announced by the SYN_MT_REPORT after its axes,
+            // tracking ID included
+            self.protocol_a.process(ev);
+        }
+        self.process_slots(ev)
+    }
+
+    /// Forgets the single-touch contact tracked before the device turned out
+    /// to be protocol A. It is lifted only if it was already reported.
+    fn drop_single_touch(&mut self) {
+        for slot in &mut self.slots {
+            if slot.active && slot.contact.state != ContactState::Down {
+                let mut old = slot.contact;
+                old.state = ContactState::Up;
+                self.lifted.push(old);
+            }
+            slot.active = false;
+            slot.dirty = false;
+        }
+        self.multitouch = false;
+        self.current = 0;
+    }
+
+    fn process_slots(&mut self, ev: &EvdevEvent) -> Option<TouchFrame> {
+        let val = ev.ev.val;
+        match ev.ev.code {
+            EvdevCode::AbsCode(AbsCode::ABS_MT_SLOT) => {
+                self.multitouch = true;
+                self.current = if val < 0 { 0 } else { val as usize };
+            }
+            EvdevCode::AbsCode(AbsCode::ABS_MT_TRACKING_ID) => {
+                self.multitouch = true;
+                let idx = self.current;
+                self.set_tracking_id(idx, val);
+            }
+            EvdevCode::AbsCode(code) => self.update_axis(code, val),
+            EvdevCode::KeyCode(KeyCode::BTN_TOUCH) if !self.multitouch => {
+                let id = if val == 0 {
+                    -1
+                } else {
+                    self.next_id += 1;
+                    self.next_id
+                };
+                self.set_tracking_id(0, id);
+            }
+            EvdevCode::SynCode(SynCode::SYN_REPORT) => return self.flush(ev.time),
+            _ => {}
+        }
+        None
+    }
+
+    /// Reads from `src` until the next frame with touch changes, or until the
+    /// source runs dry.
+    pub fn next_frame<S: EventSource>(&mut self, src: &mut S) -> Result<Option<TouchFrame>, Error> {
+        while let Some(ev) = src.read_event()? {
+            if let Some(frame) = self.process(&ev) {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    fn slot_mut(&mut self, idx: usize) -> &mut Slot {
+        while self.slots.len() <= idx {
+            let n = self.slots.len() as i32;
+            self.slots.push(Slot {
+                contact: Contact::new(n, -1, 0, 0),
+                active: false,
+                dirty: false,
+            });
+        }
+        &mut self.slots[idx]
+    }
+
+    fn set_tracking_id(&mut self, idx: usize, id: i32) {
+        let mut lifted = None;
+        {
+            let slot = self.slot_mut(idx);
+            if id < 0 {
+                if slot.active {
+                    slot.contact.state = ContactState::Up;
+                    slot.dirty = true;
+                }
+            } else if !slot.active || slot.contact.state == ContactState::Up
+                || slot.contact.tracking_id != id {
+                if slot.active && slot.contact.state != ContactState::Up {
+                    // A new contact replaced the old one without a -1 in between
+                    let mut old = slot.contact;
+                    old.state = ContactState::Up;
+                    lifted = Some(old);
+                }
+                slot.active = true;
+                slot.dirty = true;
+                slot.contact.tracking_id = id;
+                slot.contact.state = ContactState::Down;
+            }
+        }
+        if let Some(old) = lifted {
+            self.lifted.push(old);
+        }
+    }
+
+    fn update_axis(&mut self, code: AbsCode, val: i32) {
+        let idx = match code {
+            AbsCode::ABS_X | AbsCode::ABS_Y | AbsCode::ABS_PRESSURE | AbsCode::ABS_DISTANCE => {
+                if self.multitouch {
+                    return;
+                }
+                0
+            }
+            _ => self.current,
+        };
+        let slot = self.slot_mut(idx);
+        {
+            let c = &mut slot.contact;
+            match code {
+                AbsCode::ABS_MT_POSITION_X | AbsCode::ABS_X => c.x = val,
+                AbsCode::ABS_MT_POSITION_Y | AbsCode::ABS_Y => c.y = val,
+                AbsCode::ABS_MT_PRESSURE | AbsCode::ABS_PRESSURE => c.pressure = val,
+                AbsCode::ABS_MT_DISTANCE | AbsCode::ABS_DISTANCE => c.distance = val,
+                AbsCode::ABS_MT_TOUCH_MAJOR => c.touch_major = val,
+                AbsCode::ABS_MT_TOUCH_MINOR => c.touch_minor = val,
+                AbsCode::ABS_MT_WIDTH_MAJOR => c.width_major = val,
+                AbsCode::ABS_MT_WIDTH_MINOR => c.width_minor = val,
+                AbsCode::ABS_MT_ORIENTATION => c.orientation = val,
+                AbsCode::ABS_MT_TOOL_TYPE => c.tool_type = val,
+                _ => return,
+            }
+        }
+        slot.dirty = true;
+    }
+
+    fn flush(&mut self, time: TimeVal) -> Option<TouchFrame> {
+        let mut contacts = mem::take(&mut self.lifted);
+        let mut changed = !contacts.is_empty();
+        for slot in &mut self.slots {
+            changed |= slot.dirty && slot.active;
+            if slot.active {
+                contacts.push(slot.contact);
+            }
+            match slot.contact.state {
+                ContactState::Up => slot.active = false,
+                ContactState::Down => slot.contact.state = ContactState::Move,
+                ContactState::Move => {}
+            }
+            slot.dirty = false;
+        }
+        if changed {
+            Some(TouchFrame { time, contacts })
+        } else {
+            None
+        }
+    }
+}
+
+fn is_single_touch(code: EvdevCode) -> bool {
+    match code {
+        EvdevCode::KeyCode(KeyCode::BTN_TOUCH) => true,
+        EvdevCode::AbsCode(code) => {
+            matches!(code, AbsCode::ABS_X | AbsCode::ABS_Y | AbsCode::ABS_PRESSURE | AbsCode::ABS_DISTANCE)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    #[test]
+    fn two_finger_test() {
+        let mut src = MockSource::new(DeviceDescription::new("touch"));
+        src.abs(AbsCode::ABS_MT_SLOT, 0)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, 10)
+            .abs(AbsCode::ABS_MT_POSITION_X, 100)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 200)
+            .sync()
+            .advance(10_000)
+            .abs(AbsCode::ABS_MT_SLOT, 1)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, 11)
+            .abs(AbsCode::ABS_MT_POSITION_X, 300)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 400)
+            .sync()
+            .advance(10_000)
+            .abs(AbsCode::ABS_MT_SLOT, 0)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, -1)
+            .sync();
+
+        let mut tracker = TouchTracker::new();
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(vec![Contact::new(0, 10, 100, 200)], frame.contacts);
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(ContactState::Move, frame.contact(10).unwrap().state);
+        assert_eq!(ContactState::Down, frame.contact(11).unwrap().state);
+        assert_eq!((300, 400), (frame.contact(11).unwrap().x, frame.contact(11).unwrap().y));
+
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(ContactState::Up, frame.contact(10).unwrap().state);
+        assert_eq!(1, frame.active_count());
+        assert_eq!(TimeVal::new(0, 20_000), frame.time);
+        assert!(tracker.next_frame(&mut src).unwrap().is_none());
+    }
+
+    #[test]
+    fn single_touch_test() {
+        let mut src = MockSource::new(DeviceDescription::new("resistive"));
+        src.abs(AbsCode::ABS_X, 5)
+            .abs(AbsCode::ABS_Y, 6)
+            .key(KeyCode::BTN_TOUCH, 1)
+            .sync()
+            .key(KeyCode::BTN_TOUCH, 0)
+            .sync();
+
+        let mut tracker = TouchTracker::new();
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(Contact::new(0, 1, 5, 6), frame.contacts[0]);
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(ContactState::Up, frame.contacts[0].state);
+        assert!(tracker.contacts().is_empty());
+    }
+
+    #[test]
+    fn protocol_a_test() {
+        let mut src = MockSource::new(DeviceDescription::new("protocol A"));
+        // With the single-touch emulation, before and after the contacts
+        src.key(KeyCode::BTN_TOUCH, 1)
+            .abs(AbsCode::ABS_X, 5)
+            .abs(AbsCode::ABS_Y, 6)
+            .abs(AbsCode::ABS_MT_POSITION_X, 5)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 6)
+            .push(EvdevCode::SynCode(SynCode::SYN_MT_REPORT), 0)
+            .sync()
+            .abs(AbsCode::ABS_MT_POSITION_X, 7)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 6)
+            .push(EvdevCode::SynCode(SynCode::SYN_MT_REPORT), 0)
+            .abs(AbsCode::ABS_X, 7)
+            .sync()
+            .push(EvdevCode::SynCode(SynCode::SYN_MT_REPORT), 0)
+            .key(KeyCode::BTN_TOUCH, 0)
+            .sync();
+
+        let mut tracker = TouchTracker::new();
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(vec![Contact::new(0, 0, 5, 6)], frame.contacts);
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(vec![Contact { state: ContactState::Move, ..Contact::new(0, 0, 7, 6) }], frame.contacts);
+        let frame = tracker.next_frame(&mut src).unwrap().unwrap();
+        assert_eq!(1, frame.contacts.len());
+        assert_eq!(ContactState::Up, frame.contacts[0].state);
+        assert!(tracker.contacts().is_empty());
+    }
+}
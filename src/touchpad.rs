@@ -0,0 +1,455 @@
+//! Touchpads as relative pointers.
+//!
+//! `Touchpad` turns a multitouch touchpad into pointer motion, button and
+//! scroll events: one finger moves the pointer through an acceleration
+//! profile, two fingers scroll, and quick taps click. On clickpads
+//! (`INPUT_PROP_BUTTONPAD`) the bottom of the pad is split into software
+//! buttons. Key presses from a keyboard disable the touchpad for a moment,
+//! so the palms don't move the pointer while typing.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use codes::*;
+use multi::MultiSource;
+use source::{position_axes, AbsInfo, EventSource};
+use touch::{ContactState, TouchFrame, TouchTracker};
+use {EvdevEvent, Error, TimeVal};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PointerEvent {
+    /// Accelerated motion in pixels.
+    Motion { dx: f64, dy: f64 },
+    Button { button: KeyCode, pressed: bool },
+    /// Two-finger scrolling in millimetres, unaccelerated.
+    Scroll { dx: f64, dy: f64 },
+}
+
+/// How finger speed maps to pointer speed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AccelProfile {
+    /// Every millimetre moves the pointer this many pixels.
+    Flat(f64),
+    /// `base` pixels per millimetre below `threshold` mm/s; above it the
+    /// factor grows by `incline` per mm/s, up to `max` times `base`. A `max`
+    /// below 1 means no acceleration.
+    Adaptive { base: f64, threshold: f64, incline: f64, max: f64 },
+}
+
+impl AccelProfile {
+    /// Pixels per millimetre at `speed` mm/s.
+    pub fn factor(&self, speed: f64) -> f64 {
+        match *self {
+            AccelProfile::Flat(factor) => factor,
+            AccelProfile::Adaptive { base, threshold, incline, max } => {
+                // Not `clamp`, which panics on a `max` below 1 or NaN
+                base * (1.0 + incline * (speed - threshold)).max(1.0).min(max.max(1.0))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TouchpadConfig {
+    pub accel: AccelProfile,
+    pub tap_to_click: bool,
+    /// Longest touch that counts as a tap.
+    pub tap_time: Duration,
+    /// Fingers moving further than this are not tapping.
+    pub tap_distance_mm: f64,
+    pub two_finger_scroll: bool,
+    /// Height of the software button area at the bottom of a clickpad.
+    pub soft_button_mm: f64,
+    /// How long after a key press the touchpad stays disabled.
+    pub typing_timeout: Duration,
+}
+
+impl Default for TouchpadConfig {
+    fn default() -> Self {
+        TouchpadConfig {
+            accel: AccelProfile::Adaptive { base: 4.0, threshold: 30.0, incline: 0.02, max: 3.0 },
+            tap_to_click: true,
+            tap_time: Duration::from_millis(180),
+            tap_distance_mm: 3.0,
+            two_finger_scroll: true,
+            soft_button_mm: 10.0,
+            typing_timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Finger {
+    pos: (i32, i32),
+    start: (i32, i32),
+    // Went down while typing
+    ignored: bool,
+    // Went down in the software button area
+    resting: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tap {
+    start: TimeVal,
+    fingers: usize,
+    cancelled: bool,
+}
+
+// Modifiers don't disable the touchpad, so modifier-clicks still work
+fn is_typing_key(key: KeyCode) -> bool {
+    match key {
+        KeyCode::KEY_LEFTCTRL | KeyCode::KEY_RIGHTCTRL | KeyCode::KEY_LEFTSHIFT | KeyCode::KEY_RIGHTSHIFT
+        | KeyCode::KEY_LEFTALT | KeyCode::KEY_RIGHTALT | KeyCode::KEY_LEFTMETA | KeyCode::KEY_RIGHTMETA
+        | KeyCode::KEY_FN => false,
+        key => (key as u16) < KeyCode::BTN_0 as u16,
+    }
+}
+
+pub struct Touchpad {
+    config: TouchpadConfig,
+    x: AbsInfo,
+    y: AbsInfo,
+    // Device units per millimetre
+    res: (f64, f64),
+    clickpad: bool,
+    tracker: TouchTracker,
+    fingers: HashMap<i32, Finger>,
+    last_time: Option<TimeVal>,
+    tap: Option<Tap>,
+    typing_until: Option<TimeVal>,
+    // The button a clickpad press was turned into
+    clicked: Option<KeyCode>,
+}
+
+impl Touchpad {
+    /// Without resolutions, one device unit counts as a millimetre; use
+    /// `with_resolution` for such devices.
+    pub fn new(x: AbsInfo, y: AbsInfo) -> Touchpad {
+        Touchpad {
+            config: TouchpadConfig::default(),
+            x,
+            y,
+            res: (x.units_per_mm(), y.units_per_mm()),
+            clickpad: false,
+            tracker: TouchTracker::new(),
+            fingers: HashMap::new(),
+            last_time: None,
+            tap: None,
+            typing_until: None,
+            clicked: None,
+        }
+    }
+
+    /// Uses the source's `position_axes`, and finds out whether it is a
+    /// clickpad.
+    pub fn for_source<S: EventSource + ?Sized>(src: &S) -> Result<Touchpad, Error> {
+        let (x, y) = position_axes(src)?;
+        Ok(Touchpad::new(x, y).with_clickpad(src.has_property(PropCode::INPUT_PROP_BUTTONPAD)))
+    }
+
+    pub fn with_config(mut self, config: TouchpadConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_resolution(mut self, x_units_per_mm: f64, y_units_per_mm: f64) -> Self {
+        self.res = (x_units_per_mm, y_units_per_mm);
+        self
+    }
+
+    /// Whether the whole pad is the left button, with software buttons at
+    /// the bottom.
+    pub fn with_clickpad(mut self, clickpad: bool) -> Self {
+        self.clickpad = clickpad;
+        self
+    }
+
+    pub fn config(&self) -> &TouchpadConfig {
+        &self.config
+    }
+
+    /// Whether the touchpad is disabled at `time` because of typing.
+    pub fn is_typing(&self, time: TimeVal) -> bool {
+        self.typing_until.is_some_and(|until| time < until)
+    }
+
+    /// Feeds an event of a keyboard paired with the touchpad.
+    pub fn keyboard_event(&mut self, ev: &EvdevEvent) {
+        if let EvdevCode::KeyCode(key) = ev.ev.code {
+            if ev.ev.val == 1 && is_typing_key(key) {
+                self.typing_until = Some(ev.time.plus(self.config.typing_timeout));
+            }
+        }
+    }
+
+    /// Feeds an event of the touchpad.
+    pub fn process(&mut self, ev: &EvdevEvent) -> Vec<PointerEvent> {
+        let mut out = Vec::new();
+        if let EvdevCode::KeyCode(key) = ev.ev.code {
+            if key == KeyCode::BTN_LEFT || key == KeyCode::BTN_RIGHT || key == KeyCode::BTN_MIDDLE {
+                out.extend(self.physical_button(key, ev.ev.val != 0));
+            }
+        }
+        if let Some(frame) = self.tracker.process(ev) {
+            out.extend(self.frame(&frame));
+        }
+        out
+    }
+
+    /// Reads the touchpad at index `touchpad` of `multi`, treating every
+    /// other source as a keyboard, until there are pointer events or the
+    /// sources run dry.
+    pub fn next_events<S: EventSource>(&mut self, multi: &mut MultiSource<S>, touchpad: usize)
+                                       -> Result<Vec<PointerEvent>, Error> {
+        while let Some((idx, ev)) = multi.read_event()? {
+            if idx != touchpad {
+                self.keyboard_event(&ev);
+                continue;
+            }
+            let out = self.process(&ev);
+            if !out.is_empty() {
+                return Ok(out);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn in_button_area(&self, pos: (i32, i32)) -> bool {
+        self.clickpad
+            && f64::from(self.y.maximum - pos.1) / self.res.1 <= self.config.soft_button_mm
+    }
+
+    fn physical_button(&mut self, key: KeyCode, pressed: bool) -> Option<PointerEvent> {
+        if let Some(ref mut tap) = self.tap {
+            tap.cancelled = true;
+        }
+        if !self.clickpad {
+            return Some(PointerEvent::Button { button: key, pressed });
+        }
+        if key != KeyCode::BTN_LEFT {
+            return None;
+        }
+        if pressed {
+            // The finger in the button area picks the button
+            let middle = (self.x.minimum + self.x.maximum) / 2;
+            let button = match self.fingers.values().find(|f| self.in_button_area(f.pos)) {
+                Some(f) if f.pos.0 > middle => KeyCode::BTN_RIGHT,
+                _ => KeyCode::BTN_LEFT,
+            };
+            self.clicked = Some(button);
+            Some(PointerEvent::Button { button, pressed })
+        } else {
+            self.clicked.take().map(|button| PointerEvent::Button { button, pressed })
+        }
+    }
+
+    fn frame(&mut self, frame: &TouchFrame) -> Vec<PointerEvent> {
+        let now = frame.time;
+        let dt = self.last_time.map_or(0, |last| now.micros_since(last));
+        self.last_time = Some(now);
+        let typing = self.is_typing(frame.time);
+
+        let mut deltas = Vec::new();
+        for c in &frame.contacts {
+            let pos = (c.x, c.y);
+            match c.state {
+                ContactState::Down => {
+                    let resting = self.in_button_area(pos);
+                    self.fingers.insert(c.tracking_id, Finger { pos, start: pos, ignored: typing, resting });
+                    if !typing && !resting && self.tap.is_none() {
+                        self.tap = Some(Tap { start: now, fingers: 0, cancelled: false });
+                    }
+                }
+                ContactState::Move => {
+                    if let Some(f) = self.fingers.get_mut(&c.tracking_id) {
+                        let delta = (f64::from(pos.0 - f.pos.0) / self.res.0, f64::from(pos.1 - f.pos.1) / self.res.1);
+                        f.pos = pos;
+                        let moved = (f64::from(pos.0 - f.start.0) / self.res.0)
+                            .hypot(f64::from(pos.1 - f.start.1) / self.res.1);
+                        if moved > self.config.tap_distance_mm {
+                            if let Some(ref mut tap) = self.tap {
+                                tap.cancelled = true;
+                            }
+                        }
+                        if !f.ignored && !f.resting {
+                            deltas.push(delta);
+                        }
+                    }
+                }
+                ContactState::Up => {}
+            }
+        }
+
+        // Resting thumbs and fingers put down while typing aren't tapping
+        let active = self.fingers.values().filter(|f| !f.ignored && !f.resting).count();
+        if let Some(ref mut tap) = self.tap {
+            tap.fingers = tap.fingers.max(active);
+            tap.cancelled |= typing;
+        }
+        for c in frame.contacts.iter().filter(|c| c.state == ContactState::Up) {
+            self.fingers.remove(&c.tracking_id);
+        }
+
+        let mut out = Vec::new();
+        if !typing && !deltas.is_empty() {
+            let n = deltas.len() as f64;
+            let (dx, dy) = deltas.iter().fold((0.0, 0.0), |acc, d| (acc.0 + d.0, acc.1 + d.1));
+            match active {
+                1 => {
+                    let speed = if dt > 0 { dx.hypot(dy) / (dt as f64 / 1_000_000.0) } else { 0.0 };
+                    let factor = self.config.accel.factor(speed);
+                    out.push(PointerEvent::Motion { dx: dx * factor, dy: dy * factor });
+                }
+                2 if self.config.two_finger_scroll => out.push(PointerEvent::Scroll { dx: dx / n, dy: dy / n }),
+                _ => {}
+            }
+        }
+
+        if self.fingers.values().all(|f| f.ignored || f.resting) {
+            if let Some(tap) = self.tap.take() {
+                let button = match tap.fingers {
+                    1 => Some(KeyCode::BTN_LEFT),
+                    2 => Some(KeyCode::BTN_RIGHT),
+                    3 => Some(KeyCode::BTN_MIDDLE),
+                    _ => None,
+                };
+                let quick = now <= tap.start.plus(self.config.tap_time);
+                if let (true, false, true, Some(button)) = (self.config.tap_to_click, tap.cancelled, quick, button) {
+                    out.push(PointerEvent::Button { button, pressed: true });
+                    out.push(PointerEvent::Button { button, pressed: false });
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockSource;
+    use source::DeviceDescription;
+
+    fn touchpad(clickpad: bool) -> MockSource {
+        let mut desc = DeviceDescription::new("touchpad")
+            .with_abs(AbsCode::ABS_MT_SLOT, AbsInfo { maximum: 4, ..Default::default() })
+            .with_abs(AbsCode::ABS_MT_POSITION_X, AbsInfo { maximum: 1000, resolution: 10, ..Default::default() })
+            .with_abs(AbsCode::ABS_MT_POSITION_Y, AbsInfo { maximum: 600, resolution: 10, ..Default::default() });
+        if clickpad {
+            desc = desc.with_property(PropCode::INPUT_PROP_BUTTONPAD);
+        }
+        MockSource::new(desc)
+    }
+
+    fn down(src: &mut MockSource, slot: i32, id: i32, x: i32, y: i32) {
+        src.abs(AbsCode::ABS_MT_SLOT, slot)
+            .abs(AbsCode::ABS_MT_TRACKING_ID, id)
+            .abs(AbsCode::ABS_MT_POSITION_X, x)
+            .abs(AbsCode::ABS_MT_POSITION_Y, y);
+    }
+
+    fn up(src: &mut MockSource, slot: i32) {
+        src.abs(AbsCode::ABS_MT_SLOT, slot).abs(AbsCode::ABS_MT_TRACKING_ID, -1);
+    }
+
+    fn run(pad: &mut Touchpad, src: &mut MockSource) -> Vec<PointerEvent> {
+        let mut out = Vec::new();
+        while let Some(ev) = src.read_event().unwrap() {
+            out.extend(pad.process(&ev));
+        }
+        out
+    }
+
+    #[test]
+    fn motion_and_tap_test() {
+        let mut src = touchpad(false);
+        down(&mut src, 0, 1, 100, 100);
+        src.sync().advance(10_000);
+        src.abs(AbsCode::ABS_MT_POSITION_X, 110).sync().advance(10_000);
+        src.abs(AbsCode::ABS_MT_POSITION_X, 150).sync().advance(10_000);
+        up(&mut src, 0);
+        src.sync().advance(500_000);
+        // A two-finger tap
+        down(&mut src, 0, 2, 100, 100);
+        down(&mut src, 1, 3, 300, 100);
+        src.sync().advance(50_000);
+        up(&mut src, 0);
+        up(&mut src, 1);
+        src.sync();
+
+        let mut pad = Touchpad::for_source(&src).unwrap()
+            .with_config(TouchpadConfig { accel: AccelProfile::Adaptive { base: 2.0, threshold: 100.0, incline: 0.01, max: 4.0 }, ..Default::default() });
+        assert_eq!(vec![
+            // 1mm in 10ms is slow, 4mm is accelerated
+            PointerEvent::Motion { dx: 2.0, dy: 0.0 },
+            PointerEvent::Motion { dx: 8.0 * 4.0, dy: 0.0 },
+            PointerEvent::Button { button: KeyCode::BTN_RIGHT, pressed: true },
+            PointerEvent::Button { button: KeyCode::BTN_RIGHT, pressed: false },
+        ], run(&mut pad, &mut src));
+    }
+
+    #[test]
+    fn accel_test() {
+        let accel = |max| AccelProfile::Adaptive { base: 2.0, threshold: 10.0, incline: 0.1, max };
+        assert_eq!(2.0, accel(4.0).factor(0.0));
+        assert_eq!(4.0, accel(4.0).factor(20.0));
+        assert_eq!(8.0, accel(4.0).factor(1000.0));
+        assert_eq!(2.0, accel(0.5).factor(1000.0));
+        assert_eq!(2.0, accel(f64::NAN).factor(1000.0));
+    }
+
+    #[test]
+    fn scroll_test() {
+        let mut src = touchpad(false);
+        down(&mut src, 0, 1, 100, 100);
+        down(&mut src, 1, 2, 300, 100);
+        src.sync().advance(10_000);
+        src.abs(AbsCode::ABS_MT_SLOT, 0)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 120)
+            .abs(AbsCode::ABS_MT_SLOT, 1)
+            .abs(AbsCode::ABS_MT_POSITION_Y, 140)
+            .sync();
+
+        let mut pad = Touchpad::for_source(&src).unwrap();
+        assert_eq!(vec![PointerEvent::Scroll { dx: 0.0, dy: 3.0 }], run(&mut pad, &mut src));
+    }
+
+    #[test]
+    fn clickpad_and_typing_test() {
+        let mut src = touchpad(true);
+        // Resting in the bottom right corner and clicking
+        down(&mut src, 0, 1, 900, 590);
+        src.sync().advance(10_000);
+        src.abs(AbsCode::ABS_MT_POSITION_X, 880).key(KeyCode::BTN_LEFT, 1).sync().advance(300_000);
+        src.key(KeyCode::BTN_LEFT, 0).sync();
+        up(&mut src, 0);
+        src.sync().advance(100_000);
+        // Typing at this point, so this is ignored
+        down(&mut src, 0, 2, 500, 300);
+        src.sync().advance(10_000);
+        src.abs(AbsCode::ABS_MT_POSITION_X, 600).sync().advance(600_000);
+        up(&mut src, 0);
+        src.sync().advance(10_000);
+        // A tap while the thumb rests on the buttons
+        down(&mut src, 0, 3, 100, 595);
+        src.sync().advance(10_000);
+        down(&mut src, 1, 4, 500, 300);
+        src.sync().advance(50_000);
+        up(&mut src, 1);
+        src.sync();
+
+        let mut pad = Touchpad::for_source(&src).unwrap();
+        let typed = EvdevEvent::new(TimeVal::from_micros(400_000), EvdevCode::KeyCode(KeyCode::KEY_A), 1);
+        pad.keyboard_event(&typed);
+        assert_eq!(vec![
+            PointerEvent::Button { button: KeyCode::BTN_RIGHT, pressed: true },
+            PointerEvent::Button { button: KeyCode::BTN_RIGHT, pressed: false },
+            PointerEvent::Button { button: KeyCode::BTN_LEFT, pressed: true },
+            PointerEvent::Button { button: KeyCode::BTN_LEFT, pressed: false },
+        ], run(&mut pad, &mut src));
+        assert!(pad.is_typing(TimeVal::from_micros(899_999)));
+    }
+}
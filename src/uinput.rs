@@ -0,0 +1,144 @@
+//! Virtual input devices through `/dev/uinput`.
+//!
+//! Uses the plain uinput ioctls, so it works without libevdev's uinput
+//! support. Axis resolutions are not passed on.
+
+use libc;
+use num::FromPrimitive;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use codes::*;
+use replay::write_raw_event;
+use source::DeviceDescription;
+use {EvdevEvent, Error, InputId, TimeVal};
+
+const UI_DEV_CREATE: u32 = 0x5501;
+const UI_DEV_DESTROY: u32 = 0x5502;
+const UI_SET_EVBIT: u32 = 0x4004_5564;
+const UI_SET_KEYBIT: u32 = 0x4004_5565;
+const UI_SET_RELBIT: u32 = 0x4004_5566;
+const UI_SET_ABSBIT: u32 = 0x4004_5567;
+const UI_SET_MSCBIT: u32 = 0x4004_5568;
+const UI_SET_LEDBIT: u32 = 0x4004_5569;
+const UI_SET_SNDBIT: u32 = 0x4004_556a;
+const UI_SET_SWBIT: u32 = 0x4004_556d;
+const UI_SET_PROPBIT: u32 = 0x4004_556e;
+
+const ABS_CNT: usize = 0x40;
+
+/// `struct uinput_user_dev`
+#[repr(C)]
+struct UserDev {
+    name: [u8; 80],
+    // struct input_id: bustype, vendor, product, version
+    id: [u16; 4],
+    ff_effects_max: u32,
+    absmax: [i32; ABS_CNT],
+    absmin: [i32; ABS_CNT],
+    absfuzz: [i32; ABS_CNT],
+    absflat: [i32; ABS_CNT],
+}
+
+fn ioctl(file: &File, request: u32, arg: libc::c_int) -> Result<(), Error> {
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg) };
+    if ret < 0 {
+        return Err(Error::from(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+fn set_bit_request(ty: TypeCode) -> Option<u32> {
+    match ty {
+        TypeCode::EV_KEY => Some(UI_SET_KEYBIT),
+        TypeCode::EV_REL => Some(UI_SET_RELBIT),
+        TypeCode::EV_ABS => Some(UI_SET_ABSBIT),
+        TypeCode::EV_MSC => Some(UI_SET_MSCBIT),
+        TypeCode::EV_LED => Some(UI_SET_LEDBIT),
+        TypeCode::EV_SND => Some(UI_SET_SNDBIT),
+        TypeCode::EV_SW => Some(UI_SET_SWBIT),
+        _ => None,
+    }
+}
+
+/// A virtual device with the capabilities of a `DeviceDescription`. The
+/// device goes away when this is dropped.
+pub struct UinputDevice {
+    file: File,
+}
+
+impl UinputDevice {
+    pub fn create(desc: &DeviceDescription, id: InputId) -> Result<UinputDevice, Error> {
+        UinputDevice::create_at("/dev/uinput", desc, id)
+    }
+
+    pub fn create_at<P: AsRef<Path>>(path: P, desc: &DeviceDescription, id: InputId) -> Result<UinputDevice, Error> {
+        let file = OpenOptions::new().write(true).open(path)?;
+
+        let mut types: Vec<u16> = desc.codes.iter().map(|c| c.to_raw().0).collect();
+        types.sort();
+        types.dedup();
+        for &ty in &types {
+            ioctl(&file, UI_SET_EVBIT, libc::c_int::from(ty))?;
+        }
+        for code in &desc.codes {
+            let (ty, num) = code.to_raw();
+            match TypeCode::from_u16(ty).map(|t| (t, set_bit_request(t))) {
+                Some((_, Some(request))) => ioctl(&file, request, libc::c_int::from(num))?,
+                Some((TypeCode::EV_SYN, None)) | Some((TypeCode::EV_REP, None)) => {}
+                _ => return Err(Error(format!("uinput cannot set up code {:?}", code))),
+            }
+        }
+        for &prop in &desc.props {
+            ioctl(&file, UI_SET_PROPBIT, prop as libc::c_int)?;
+        }
+
+        let mut dev = UserDev {
+            name: [0; 80],
+            id: [id.bustype, id.vendor, id.product, id.version],
+            ff_effects_max: 0,
+            absmax: [0; ABS_CNT],
+            absmin: [0; ABS_CNT],
+            absfuzz: [0; ABS_CNT],
+            absflat: [0; ABS_CNT],
+        };
+        let name = desc.name.as_bytes();
+        let len = name.len().min(dev.name.len() - 1);
+        dev.name[..len].copy_from_slice(&name[..len]);
+        for (&code, info) in &desc.abs {
+            let i = code as usize;
+            dev.absmin[i] = info.minimum;
+            dev.absmax[i] = info.maximum;
+            dev.absfuzz[i] = info.fuzz;
+            dev.absflat[i] = info.flat;
+        }
+        let size = ::std::mem::size_of::<UserDev>();
+        let ret = unsafe { libc::write(file.as_raw_fd(), &dev as *const UserDev as *const libc::c_void, size) };
+        if ret != size as isize {
+            return Err(Error::from(io::Error::last_os_error()));
+        }
+        ioctl(&file, UI_DEV_CREATE, 0)?;
+        Ok(UinputDevice { file })
+    }
+
+    /// Emits one event. The kernel stamps it with its own time.
+    pub fn write_event(&mut self, ev: &EvdevEvent) -> Result<(), Error> {
+        Ok(write_raw_event(&mut self.file, ev)?)
+    }
+
+    pub fn write(&mut self, code: EvdevCode, val: i32) -> Result<(), Error> {
+        self.write_event(&EvdevEvent::new(TimeVal::default(), code, val))
+    }
+
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.write(EvdevCode::SynCode(SynCode::SYN_REPORT), 0)
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        let _ = ioctl(&self.file, UI_DEV_DESTROY, 0);
+    }
+}
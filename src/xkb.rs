@@ -0,0 +1,724 @@
+//! Keymaps from XKB text files.
+//!
+//! Reads the practical part of XKB: `xkb_keycodes`, `xkb_types` and
+//! `xkb_symbols`, either from a compiled keymap as `xkbcli compile-keymap`
+//! or `xkbcomp -xkb` write it, or from the components in an XKB directory
+//! such as `/usr/share/X11/xkb`, following `include`s. Every group becomes
+//! a `Keymap`. Types only decide whether Caps lock or Num lock apply, as
+//! `KeyKind` does; actions, compat maps and geometry are skipped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use codes::*;
+use keymap::{Key, KeyKind, Keymap, Keysym};
+use num::FromPrimitive;
+use Error;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    /// A key name without its angle brackets.
+    KeyName(String),
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref s) => write!(f, "'{}'", s),
+            Token::Str(ref s) => write!(f, "\"{}\"", s),
+            Token::KeyName(ref s) => write!(f, "<{}>", s),
+            Token::Punct(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+fn unexpected(tok: &Token) -> Error {
+    Error(format!("unexpected {} in XKB keymap", tok))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => prev = c,
+                        None => return Err(Error("unterminated comment in XKB keymap".to_string())),
+                    }
+                }
+            }
+            '"' | '<' => {
+                let end = if c == '"' { '"' } else { '>' };
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(c) if c == end => break,
+                        Some('\\') if end == '"' => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err(Error(format!("unterminated {} in XKB keymap", c))),
+                    }
+                }
+                tokens.push(if c == '"' { Token::Str(s) } else { Token::KeyName(s) });
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(s));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Parser<'a> {
+        Parser { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_token(&mut self) -> Option<&'a Token> {
+        let tok = self.peek()?;
+        self.pos += 1;
+        Some(tok)
+    }
+
+    fn next(&mut self) -> Result<&'a Token, Error> {
+        self.next_token().ok_or_else(|| Error("unexpected end of XKB keymap".to_string()))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        match *self.next()? {
+            Token::Punct(p) if p == c => Ok(()),
+            ref tok => Err(unexpected(tok)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, Error> {
+        match *self.next()? {
+            Token::Ident(ref s) => Ok(s),
+            ref tok => Err(unexpected(tok)),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, Error> {
+        match *self.next()? {
+            Token::Str(ref s) => Ok(s),
+            ref tok => Err(unexpected(tok)),
+        }
+    }
+
+    fn key_name(&mut self) -> Result<&'a str, Error> {
+        match *self.next()? {
+            Token::KeyName(ref s) => Ok(s),
+            ref tok => Err(unexpected(tok)),
+        }
+    }
+
+    // `[Group2]` as 1
+    fn group_index(&mut self) -> Result<usize, Error> {
+        self.expect('[')?;
+        let word = self.ident()?;
+        self.expect(']')?;
+        let num = word.trim_start_matches(|c: char| c.is_alphabetic());
+        match num.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n - 1),
+            _ => Err(Error(format!("bad group '{}' in XKB keymap", word))),
+        }
+    }
+
+    // The tokens up to the `}` closing an opened `{`, which is consumed
+    fn block(&mut self) -> Result<&'a [Token], Error> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match *self.next()? {
+                Token::Punct('{') => depth += 1,
+                Token::Punct('}') if depth == 0 => return Ok(&self.tokens[start..self.pos - 1]),
+                Token::Punct('}') => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    // Skips up to `end` outside of brackets, leaving it to be read
+    fn skip_to(&mut self, end: char) {
+        let mut depth = 0;
+        while let Some(tok) = self.peek() {
+            match *tok {
+                Token::Punct(c) if c == end && depth == 0 => return,
+                Token::Punct('{') | Token::Punct('[') | Token::Punct('(') => depth += 1,
+                Token::Punct('}') | Token::Punct(']') | Token::Punct(')') => depth -= 1,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    // The keysyms of a level list whose `[` was read
+    fn levels(&mut self) -> Result<Vec<Option<Keysym>>, Error> {
+        let mut levels = Vec::new();
+        loop {
+            match *self.next()? {
+                Token::Punct(']') => return Ok(levels),
+                Token::Punct(',') => {}
+                Token::Ident(ref name) => levels.push(Keysym::from_name(name)),
+                ref tok => return Err(unexpected(tok)),
+            }
+        }
+    }
+}
+
+struct Section<'a> {
+    kind: &'a str,
+    name: &'a str,
+    default: bool,
+    body: &'a [Token],
+}
+
+fn sections(tokens: &[Token]) -> Result<Vec<Section<'_>>, Error> {
+    let mut p = Parser::new(tokens);
+    let mut sections = Vec::new();
+    while p.peek().is_some() {
+        if p.eat(';') {
+            continue;
+        }
+        // Flags such as `default partial alphanumeric_keys`, then the kind
+        let mut default = false;
+        let kind = loop {
+            let word = p.ident()?;
+            if word.starts_with("xkb_") {
+                break word;
+            }
+            default |= word == "default";
+        };
+        let name = match p.peek() {
+            Some(&Token::Str(_)) => p.string()?,
+            _ => "",
+        };
+        p.expect('{')?;
+        let body = p.block()?;
+        sections.push(Section { kind, name, default, body });
+    }
+    Ok(sections)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Merge {
+    Override,
+    Augment,
+}
+
+fn merge_word(tok: &Token) -> Option<Merge> {
+    match *tok {
+        Token::Ident(ref w) if w == "include" || w == "override" || w == "replace" => Some(Merge::Override),
+        Token::Ident(ref w) if w == "augment" => Some(Merge::Augment),
+        _ => None,
+    }
+}
+
+// `pc+us(intl)|inet(evdev):2` as its parts and how each merges
+fn include_parts(spec: &str) -> Vec<(Merge, &str)> {
+    let mut parts = Vec::new();
+    let mut merge = Merge::Override;
+    let mut start = 0;
+    for (i, c) in spec.char_indices() {
+        if c == '+' || c == '|' {
+            parts.push((merge, &spec[start..i]));
+            merge = if c == '|' { Merge::Augment } else { Merge::Override };
+            start = i + 1;
+        }
+    }
+    parts.push((merge, &spec[start..]));
+    parts.retain(|&(_, part)| !part.is_empty());
+    parts
+}
+
+fn insert<V>(map: &mut HashMap<String, V>, key: &str, val: V, merge: Merge) {
+    if merge == Merge::Override || !map.contains_key(key) {
+        map.insert(key.to_string(), val);
+    }
+}
+
+// Kinds of the common types, for keymaps without `xkb_types`
+fn builtin_kind(name: &str) -> Option<KeyKind> {
+    match name {
+        "ONE_LEVEL" | "TWO_LEVEL" | "FOUR_LEVEL" => Some(KeyKind::Plain),
+        "ALPHABETIC" | "FOUR_LEVEL_ALPHABETIC" | "FOUR_LEVEL_SEMIALPHABETIC" => Some(KeyKind::Alphabetic),
+        "KEYPAD" | "FOUR_LEVEL_KEYPAD" | "FOUR_LEVEL_MIXED_KEYPAD" => Some(KeyKind::Keypad),
+        _ => None,
+    }
+}
+
+// A type is keypad-like if Num lock picks a level, alphabetic if Caps lock does
+fn type_kind(body: &[Token]) -> KeyKind {
+    let mentions = |name: &str| body.iter().any(|tok| *tok == Token::Ident(name.to_string()));
+    if mentions("NumLock") {
+        KeyKind::Keypad
+    } else if mentions("Lock") {
+        KeyKind::Alphabetic
+    } else {
+        KeyKind::Plain
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Group {
+    key_type: Option<String>,
+    levels: Vec<Option<Keysym>>,
+}
+
+// The groups of a key body, with `key_type` for those that don't say
+fn parse_key(body: &[Token], key_type: Option<&str>) -> Result<Vec<Group>, Error> {
+    let mut p = Parser::new(body);
+    let mut groups: Vec<Group> = Vec::new();
+    let mut key_type = key_type.map(str::to_string);
+    let mut next_group = 0;
+    fn group(groups: &mut Vec<Group>, idx: usize) -> &mut Group {
+        if groups.len() <= idx {
+            groups.resize(idx + 1, Group::default());
+        }
+        &mut groups[idx]
+    }
+    while let Some(tok) = p.next_token() {
+        match *tok {
+            Token::Punct(',') => {}
+            Token::Punct('[') => {
+                group(&mut groups, next_group).levels = p.levels()?;
+                next_group += 1;
+            }
+            Token::Ident(ref w) if w == "type" || w == "symbols" => {
+                let idx = match p.peek() {
+                    Some(&Token::Punct('[')) => Some(p.group_index()?),
+                    _ => None,
+                };
+                p.expect('=')?;
+                if w == "type" {
+                    let name = p.string()?.to_string();
+                    match idx {
+                        Some(idx) => group(&mut groups, idx).key_type = Some(name),
+                        None => key_type = Some(name),
+                    }
+                } else {
+                    p.expect('[')?;
+                    group(&mut groups, idx.unwrap_or(0)).levels = p.levels()?;
+                }
+            }
+            // Actions, virtual modifiers, repeat and the like
+            _ => p.skip_to(','),
+        }
+    }
+    for g in &mut groups {
+        if g.key_type.is_none() {
+            g.key_type = key_type.clone();
+        }
+    }
+    Ok(groups)
+}
+
+#[derive(Debug, Default)]
+struct Components {
+    keycodes: HashMap<String, u32>,
+    aliases: HashMap<String, String>,
+    types: HashMap<String, KeyKind>,
+    keys: HashMap<String, Vec<Option<Group>>>,
+    names: Vec<Option<String>>,
+}
+
+impl Components {
+    fn merge_key(&mut self, name: &str, groups: Vec<Group>, merge: Merge, offset: usize) {
+        let existing = self.keys.entry(name.to_string()).or_default();
+        for (i, group) in groups.into_iter().enumerate() {
+            let idx = i + offset;
+            if existing.len() <= idx {
+                existing.resize(idx + 1, None);
+            }
+            let slot = existing[idx].get_or_insert_with(Group::default);
+            if !group.levels.is_empty() && (merge == Merge::Override || slot.levels.is_empty()) {
+                slot.levels = group.levels;
+            }
+            if group.key_type.is_some() && (merge == Merge::Override || slot.key_type.is_none()) {
+                slot.key_type = group.key_type;
+            }
+        }
+    }
+
+    fn set_name(&mut self, idx: usize, name: &str, merge: Merge) {
+        if self.names.len() <= idx {
+            self.names.resize(idx + 1, None);
+        }
+        if merge == Merge::Override || self.names[idx].is_none() {
+            self.names[idx] = Some(name.to_string());
+        }
+    }
+
+    fn key_code(&self, name: &str) -> Option<KeyCode> {
+        let name = self.aliases.get(name).map_or(name, |n| n.as_str());
+        let code = self.keycodes.get(name)?.checked_sub(8)?;
+        KeyCode::from_u32(code)
+    }
+
+    fn kind(&self, group: &Group) -> Option<KeyKind> {
+        let name = group.key_type.as_ref()?;
+        self.types.get(name).cloned().or_else(|| builtin_kind(name))
+    }
+
+    fn build(self, name: &str) -> XkbKeymap {
+        let count = self.keys.values().map(Vec::len).max().unwrap_or(0).max(1);
+        let mut groups: Vec<Keymap> = (0..count)
+            .map(|i| match self.names.get(i).and_then(Option::as_ref) {
+                Some(name) => Keymap::new(name),
+                None if count > 1 => Keymap::new(&format!("{} (group {})", name, i + 1)),
+                None => Keymap::new(name),
+            })
+            .collect();
+        for (key_name, key_groups) in &self.keys {
+            let code = match self.key_code(key_name) {
+                Some(code) => code,
+                None => continue,
+            };
+            let first = match key_groups.iter().flatten().next() {
+                Some(first) => first,
+                None => continue,
+            };
+            for (i, keymap) in groups.iter_mut().enumerate() {
+                // Keys with fewer groups wrap around, as XKB does by default
+                let group = key_groups[i % key_groups.len()].as_ref().unwrap_or(first);
+                if group.levels.is_empty() {
+                    continue;
+                }
+                let mut key = Key::guess(group.levels.clone());
+                if let Some(kind) = self.kind(group) {
+                    key.kind = kind;
+                }
+                keymap.set_key(code, key);
+            }
+        }
+        XkbKeymap { groups }
+    }
+}
+
+struct Loader<'a> {
+    dir: Option<&'a Path>,
+}
+
+impl<'a> Loader<'a> {
+    fn include(&self, comps: &mut Components, kind: &str, spec: &str, merge: Merge, offset: usize, depth: usize)
+        -> Result<(), Error>
+    {
+        let dir = match self.dir {
+            Some(dir) => dir,
+            None => return Err(Error(format!("cannot include '{}' without an XKB directory", spec))),
+        };
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(Error(format!("XKB includes nested too deep at '{}'", spec)));
+        }
+        for (part_merge, part) in include_parts(spec) {
+            let merge = if merge == Merge::Augment { merge } else { part_merge };
+            let (part, group) = match part.find(':') {
+                Some(i) => match part[i + 1..].parse::<usize>() {
+                    Ok(n) if n > 0 => (&part[..i], n - 1),
+                    _ => return Err(Error(format!("bad group in XKB include '{}'", spec))),
+                },
+                None => (part, 0),
+            };
+            let (file, name) = match part.find('(') {
+                Some(i) => (&part[..i], Some(part[i + 1..].trim_end_matches(')'))),
+                None => (part, None),
+            };
+            let path = dir.join(kind.trim_start_matches("xkb_")).join(file);
+            let text = fs::read_to_string(&path).map_err(|e| Error(format!("{}: {}", path.display(), e)))?;
+            let tokens = tokenize(&text)?;
+            let sections = sections(&tokens)?;
+            let section = match name {
+                Some(name) => sections.iter().find(|s| s.name == name),
+                None => sections.iter().find(|s| s.default).or_else(|| sections.first()),
+            };
+            let section = section.ok_or_else(|| {
+                Error(format!("no section '{}' in {}", name.unwrap_or("default"), path.display()))
+            })?;
+            self.apply(comps, kind, section.body, merge, offset + group, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&self, comps: &mut Components, kind: &str, body: &[Token], merge: Merge, offset: usize, depth: usize)
+        -> Result<(), Error>
+    {
+        let mut p = Parser::new(body);
+        let mut key_type: Option<&str> = None;
+        while let Some(mut tok) = p.next_token() {
+            let mut merge = merge;
+            // `include "file"`, or a merge mode in front of a statement
+            if let Some(mode) = merge_word(tok) {
+                if merge == Merge::Override {
+                    merge = mode;
+                }
+                if let Some(Token::Str(spec)) = p.peek() {
+                    p.next()?;
+                    self.include(comps, kind, spec, merge, offset, depth)?;
+                    continue;
+                }
+                tok = p.next()?;
+            }
+            match (kind, tok) {
+                (_, Token::Punct(';')) => {}
+                ("xkb_keycodes", Token::KeyName(name)) => {
+                    p.expect('=')?;
+                    let code = p.ident()?.parse()?;
+                    insert(&mut comps.keycodes, name, code, merge);
+                }
+                ("xkb_keycodes", Token::Ident(w)) if w == "alias" => {
+                    let alias = p.key_name()?;
+                    p.expect('=')?;
+                    let name = p.key_name()?.to_string();
+                    insert(&mut comps.aliases, alias, name, merge);
+                }
+                ("xkb_types", Token::Ident(w)) if w == "type" => {
+                    let name = p.string()?;
+                    p.expect('{')?;
+                    let kind = type_kind(p.block()?);
+                    insert(&mut comps.types, name, kind, merge);
+                }
+                ("xkb_symbols", Token::Ident(w)) if w == "key" && p.eat('.') => {
+                    // `key.type[Group1] = "KEYPAD";` sets the type of the keys after it
+                    if p.ident()? == "type" {
+                        if let Some(&Token::Punct('[')) = p.peek() {
+                            p.group_index()?;
+                        }
+                        p.expect('=')?;
+                        key_type = Some(p.string()?);
+                    } else {
+                        p.skip_to(';');
+                    }
+                }
+                ("xkb_symbols", Token::Ident(w)) if w == "key" => {
+                    let name = p.key_name()?;
+                    p.expect('{')?;
+                    let groups = parse_key(p.block()?, key_type)?;
+                    comps.merge_key(name, groups, merge, offset);
+                }
+                ("xkb_symbols", Token::Ident(w)) if w == "name" => {
+                    let idx = p.group_index()?;
+                    p.expect('=')?;
+                    let name = p.string()?;
+                    comps.set_name(idx + offset, name, merge);
+                }
+                // Modifier maps, virtual modifiers, indicators and so on
+                _ => p.skip_to(';'),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A keymap read from XKB files, with one `Keymap` per group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XkbKeymap {
+    groups: Vec<Keymap>,
+}
+
+impl XkbKeymap {
+    /// Reads a compiled keymap file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<XkbKeymap, Error> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Builds a keymap from the components in the XKB directory `dir`, named
+    /// as `setxkbmap -print` shows them: keycodes `evdev+aliases(qwerty)`,
+    /// types `complete` and symbols `pc+us+de:2`.
+    pub fn from_components<P: AsRef<Path>>(dir: P, keycodes: &str, types: &str, symbols: &str)
+        -> Result<XkbKeymap, Error>
+    {
+        let loader = Loader { dir: Some(dir.as_ref()) };
+        let mut comps = Components::default();
+        loader.include(&mut comps, "xkb_keycodes", keycodes, Merge::Override, 0, 0)?;
+        loader.include(&mut comps, "xkb_types", types, Merge::Override, 0, 0)?;
+        loader.include(&mut comps, "xkb_symbols", symbols, Merge::Override, 0, 0)?;
+        Ok(comps.build(symbols))
+    }
+
+    pub fn groups(&self) -> &[Keymap] {
+        &self.groups
+    }
+
+    pub fn group(&self, idx: usize) -> Option<&Keymap> {
+        self.groups.get(idx)
+    }
+
+    pub fn into_groups(self) -> Vec<Keymap> {
+        self.groups
+    }
+}
+
+impl FromStr for XkbKeymap {
+    type Err = Error;
+
+    /// Parses a compiled `xkb_keymap`, or bare sections without includes.
+    fn from_str(s: &str) -> Result<XkbKeymap, Error> {
+        let tokens = tokenize(s)?;
+        let mut sections = sections(&tokens)?;
+        if sections.len() == 1 && sections[0].kind == "xkb_keymap" {
+            let body = sections[0].body;
+            sections = self::sections(body)?;
+        }
+        let loader = Loader { dir: None };
+        let mut comps = Components::default();
+        let mut name = "";
+        for section in &sections {
+            if section.kind == "xkb_symbols" {
+                name = section.name;
+            }
+            loader.apply(&mut comps, section.kind, section.body, Merge::Override, 0, 0)?;
+        }
+        Ok(comps.build(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keyboard::{Locks, Modifiers};
+
+    fn fixtures() -> &'static Path {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/xkb"))
+    }
+
+    fn sym(keymap: &Keymap, key: KeyCode, mods: Modifiers, locks: Locks) -> Option<Keysym> {
+        keymap.keysym(key, &mods, &locks)
+    }
+
+    #[test]
+    fn components_test() {
+        let none = Modifiers::default();
+        let shift = Modifiers { left_shift: true, ..Default::default() };
+        let altgr = Modifiers { right_alt: true, ..Default::default() };
+        let caps = Locks { caps: true, ..Default::default() };
+        let num = Locks { num: true, ..Default::default() };
+        let unlocked = Locks::default();
+        let c = |c| Some(Keysym::Char(c));
+
+        let xkb = XkbKeymap::from_components(fixtures(), "evdev", "complete", "pc+us+de(nodeadkeys):2").unwrap();
+        assert_eq!(2, xkb.groups().len());
+        let (us, de) = (xkb.group(0).unwrap(), xkb.group(1).unwrap());
+        assert_eq!(("English (US)", "German (no dead keys)"), (us.name(), de.name()));
+
+        assert_eq!(c('Q'), sym(us, KeyCode::KEY_Q, none, caps));
+        assert_eq!(c('q'), sym(us, KeyCode::KEY_Q, shift, caps));
+        assert_eq!(c('@'), sym(us, KeyCode::KEY_2, shift, unlocked));
+        assert!(!us.has_altgr() && de.has_altgr());
+
+        assert_eq!(c('z'), sym(de, KeyCode::KEY_Y, none, unlocked));
+        assert_eq!(c('Ö'), sym(de, KeyCode::KEY_SEMICOLON, none, caps));
+        assert_eq!(c('€'), sym(de, KeyCode::KEY_E, altgr, caps));
+        assert_eq!(c('\''), sym(de, KeyCode::KEY_EQUAL, none, unlocked));
+        assert_eq!(c('\\'), sym(de, KeyCode::KEY_MINUS, altgr, unlocked));
+        assert_eq!(c('@'), sym(de, KeyCode::KEY_Q, altgr, caps));
+        let shift_altgr = Modifiers { left_shift: true, right_alt: true, ..Default::default() };
+        assert_eq!(Some(Keysym::named("Greek_OMEGA")), sym(de, KeyCode::KEY_Q, shift_altgr, unlocked));
+
+        // Keys only in group 1 wrap around
+        assert_eq!(Some(Keysym::named("Escape")), sym(de, KeyCode::KEY_ESC, none, unlocked));
+        assert_eq!(c('.'), sym(us, KeyCode::KEY_KPDOT, none, num));
+        assert_eq!(c(','), sym(de, KeyCode::KEY_KPDOT, none, num));
+        assert_eq!(Some(Keysym::named("KP_Home")), sym(us, KeyCode::KEY_KP7, shift, num));
+
+        let err = XkbKeymap::from_components(fixtures(), "evdev", "complete", "us(dvorak)").unwrap_err();
+        assert!(err.to_string().starts_with("no section 'dvorak'"));
+    }
+
+    #[test]
+    fn compiled_test() {
+        let text = r#"
+            xkb_keymap {
+            xkb_keycodes "evdev+aliases(qwerty)" {
+                minimum = 8;
+                maximum = 255;
+                <AE01> = 10;
+                <AC01> = 38;
+                <BKSL> = 51;
+                indicator 1 = "Caps Lock";
+                alias <AC12> = <BKSL>;
+            };
+            xkb_types "complete" {
+                virtual_modifiers NumLock,LevelThree;
+                type "SHIFT_LOCKED" {
+                    modifiers= Shift+Lock;
+                    map[Shift]= 2;
+                    map[Lock]= 2;
+                    level_name[1]= "Base";
+                };
+            };
+            xkb_compatibility "complete" {
+                interpret Caps_Lock+AnyOfOrNone(all) { action= LockMods(modifiers=Lock); };
+            };
+            xkb_symbols "pc+fi+inet(evdev)" {
+                name[group1]="Finnish";
+                key <AE01> { [ 1, exclam, onesuperior, exclamdown ] };
+                key <AC01> { type= "SHIFT_LOCKED", symbols[Group1]= [ aring, Aring ] }; /* made up */
+                key <AC12> {
+                    type= "TWO_LEVEL",
+                    symbols[Group1]= [ apostrophe, asterisk ],
+                    actions[Group1]= [ NoAction(), NoAction() ]
+                };
+                modifier_map Lock { <CAPS> };
+            };
+            xkb_geometry "pc(pc105)" {
+                shape "NORM" { { [ 18, 18 ] } };
+            };
+            };"#;
+        let xkb: XkbKeymap = text.parse().unwrap();
+        assert_eq!(1, xkb.groups().len());
+        let fi = xkb.group(0).unwrap();
+        assert_eq!("Finnish", fi.name());
+        assert_eq!(KeyKind::Alphabetic, fi.key(KeyCode::KEY_A).unwrap().kind);
+        let caps = Locks { caps: true, ..Default::default() };
+        assert_eq!(Some(Keysym::Char('Å')), fi.keysym(KeyCode::KEY_A, &Modifiers::default(), &caps));
+        assert_eq!(Some(Keysym::Char('\'')), fi.keysym(KeyCode::KEY_BACKSLASH, &Modifiers::default(), &caps));
+        assert_eq!(Some(Keysym::Char('1')), fi.keysym(KeyCode::KEY_1, &Modifiers::default(), &caps));
+
+        assert!("xkb_symbols { include \"us\" };".parse::<XkbKeymap>().is_err());
+    }
+}